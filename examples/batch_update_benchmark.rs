@@ -0,0 +1,71 @@
+/**
+Compare updating the positions of many sources one by one with a single batched update.
+
+Each frame moves every source and then mixes one block. The time spent on sending the updates
+and the time spent on mixing, which includes applying the updates, are reported separately.
+
+Run in Release mode for meaningful numbers: `cargo run --release --example batch_update_benchmark`
+*/
+use ambisonic::{bmixer_with_config, BmixerConfig, BstreamConfig};
+use std::time::{Duration, Instant};
+
+const SAMPLE_RATE: u32 = 48000;
+const BLOCK_SIZE: usize = 512;
+const N_SOURCES: usize = 500;
+const N_FRAMES: usize = 1000;
+
+fn position(i: usize, frame: usize) -> [f32; 3] {
+    let angle = (i as f32 / N_SOURCES as f32 + frame as f32 * 0.001) * 2.0 * std::f32::consts::PI;
+    [angle.cos(), angle.sin(), 0.0]
+}
+
+fn main() {
+    for &batched in &[false, true] {
+        let config = BmixerConfig::new().with_block_size(BLOCK_SIZE);
+        let (mut mixer, composer) = bmixer_with_config(SAMPLE_RATE, config);
+
+        let mut sources: Vec<_> = (0..N_SOURCES)
+            .map(|i| {
+                let source = ambisonic::sources::Constant::new(0.01, SAMPLE_RATE);
+                composer.play(source, BstreamConfig::new().with_position(position(i, 0)))
+            })
+            .collect();
+
+        let (mut update_time, mut mix_time) = (Duration::default(), Duration::default());
+        let mut checksum = 0.0;
+        for frame in 1..=N_FRAMES {
+            let start = Instant::now();
+            if batched {
+                let updates: Vec<_> = sources
+                    .iter()
+                    .enumerate()
+                    .map(|(i, src)| (src.id(), position(i, frame)))
+                    .collect();
+                composer.update_positions(&updates);
+            } else {
+                for (i, src) in sources.iter_mut().enumerate() {
+                    src.adjust_position(position(i, frame));
+                }
+            }
+            update_time += start.elapsed();
+
+            let start = Instant::now();
+            checksum += mixer
+                .by_ref()
+                .take(BLOCK_SIZE)
+                .map(|s| s.components()[0])
+                .sum::<f32>();
+            mix_time += start.elapsed();
+        }
+
+        let per_frame = |t: Duration| t.as_secs_f64() * 1e6 / N_FRAMES as f64;
+        println!(
+            "{:>10}: {} sources, {:.1} µs updating and {:.1} µs mixing per frame (checksum {})",
+            if batched { "batched" } else { "individual" },
+            N_SOURCES,
+            per_frame(update_time),
+            per_frame(mix_time),
+            checksum,
+        );
+    }
+}
@@ -0,0 +1,40 @@
+/**
+Compare the throughput of sample-by-sample mixing (block size 1) with block processing.
+
+Run in Release mode for meaningful numbers: `cargo run --release --example mixer_benchmark`
+*/
+use ambisonic::{bmixer_with_config, BmixerConfig, BstreamConfig};
+use std::time::Instant;
+
+const SAMPLE_RATE: u32 = 48000;
+const N_SOURCES: usize = 100;
+const N_SAMPLES: usize = 10 * SAMPLE_RATE as usize;
+
+fn main() {
+    for &block_size in &[1, 64, 512] {
+        let config = BmixerConfig::new().with_block_size(block_size);
+        let (mixer, composer) = bmixer_with_config(SAMPLE_RATE, config);
+
+        // constant sources keep the cost of generating samples out of the measurement
+        for i in 0..N_SOURCES {
+            let source = ambisonic::sources::Constant::new(0.01, SAMPLE_RATE);
+            let angle = i as f32 / N_SOURCES as f32 * 2.0 * std::f32::consts::PI;
+            composer.play(
+                source,
+                BstreamConfig::new().with_position([angle.cos(), angle.sin(), 0.0]),
+            );
+        }
+
+        let start = Instant::now();
+        let checksum: f32 = mixer.take(N_SAMPLES).map(|s| s.components()[0]).sum();
+        let elapsed = start.elapsed();
+
+        println!(
+            "block size {:>3}: {} sources, {:.1}x real time (checksum {})",
+            block_size,
+            N_SOURCES,
+            N_SAMPLES as f64 / SAMPLE_RATE as f64 / elapsed.as_secs_f64(),
+            checksum,
+        );
+    }
+}
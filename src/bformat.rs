@@ -5,16 +5,77 @@ use std::iter::FromIterator;
 use cpal::{Sample as CpalSample, SampleFormat};
 use rodio::Sample;
 
-/// Audio sample in first-order *B-format*.
+use crate::math;
+
+/// Ambisonic order of a sound scene
+///
+/// Higher orders encode the sound field with more components, which gives sharper localization
+/// at the cost of more processing.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AmbisonicOrder {
+    /// First order: four components (default)
+    #[default]
+    First,
+
+    /// Second order: nine components
+    Second,
+}
+
+/// Audio sample in *B-format*.
 ///
-/// It encodes four components of the sound field at the listener position: omnidirectional level
-/// `w` and the level gradient in `x`, `y`, and `z` directions.
+/// It encodes the sound field at the listener position: omnidirectional level `w` and the
+/// level gradient in `x`, `y`, and `z` directions (first order), and five second-order
+/// components `q`. The second-order components are zero in first-order scenes.
 #[derive(Debug, Copy, Clone)]
 pub struct Bformat {
     w: f32,
     x: f32,
     y: f32,
     z: f32,
+    q: [f32; 5],
+}
+
+impl Bformat {
+    /// Components of the sample in the order `[w, x, y, z]`.
+    pub fn components(&self) -> [f32; 4] {
+        [self.w, self.x, self.y, self.z]
+    }
+
+    /// First-order sample with components in the order `[w, x, y, z]`.
+    pub(crate) fn from_components(c: [f32; 4]) -> Self {
+        Bformat {
+            w: c[0],
+            x: c[1],
+            y: c[2],
+            z: c[3],
+            q: [0.0; 5],
+        }
+    }
+
+    /// Rotate the directional components of the sound field.
+    pub fn rotate(self, rot: &Rotation) -> Self {
+        let mut q = [0.0; 5];
+        for (qi, row) in q.iter_mut().zip(&rot.q_matrix) {
+            *qi = row.iter().zip(&self.q).map(|(r, q)| r * q).sum();
+        }
+        Bformat {
+            q,
+            ..self.rotate_first_order(rot)
+        }
+    }
+
+    /// Rotate only the first-order components, leaving the second-order components as they are.
+    pub(crate) fn rotate_first_order(self, rot: &Rotation) -> Self {
+        let m = &rot.matrix;
+        Bformat {
+            w: self.w,
+            x: m[0][0] * self.x + m[0][1] * self.y + m[0][2] * self.z,
+            y: m[1][0] * self.x + m[1][1] * self.y + m[1][2] * self.z,
+            z: m[2][0] * self.x + m[2][1] * self.y + m[2][2] * self.z,
+            q: self.q,
+        }
+    }
 }
 
 impl Sample for Bformat {
@@ -25,6 +86,7 @@ impl Sample for Bformat {
             x: first.x * alpha + second.x * (1.0 - alpha),
             y: first.y * alpha + second.y * (1.0 - alpha),
             z: first.z * alpha + second.z * (1.0 - alpha),
+            q: zip_q(first.q, second.q, |a, b| a * alpha + b * (1.0 - alpha)),
         }
     }
 
@@ -34,6 +96,7 @@ impl Sample for Bformat {
             x: self.x * alpha,
             y: self.y * alpha,
             z: self.z * alpha,
+            q: self.q.map(|q| q * alpha),
         }
     }
 
@@ -43,6 +106,7 @@ impl Sample for Bformat {
             x: self.x + other.x,
             y: self.y + other.y,
             z: self.z + other.z,
+            q: zip_q(self.q, other.q, |a, b| a + b),
         }
     }
 
@@ -52,10 +116,38 @@ impl Sample for Bformat {
             x: 0.0,
             y: 0.0,
             z: 0.0,
+            q: [0.0; 5],
         }
     }
 }
 
+/// Combine second-order components element by element
+#[inline(always)]
+fn zip_q(a: [f32; 5], b: [f32; 5], f: impl Fn(f32, f32) -> f32) -> [f32; 5] {
+    [
+        f(a[0], b[0]),
+        f(a[1], b[1]),
+        f(a[2], b[2]),
+        f(a[3], b[3]),
+        f(a[4], b[4]),
+    ]
+}
+
+/// Second-order components of a unit direction
+///
+/// The components are normalized such that the dot product of the components of two directions
+/// is the second-order Legendre polynomial of the cosine of the angle between them.
+pub(crate) fn second_order_components(d: [f32; 3]) -> [f32; 5] {
+    let s3 = 3f32.sqrt();
+    [
+        s3 * d[0] * d[1],
+        s3 * d[0] * d[2],
+        s3 * d[1] * d[2],
+        s3 / 2.0 * (d[0] * d[0] - d[1] * d[1]),
+        (3.0 * d[2] * d[2] - 1.0) / 2.0,
+    ]
+}
+
 // Why? Oh, why!?
 unsafe impl CpalSample for Bformat {
     const FORMAT: SampleFormat = SampleFormat::F32;
@@ -80,40 +172,249 @@ unsafe impl CpalSample for Bformat {
     }
 }
 
+/// Block of consecutive `Bformat` samples, stored by component for efficient processing.
+///
+/// Second-order components are only stored in second-order blocks.
+#[derive(Debug, Clone)]
+pub(crate) struct BformatBlock {
+    w: Vec<f32>,
+    x: Vec<f32>,
+    y: Vec<f32>,
+    z: Vec<f32>,
+    q: Vec<Vec<f32>>,
+}
+
+impl BformatBlock {
+    /// Create a block of `len` zero samples.
+    pub fn new(len: usize, order: AmbisonicOrder) -> Self {
+        let n_q = match order {
+            AmbisonicOrder::First => 0,
+            AmbisonicOrder::Second => 5,
+        };
+        BformatBlock {
+            w: vec![0.0; len],
+            x: vec![0.0; len],
+            y: vec![0.0; len],
+            z: vec![0.0; len],
+            q: vec![vec![0.0; len]; n_q],
+        }
+    }
+
+    /// Number of samples in the block.
+    pub fn len(&self) -> usize {
+        self.w.len()
+    }
+
+    /// Set all samples to zero.
+    pub fn clear(&mut self) {
+        for c in [&mut self.w, &mut self.x, &mut self.y, &mut self.z] {
+            c.iter_mut().for_each(|v| *v = 0.0);
+        }
+        for c in &mut self.q {
+            c.iter_mut().for_each(|v| *v = 0.0);
+        }
+    }
+
+    /// Sample at index `i`.
+    #[inline(always)]
+    pub fn get(&self, i: usize) -> Bformat {
+        let mut q = [0.0; 5];
+        for (qk, c) in q.iter_mut().zip(&self.q) {
+            *qk = c[i];
+        }
+        Bformat {
+            w: self.w[i],
+            x: self.x[i],
+            y: self.y[i],
+            z: self.z[i],
+            q,
+        }
+    }
+
+    /// Replace the sample at index `i`.
+    #[inline(always)]
+    pub fn set(&mut self, i: usize, b: Bformat) {
+        self.w[i] = b.w;
+        self.x[i] = b.x;
+        self.y[i] = b.y;
+        self.z[i] = b.z;
+        for (c, q) in self.q.iter_mut().zip(&b.q) {
+            c[i] = *q;
+        }
+    }
+
+    /// Add a sample to the sample at index `i`.
+    #[inline(always)]
+    pub fn add(&mut self, i: usize, b: Bformat) {
+        self.w[i] += b.w;
+        self.x[i] += b.x;
+        self.y[i] += b.y;
+        self.z[i] += b.z;
+        for (c, q) in self.q.iter_mut().zip(&b.q) {
+            c[i] += q;
+        }
+    }
+
+    /// Scale the sample at index `i` by `gain`.
+    #[inline(always)]
+    pub fn amplify(&mut self, i: usize, gain: f32) {
+        self.w[i] *= gain;
+        self.x[i] *= gain;
+        self.y[i] *= gain;
+        self.z[i] *= gain;
+        for c in &mut self.q {
+            c[i] *= gain;
+        }
+    }
+
+    /// Rotate all samples of the block.
+    ///
+    /// Second-order components are only rotated in second-order blocks.
+    pub fn rotate(&mut self, rot: &Rotation) {
+        let m = &rot.matrix;
+        for ((x, y), z) in self.x.iter_mut().zip(&mut self.y).zip(&mut self.z) {
+            let v = [*x, *y, *z];
+            *x = m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2];
+            *y = m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2];
+            *z = m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2];
+        }
+        if !self.q.is_empty() {
+            let mut q = [0.0; 5];
+            for i in 0..self.len() {
+                for (qk, c) in q.iter_mut().zip(&self.q) {
+                    *qk = c[i];
+                }
+                for (c, row) in self.q.iter_mut().zip(&rot.q_matrix) {
+                    c[i] = row.iter().zip(&q).map(|(r, q)| r * q).sum();
+                }
+            }
+        }
+    }
+
+    /// Add mono `samples` with constant weights, starting at index `offset`.
+    ///
+    /// Equivalent to calling `add(offset + k, weights.scale(samples[k]))` for every `k`.
+    pub fn add_weighted(&mut self, offset: usize, weights: &Bweights, samples: &[f32]) {
+        let end = offset + samples.len();
+        for (c, wt) in [
+            (&mut self.w[offset..end], weights.w),
+            (&mut self.x[offset..end], weights.x),
+            (&mut self.y[offset..end], weights.y),
+            (&mut self.z[offset..end], weights.z),
+        ] {
+            for (v, s) in c.iter_mut().zip(samples) {
+                *v += wt * s;
+            }
+        }
+        for (c, &wt) in self.q.iter_mut().zip(&weights.q) {
+            for (v, s) in c[offset..end].iter_mut().zip(samples) {
+                *v += wt * s;
+            }
+        }
+    }
+}
+
 /// Weights for manipulating `Bformat` samples.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bweights {
     w: f32,
     x: f32,
     y: f32,
     z: f32,
+    q: [f32; 5],
 }
 
 impl Bweights {
-    /// Initialze new weights with given values
+    /// Initialze new first-order weights with given values
     pub fn new(w: f32, x: f32, y: f32, z: f32) -> Self {
-        Bweights { w, x, y, z }
+        Bweights {
+            w,
+            x,
+            y,
+            z,
+            q: [0.0; 5],
+        }
     }
 
-    /// Weights that correspond to a omnidirectional source
-    pub fn omni_source() -> Self {
+    /// First-order weights in W, X, Y, Z order
+    pub fn components(&self) -> [f32; 4] {
+        [self.w, self.x, self.y, self.z]
+    }
+
+    /// Initialize new weights with second-order components
+    pub fn with_second_order(self, q: [f32; 5]) -> Self {
+        Bweights { q, ..self }
+    }
+
+    /// Scale the directional components, keeping the omnidirectional component
+    pub(crate) fn scale_directional(self, s: f32) -> Self {
         Bweights {
-            w: 1.0 / 2f32.sqrt(),
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
+            w: self.w,
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+            q: self.q.map(|q| q * s),
+        }
+    }
+
+    /// Scale all components
+    pub(crate) fn amplify(self, s: f32) -> Self {
+        Bweights {
+            w: self.w * s,
+            x: self.x * s,
+            y: self.y * s,
+            z: self.z * s,
+            q: self.q.map(|q| q * s),
         }
     }
 
-    /// Compute weights that correspond to a sound source at given position.
-    pub fn from_position(pos: [f32; 3]) -> Self {
-        let dist = (pos[0] * pos[0] + pos[1] * pos[1] + pos[2] * pos[2]).sqrt();
-        let falloff = 1.0 / dist.max(1.0); // todo: proper falloff and distance model(s)
+    /// Scale the first and second order components separately
+    pub(crate) fn scale_orders(self, first: f32, second: f32) -> Self {
         Bweights {
-            w: falloff / 2f32.sqrt(),
-            x: falloff * pos[0] / dist,
-            y: falloff * pos[1] / dist,
-            z: falloff * pos[2] / dist,
+            w: self.w,
+            x: self.x * first,
+            y: self.y * first,
+            z: self.z * first,
+            q: self.q.map(|q| q * second),
+        }
+    }
+
+    /// Weights that correspond to a omnidirectional source
+    pub fn omni_source() -> Self {
+        Bweights::new(1.0 / 2f32.sqrt(), 0.0, 0.0, 0.0)
+    }
+
+    /// Compute weights that correspond to a sound source in given direction, scaled by `gain`.
+    ///
+    /// The direction does not need to be normalized. A zero direction yields an
+    /// omnidirectional source.
+    pub fn from_direction(dir: [f32; 3], gain: f32) -> Self {
+        let l = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+        if l == 0.0 {
+            return Bweights::new(gain / 2f32.sqrt(), 0.0, 0.0, 0.0);
+        }
+        Bweights::new(
+            gain / 2f32.sqrt(),
+            gain * dir[0] / l,
+            gain * dir[1] / l,
+            gain * dir[2] / l,
+        )
+    }
+
+    /// Like `from_direction`, but encodes the source up to the given ambisonic order.
+    pub fn from_direction_with_order(dir: [f32; 3], gain: f32, order: AmbisonicOrder) -> Self {
+        let bw = Bweights::from_direction(dir, gain);
+        match order {
+            AmbisonicOrder::First => bw,
+            AmbisonicOrder::Second => {
+                let l = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+                if l == 0.0 {
+                    return bw;
+                }
+                let q = second_order_components([dir[0] / l, dir[1] / l, dir[2] / l]);
+                bw.with_second_order(q.map(|q| q * gain))
+            }
         }
     }
 
@@ -128,12 +429,28 @@ impl Bweights {
             + direction[1] * direction[1]
             + direction[1] * direction[2])
             .sqrt();
-        Bweights {
-            w: p * 2f32.sqrt(),
-            x: direction[0] * (1.0 - p) / l,
-            y: direction[1] * (1.0 - p) / l,
-            z: direction[2] * (1.0 - p) / l,
-        }
+        Bweights::new(
+            p * 2f32.sqrt(),
+            direction[0] * (1.0 - p) / l,
+            direction[1] * (1.0 - p) / l,
+            direction[2] * (1.0 - p) / l,
+        )
+    }
+
+    /// Compute weights that correspond to a second-order virtual microphone.
+    ///
+    /// The microphone has the polar pattern `((1 + cos θ) / 2)²`, which is narrower than a
+    /// first-order cardioid. The `direction` does not need to be normalized.
+    pub fn second_order_cardioid(direction: [f32; 3]) -> Self {
+        let d = normalize(direction);
+        // ((1 + c) / 2)² = 1/3 + c/2 + P2(c)/6
+        Bweights::new(2f32.sqrt() / 3.0, d[0] / 2.0, d[1] / 2.0, d[2] / 2.0)
+            .with_second_order(second_order_components(d).map(|q| q / 6.0))
+    }
+
+    /// Gain of the omnidirectional component, if the weights correspond to a sound source.
+    pub fn omni_gain(&self) -> f32 {
+        self.w * 2f32.sqrt()
     }
 
     /// Dot product of *B-format* weights and sample.
@@ -141,7 +458,8 @@ impl Bweights {
     /// If the weights correspond to a virtual microphone, the result is the signal recorded by that
     /// microphone.
     pub fn dot(&self, b: Bformat) -> f32 {
-        self.w * b.w + self.x * b.x + self.y * b.y + self.z * b.z
+        let q: f32 = self.q.iter().zip(&b.q).map(|(a, b)| a * b).sum();
+        self.w * b.w + self.x * b.x + self.y * b.y + self.z * b.z + q
     }
 
     /// Produce a *B-format* sample by scaling weights.
@@ -154,9 +472,24 @@ impl Bweights {
             x: self.x * s,
             y: self.y * s,
             z: self.z * s,
+            q: self.q.map(|q| q * s),
         }
     }
 
+    /// Euclidean distance between two sets of weights
+    pub fn distance(&self, other: &Bweights) -> f32 {
+        let (w, x, y, z) = (
+            other.w - self.w,
+            other.x - self.x,
+            other.y - self.y,
+            other.z - self.z,
+        );
+        let q: f32 = zip_q(other.q, self.q, |a, b| (a - b) * (a - b))
+            .iter()
+            .sum();
+        (w * w + x * x + y * y + z * z + q).sqrt()
+    }
+
     /// adjust weights towards target
     pub fn approach(&mut self, target: &Bweights, max_step: f32) {
         // if this turns out too slow we could try to replace it with simple steps along each dimension
@@ -166,7 +499,7 @@ impl Bweights {
             target.y - self.y,
             target.z - self.z,
         ];
-        let dist = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2] + dir[3] * dir[3]).sqrt();
+        let dist = self.distance(target);
         if dist <= max_step {
             *self = *target;
         } else {
@@ -175,6 +508,7 @@ impl Bweights {
             self.x += dir[1] * d;
             self.y += dir[2] * d;
             self.z += dir[3] * d;
+            self.q = zip_q(self.q, target.q, |a, b| a + (b - a) * d);
         }
     }
 }
@@ -182,13 +516,250 @@ impl Bweights {
 impl FromIterator<f32> for Bweights {
     fn from_iter<T: IntoIterator<Item = f32>>(iter: T) -> Self {
         let mut iter = iter.into_iter();
-        let bw = Bweights {
-            w: iter.next().unwrap(),
-            x: iter.next().unwrap(),
-            y: iter.next().unwrap(),
-            z: iter.next().unwrap(),
-        };
+        let bw = Bweights::new(
+            iter.next().unwrap(),
+            iter.next().unwrap(),
+            iter.next().unwrap(),
+            iter.next().unwrap(),
+        );
         assert!(iter.next().is_none());
         bw
     }
 }
+
+/// Rotation of a *B-format* sound field.
+#[derive(Debug, Copy, Clone)]
+pub struct Rotation {
+    matrix: [[f32; 3]; 3],
+    q_matrix: [[f32; 5]; 5],
+}
+
+impl Rotation {
+    /// Rotation that transforms scene coordinates into the frame of a listener looking into
+    /// direction `forward` with the top of their head pointing `up`.
+    ///
+    /// Neither vector needs to be normalized, but they must not be parallel. The `up` vector is
+    /// made orthogonal to `forward`.
+    pub fn from_listener_orientation(forward: [f32; 3], up: [f32; 3]) -> Self {
+        let forward = normalize(forward);
+        let right = normalize(cross(forward, up));
+        let up = cross(right, forward);
+        let matrix = [right, forward, up];
+        Rotation {
+            matrix,
+            q_matrix: second_order_rotation(&matrix),
+        }
+    }
+
+    /// Rotation that transforms scene coordinates into the frame of a listener whose head is
+    /// turned by `yaw`, `pitch`, and `roll` (in degrees)
+    ///
+    /// The head first turns by `yaw` to the left, then tilts by `pitch` upwards, and finally
+    /// rolls by `roll` so that its top leans to the right. Yaw and pitch are the azimuth and
+    /// elevation of the direction the listener looks into (see the `math` module).
+    pub fn from_yaw_pitch_roll(yaw: f32, pitch: f32, roll: f32) -> Self {
+        let forward = math::spherical_to_cartesian(yaw, pitch, 1.0);
+        let level_up = math::spherical_to_cartesian(yaw, pitch + 90.0, 1.0);
+        let right = cross(forward, level_up);
+        let (sin, cos) = roll.to_radians().sin_cos();
+        let up = [
+            cos * level_up[0] + sin * right[0],
+            cos * level_up[1] + sin * right[1],
+            cos * level_up[2] + sin * right[2],
+        ];
+        Rotation::from_listener_orientation(forward, up)
+    }
+
+    /// Rotation in the opposite direction
+    pub(crate) fn inverse(&self) -> Self {
+        let mut matrix = [[0.0; 3]; 3];
+        for (i, row) in self.matrix.iter().enumerate() {
+            for (j, v) in row.iter().enumerate() {
+                matrix[j][i] = *v;
+            }
+        }
+        let mut q_matrix = [[0.0; 5]; 5];
+        for (i, row) in self.q_matrix.iter().enumerate() {
+            for (j, v) in row.iter().enumerate() {
+                q_matrix[j][i] = *v;
+            }
+        }
+        Rotation { matrix, q_matrix }
+    }
+
+    /// Transform a direction or position into the rotated frame
+    pub(crate) fn apply(&self, v: [f32; 3]) -> [f32; 3] {
+        let m = &self.matrix;
+        [
+            m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+            m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+            m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+        ]
+    }
+}
+
+/// Rotation matrix of the second-order components for a given rotation of directions
+///
+/// The second-order components of a direction `d` are coefficients of the traceless matrix
+/// `d dᵀ - I/3` in an orthonormal basis. Rotating `d` by `R` maps this matrix to `R (d dᵀ - I/3) Rᵀ`,
+/// so each element of the result is the projection of a rotated basis matrix onto another.
+fn second_order_rotation(r: &[[f32; 3]; 3]) -> [[f32; 5]; 5] {
+    let s2 = 1.0 / 2f32.sqrt();
+    let s6 = 1.0 / 6f32.sqrt();
+    let basis = [
+        [[0.0, s2, 0.0], [s2, 0.0, 0.0], [0.0, 0.0, 0.0]],
+        [[0.0, 0.0, s2], [0.0, 0.0, 0.0], [s2, 0.0, 0.0]],
+        [[0.0, 0.0, 0.0], [0.0, 0.0, s2], [0.0, s2, 0.0]],
+        [[s2, 0.0, 0.0], [0.0, -s2, 0.0], [0.0, 0.0, 0.0]],
+        [[-s6, 0.0, 0.0], [0.0, -s6, 0.0], [0.0, 0.0, 2.0 * s6]],
+    ];
+
+    let mut m = [[0.0; 5]; 5];
+    for (j, b) in basis.iter().enumerate() {
+        // R B Rᵀ
+        let mut rotated = [[0.0; 3]; 3];
+        for (k, row) in rotated.iter_mut().enumerate() {
+            for (l, v) in row.iter_mut().enumerate() {
+                for a in 0..3 {
+                    for c in 0..3 {
+                        *v += r[k][a] * b[a][c] * r[l][c];
+                    }
+                }
+            }
+        }
+        for (i, bi) in basis.iter().enumerate() {
+            m[i][j] = (0..3)
+                .flat_map(|k| (0..3).map(move |l| (k, l)))
+                .map(|(k, l)| bi[k][l] * rotated[k][l])
+                .sum();
+        }
+    }
+    m
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub(crate) fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let l = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    assert!(l > 0.0, "cannot normalize a zero-length vector");
+    [v[0] / l, v[1] / l, v[2] / l]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn angle_cos(a: [f32; 3], b: [f32; 3]) -> f32 {
+        let (a, b) = (normalize(a), normalize(b));
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    #[test]
+    fn second_order_components_reproduce_legendre_polynomial() {
+        let dirs = [
+            [1.0, 0.0, 0.0],
+            [0.3, -0.5, 0.8],
+            [-1.0, 2.0, 0.5],
+            [0.0, 0.0, -1.0],
+        ];
+        for &a in &dirs {
+            for &b in &dirs {
+                let qa = second_order_components(normalize(a));
+                let qb = second_order_components(normalize(b));
+                let dot: f32 = qa.iter().zip(&qb).map(|(x, y)| x * y).sum();
+                let c = angle_cos(a, b);
+                assert!((dot - (3.0 * c * c - 1.0) / 2.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn yaw_pitch_roll_follow_the_head() {
+        let check = |rot: Rotation, v: [f32; 3], expected: [f32; 3]| {
+            assert!(angle_cos(rot.apply(v), expected) > 1.0 - 1e-6);
+        };
+
+        // turning left puts a source in front to the right
+        check(
+            Rotation::from_yaw_pitch_roll(90.0, 0.0, 0.0),
+            [0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0],
+        );
+        // looking up puts a source above in front
+        check(
+            Rotation::from_yaw_pitch_roll(0.0, 90.0, 0.0),
+            [0.0, 0.0, 1.0],
+            [0.0, 1.0, 0.0],
+        );
+        // leaning the head to the right puts a source on the right above
+        check(
+            Rotation::from_yaw_pitch_roll(0.0, 0.0, 90.0),
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+        );
+
+        // the angles combine like the head's motion
+        let rot = Rotation::from_yaw_pitch_roll(90.0, 30.0, 0.0);
+        check(
+            rot,
+            math::spherical_to_cartesian(90.0, 30.0, 1.0),
+            [0.0, 1.0, 0.0],
+        );
+    }
+
+    #[test]
+    fn rotation_rotates_second_order_components() {
+        let rot = Rotation::from_listener_orientation([1.0, 1.0, 0.3], [0.2, 0.0, 1.0]);
+        let dir = [0.3, -0.5, 0.8];
+        let m = rot.matrix;
+        let rotated_dir = [0, 1, 2].map(|i| m[i][0] * dir[0] + m[i][1] * dir[1] + m[i][2] * dir[2]);
+
+        let encoded = Bweights::from_direction_with_order(dir, 1.0, AmbisonicOrder::Second)
+            .scale(1.0)
+            .rotate(&rot);
+        let expected =
+            Bweights::from_direction_with_order(rotated_dir, 1.0, AmbisonicOrder::Second)
+                .scale(1.0);
+
+        for (a, b) in encoded.q.iter().zip(&expected.q) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn blocks_rotate_like_their_samples() {
+        let rot = Rotation::from_listener_orientation([1.0, 1.0, 0.3], [0.2, 0.0, 1.0]);
+        let dirs = [[0.3, -0.5, 0.8], [-1.0, 2.0, 0.5], [0.0, 0.0, -1.0]];
+
+        for order in [AmbisonicOrder::First, AmbisonicOrder::Second] {
+            let mut block = BformatBlock::new(dirs.len(), order);
+            for (i, &dir) in dirs.iter().enumerate() {
+                block.set(
+                    i,
+                    Bweights::from_direction_with_order(dir, 1.0, order).scale(0.5),
+                );
+            }
+            let expected: Vec<_> = (0..block.len())
+                .map(|i| block.get(i).rotate(&rot))
+                .collect();
+
+            block.rotate(&rot);
+            for (i, b) in expected.into_iter().enumerate() {
+                let a = block.get(i);
+                assert!((a.w - b.w).abs() < 1e-6);
+                assert!((a.x - b.x).abs() < 1e-6);
+                assert!((a.y - b.y).abs() < 1e-6);
+                assert!((a.z - b.z).abs() < 1e-6);
+                for (qa, qb) in a.q.iter().zip(&b.q) {
+                    assert!((qa - qb).abs() < 1e-6);
+                }
+            }
+        }
+    }
+}
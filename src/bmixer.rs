@@ -3,29 +3,247 @@
 //! This module provides functionality for dynamically composing sound sources into a 3D sound
 //! scene.
 
-use crate::bformat::Bformat;
-use crate::bstream::{self, Bstream, BstreamConfig, SoundController};
+use crate::bformat::{AmbisonicOrder, Bformat, BformatBlock, Bweights, Rotation};
+use crate::bstream::{
+    self, Bstream, BstreamConfig, DuckRole, ListenerFocus, SoundController, SoundShared, SourceId,
+    SourceSnapshot, StereoSoundController,
+};
+use crate::constants::SceneConstants;
+use crate::ducking::{DuckConfig, Ducker};
+use crate::field::{BformatLayout, FieldController, FieldStream};
+use crate::reverb::{Reverb, ReverbConfig};
+use crate::sources::{deinterleave, Downmix};
+use crate::sync::{catch_panic, lock};
 use rodio::{source::UniformSourceIterator, Sample, Source};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 
 /// Construct a 3D sound mixer and associated sound composer.
 pub fn bmixer(sample_rate: u32) -> (BstreamMixer, Arc<BmixerComposer>) {
+    bmixer_with_config(sample_rate, BmixerConfig::new())
+}
+
+/// Construct a 3D sound mixer and associated sound composer with custom configuration.
+pub fn bmixer_with_config(
+    sample_rate: u32,
+    config: BmixerConfig,
+) -> (BstreamMixer, Arc<BmixerComposer>) {
     let controller = Arc::new(BmixerComposer {
         sample_rate,
+        constants: config.constants,
+        order: config.order,
+        focus: config.focus,
+        block_size: config.block_size,
         pending_streams: Mutex::new(Vec::new()),
+        pending_fields: Mutex::new(Vec::new()),
         has_pending: AtomicBool::new(false),
+        commands: Mutex::new(Vec::new()),
+        pending_commands: AtomicBool::new(false),
+        n_streams: AtomicUsize::new(0),
+        clock: AtomicU64::new(0),
+        volume: Mutex::new(1.0),
+        listener: Mutex::new(Listener {
+            position: [0.0, 0.0, 0.0],
+            velocity: [0.0, 0.0, 0.0],
+            rotation: None,
+            sources: HashMap::new(),
+        }),
     });
 
     let mixer = BstreamMixer {
         controller: controller.clone(),
         active_streams: Vec::with_capacity(8),
+        fields: Vec::new(),
+        max_streams: config.max_streams,
+        eviction_policy: config.eviction_policy,
+        rotation: None,
+        volume: 1.0,
+        target_volume: 1.0,
+        volume_step: 1.0 / (VOLUME_RAMP_DURATION * sample_rate as f32),
+        headroom: config.headroom,
+        paused: false,
+        taps: Vec::new(),
+        block_callback: None,
+        block: BformatBlock::new(config.block_size, config.order),
+        block_position: config.block_size,
+        scratch: Vec::with_capacity(config.block_size),
+        reverb: config.reverb.map(|r| Reverb::new(&r, sample_rate)),
+        send: vec![0.0; config.block_size],
+        ducker: config.ducking.map(|d| Ducker::new(&d, sample_rate)),
+        trigger_block: BformatBlock::new(config.block_size, config.order),
+        duck_block: BformatBlock::new(config.block_size, config.order),
+        duck_send: vec![0.0; config.block_size],
+        position_updates: Vec::new(),
+        stream_index: HashMap::new(),
+        soloed: HashSet::with_capacity(SOLO_CAPACITY),
+        solo_active: false,
+        muted_block: BformatBlock::new(config.block_size, config.order),
     };
 
     (mixer, controller)
 }
 
+/// Decides which stream to remove when too many streams are playing
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EvictionPolicy {
+    /// Remove the stream that has been playing longest
+    Oldest,
+
+    /// Remove the stream with the lowest gain after distance attenuation
+    ///
+    /// This considers the gain and position of streams, but not the level of their sources.
+    Quietest,
+}
+
+/// number of samples the mixer processes at once, unless configured otherwise
+const DEFAULT_BLOCK_SIZE: usize = 512;
+
+/// number of sources that can be soloed at once before the mixer allocates on the audio thread
+const SOLO_CAPACITY: usize = 64;
+
+/// Scene-wide configuration for constructing `BstreamMixer`s
+pub struct BmixerConfig {
+    constants: SceneConstants,
+    max_streams: Option<usize>,
+    eviction_policy: EvictionPolicy,
+    block_size: usize,
+    order: AmbisonicOrder,
+    reverb: Option<ReverbConfig>,
+    ducking: Option<DuckConfig>,
+    focus: Option<ListenerFocus>,
+    headroom: f32,
+}
+
+impl Default for BmixerConfig {
+    fn default() -> Self {
+        BmixerConfig {
+            constants: SceneConstants::default(),
+            max_streams: None,
+            eviction_policy: EvictionPolicy::Oldest,
+            block_size: DEFAULT_BLOCK_SIZE,
+            order: AmbisonicOrder::First,
+            reverb: None,
+            ducking: None,
+            focus: None,
+            headroom: 1.0,
+        }
+    }
+}
+
+impl BmixerConfig {
+    /// Create new `BmixerConfig` with default settings.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the default speed of sound (in m/s) for all streams in the scene.
+    ///
+    /// Streams can override this value with `BstreamConfig::with_speed_of_sound`.
+    pub fn with_speed_of_sound(mut self, s: f32) -> Self {
+        self.constants = self.constants.with_speed_of_sound(s);
+        self
+    }
+
+    /// Scale the doppler effect of all streams in the scene (defaults to 1).
+    ///
+    /// See `SceneConstants::with_doppler_factor`.
+    pub fn with_doppler_factor(mut self, factor: f32) -> Self {
+        self.constants = self.constants.with_doppler_factor(factor);
+        self
+    }
+
+    /// Set the physical constants of the scene (defaults to `SceneConstants::default()`).
+    ///
+    /// Streams can override the individual values with the respective `BstreamConfig`
+    /// methods.
+    pub fn with_constants(mut self, constants: SceneConstants) -> Self {
+        self.constants = constants;
+        self
+    }
+
+    /// Limit the number of simultaneously playing streams (unlimited by default).
+    ///
+    /// When a new stream would exceed the limit, another stream is stopped according to the
+    /// eviction policy. Only streams of the lowest priority that is playing are candidates
+    /// (see `BstreamConfig::with_priority`). This may be the new stream itself, in which case
+    /// its controller is already stopped when returned.
+    pub fn with_max_streams(mut self, n: usize) -> Self {
+        self.max_streams = Some(n);
+        self
+    }
+
+    /// Set the policy for stopping streams when there are too many (defaults to `Oldest`).
+    pub fn with_eviction_policy(mut self, policy: EvictionPolicy) -> Self {
+        self.eviction_policy = policy;
+        self
+    }
+
+    /// Set the number of samples the mixer processes at once (defaults to 512).
+    ///
+    /// The mixer processes each stream for a whole block before moving on to the next stream,
+    /// which is considerably faster than processing all streams sample by sample. Changes made
+    /// through controllers take effect at the start of the next block, so larger blocks also
+    /// delay them. A block size of 1 applies changes with sample accuracy. Panics if `n` is 0.
+    pub fn with_block_size(mut self, n: usize) -> Self {
+        assert!(n > 0, "block size must be positive");
+        self.block_size = n;
+        self
+    }
+
+    /// Set the ambisonic order of the scene (defaults to `AmbisonicOrder::First`).
+    ///
+    /// Streams are encoded up to the given order. Second-order scenes localize sources more
+    /// sharply but need more processing; first-order scenes do not process second-order
+    /// components at all.
+    pub fn with_order(mut self, order: AmbisonicOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Add a reverb to the scene (no reverb by default).
+    ///
+    /// Each stream feeds the reverb according to its reverb send (see
+    /// `BstreamConfig::with_reverb_send`).
+    pub fn with_reverb(mut self, config: ReverbConfig) -> Self {
+        self.reverb = Some(config);
+        self
+    }
+
+    /// Duck some streams under others (no ducking by default).
+    ///
+    /// Streams marked with `BstreamConfig::as_duck_trigger` attenuate streams marked with
+    /// `BstreamConfig::as_duckable`; see `DuckConfig` for details.
+    pub fn with_ducking(mut self, config: DuckConfig) -> Self {
+        self.ducking = Some(config);
+        self
+    }
+
+    /// Emphasize streams in the direction the listener is facing (disabled by default).
+    ///
+    /// Streams within `angle / 2` degrees of the listener's forward direction (see
+    /// `BmixerComposer::set_listener_orientation`) are amplified by `front_gain`. Further
+    /// off-axis, the gain changes linearly with the angle until it reaches `rear_gain` directly
+    /// behind the listener. Direct streams are not affected.
+    pub fn with_focus(mut self, angle: f32, front_gain: f32, rear_gain: f32) -> Self {
+        self.focus = Some(ListenerFocus::new(angle, front_gain, rear_gain));
+        self
+    }
+
+    /// Attenuate the mix bus by a fixed amount of `db` decibels (defaults to 0).
+    ///
+    /// Equal sources add up, so a scene of `n` sources playing at full scale needs about
+    /// `20 * log10(n)` dB of headroom to stay below full scale. Unlike a limiter, the headroom
+    /// is a static gain that does not depend on the signal. It applies on top of the master
+    /// volume. Negative values are treated as 0.
+    pub fn with_headroom(mut self, db: f32) -> Self {
+        self.headroom = 10f32.powf(-db.max(0.0) / 20.0);
+        self
+    }
+}
+
 /// Combine all currently playing 3D sound sources into a single *B-format* stream.
 ///
 /// The mixer implements `rodio::Source<Item = Bformat>`, which must be passed to a renderer before
@@ -33,6 +251,119 @@ pub fn bmixer(sample_rate: u32) -> (BstreamMixer, Arc<BmixerComposer>) {
 pub struct BstreamMixer {
     controller: Arc<BmixerComposer>,
     active_streams: Vec<Bstream>,
+    // recorded sound fields, which are not limited by `max_streams`
+    fields: Vec<FieldStream>,
+    max_streams: Option<usize>,
+    eviction_policy: EvictionPolicy,
+    rotation: Option<Rotation>,
+    volume: f32,
+    target_volume: f32,
+    volume_step: f32,
+    // static attenuation of the mix bus
+    headroom: f32,
+    paused: bool,
+    taps: Vec<BformatTap>,
+    block_callback: Option<BlockCallback>,
+    block: BformatBlock,
+    block_position: usize,
+    scratch: Vec<f32>,
+    reverb: Option<Reverb>,
+    send: Vec<f32>,
+    ducker: Option<Ducker>,
+    // sub-mixes of the duck triggers and the duckable streams, if the scene ducks
+    trigger_block: BformatBlock,
+    duck_block: BformatBlock,
+    // reverb send of the duckable streams, which is ducked with their dry signal
+    duck_send: Vec<f32>,
+    position_updates: Vec<PositionUpdate>,
+    // reused lookup table from source ids to active streams
+    stream_index: HashMap<SourceId, usize>,
+    // sources that are soloed; all others are muted while a solo is active, even after the
+    // soloed sources have finished
+    soloed: HashSet<SourceId>,
+    solo_active: bool,
+    // receives the output of muted streams, which keep playing silently
+    muted_block: BformatBlock,
+}
+
+/// New weights and playback rate of a stream
+type PositionUpdate = (SourceId, Bweights, f64, Option<f32>);
+
+/// Callback that receives the mixed B-format samples
+type BformatTap = Box<dyn FnMut([f32; 4]) + Send>;
+
+/// Callback that is invoked once per mixed block
+type BlockCallback = Box<dyn FnMut(BlockInfo) + Send>;
+
+/// Description of a block of samples that the mixer has mixed
+///
+/// See `BmixerComposer::set_block_callback`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// Sample of the scene's clock at which the block starts (see `BmixerComposer::current_sample`)
+    pub start_sample: u64,
+
+    /// Number of samples in the block
+    pub len: usize,
+
+    /// Number of sources that were mixed into the block, including recorded sound fields
+    pub active_sources: usize,
+}
+
+impl BstreamMixer {
+    /// Apply batched position updates to the active streams
+    fn apply_position_updates(&mut self) {
+        self.stream_index.clear();
+        self.stream_index.extend(
+            self.active_streams
+                .iter()
+                .enumerate()
+                .map(|(i, stream)| (stream.id(), i)),
+        );
+
+        for (id, weights, speed, cutoff) in self.position_updates.drain(..) {
+            if let Some(&i) = self.stream_index.get(&id) {
+                let stream = &mut self.active_streams[i];
+                stream.set_speed(speed);
+                stream.set_air_absorption(cutoff);
+                stream.set_target(weights);
+            }
+        }
+    }
+
+    /// Stop streams until the stream limit is satisfied
+    fn evict_streams(&mut self) {
+        let max_streams = match self.max_streams {
+            Some(n) => n,
+            None => return,
+        };
+
+        while self.active_streams.len() > max_streams {
+            let lowest = self
+                .active_streams
+                .iter()
+                .map(Bstream::priority)
+                .min()
+                .unwrap();
+            let mut candidates = self
+                .active_streams
+                .iter()
+                .enumerate()
+                .filter(|(_, stream)| stream.priority() == lowest);
+
+            let i = match self.eviction_policy {
+                EvictionPolicy::Oldest => candidates.next().map(|(i, _)| i).unwrap(),
+                EvictionPolicy::Quietest => candidates
+                    .min_by(|(_, a), (_, b)| a.gain().total_cmp(&b.gain()))
+                    .map(|(i, _)| i)
+                    .unwrap(),
+            };
+            let stream = self.active_streams.remove(i);
+            self.soloed.remove(&stream.id());
+            stream.stop();
+            self.controller.n_streams.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
 }
 
 impl Source for BstreamMixer {
@@ -57,67 +388,1363 @@ impl Source for BstreamMixer {
     }
 }
 
-impl Iterator for BstreamMixer {
-    type Item = Bformat;
+impl BstreamMixer {
+    /// Mix the next block of samples from all streams
+    fn mix_block(&mut self) {
+        if self.controller.pending_commands.load(Ordering::SeqCst) {
+            let mut commands = lock(&self.controller.commands);
+
+            for cmd in commands.drain(..) {
+                match cmd {
+                    MixerCommand::SetRotation(rot) => self.rotation = Some(rot),
+                    MixerCommand::SetVolume(v) => self.target_volume = v,
+                    MixerCommand::FadeOut(duration) => {
+                        let samples = duration.as_secs_f32() * self.controller.sample_rate as f32;
+                        self.target_volume = 0.0;
+                        self.volume_step = self.volume / samples.max(1.0);
+                    }
+                    MixerCommand::AddTap(tap) => self.taps.push(tap),
+                    MixerCommand::SetBlockCallback(f) => self.block_callback = Some(f),
+                    MixerCommand::Pause => self.paused = true,
+                    MixerCommand::Resume => self.paused = false,
+                    MixerCommand::UpdatePositions(updates) => self.position_updates.extend(updates),
+                    MixerCommand::Solo(id) => {
+                        self.soloed.insert(id);
+                        self.solo_active = true;
+                    }
+                    MixerCommand::Unsolo => {
+                        self.soloed.clear();
+                        self.solo_active = false;
+                    }
+                    MixerCommand::Clear => {
+                        self.controller
+                            .n_streams
+                            .fetch_sub(self.active_streams.len(), Ordering::SeqCst);
+                        for stream in self.active_streams.drain(..) {
+                            self.soloed.remove(&stream.id());
+                            stream.stop();
+                        }
+                        self.controller
+                            .n_streams
+                            .fetch_sub(self.fields.len(), Ordering::SeqCst);
+                        self.fields.clear();
+                    }
+                }
+            }
+
+            self.controller
+                .pending_commands
+                .store(false, Ordering::SeqCst);
+        }
+
+        // streams are added after the commands so that `clear` does not remove streams that
+        // were played after it
+        let now = self.controller.clock.load(Ordering::SeqCst);
 
-    fn next(&mut self) -> Option<Self::Item> {
         if self.controller.has_pending.load(Ordering::SeqCst) {
-            let mut pending = self
-                .controller
-                .pending_streams
-                .lock()
-                .expect("Cannot lock pending streams");
-            self.active_streams.extend(pending.drain(..));
+            let mut pending = lock(&self.controller.pending_streams);
+            for mut stream in pending.drain(..) {
+                stream.schedule(now);
+                self.active_streams.push(stream);
+            }
+            self.fields
+                .append(&mut lock(&self.controller.pending_fields));
             self.controller.has_pending.store(false, Ordering::SeqCst);
+            drop(pending);
+            self.evict_streams();
         }
 
-        let mut mix = Bformat::zero_value();
+        // position updates are applied after adding streams, so they reach streams that were
+        // played just before the update
+        if !self.position_updates.is_empty() {
+            self.apply_position_updates();
+        }
+
+        // once a pause has faded out the scene, the streams are not advanced any further
+        if self.paused && self.volume == 0.0 {
+            self.block.clear();
+            self.block_position = 0;
+            self.report_block(now);
+            return;
+        }
+
+        self.block.clear();
+        self.send.iter_mut().for_each(|s| *s = 0.0);
+        if self.ducker.is_some() {
+            self.trigger_block.clear();
+            self.duck_block.clear();
+            self.duck_send.iter_mut().for_each(|s| *s = 0.0);
+        }
 
         let mut done = Vec::new();
 
         for (i, stream) in self.active_streams.iter_mut().enumerate() {
-            match stream.next() {
-                Some(x) => mix = mix.saturating_add(x),
-                None => done.push(i),
+            let (block, send) = if self.solo_active && !self.soloed.contains(&stream.id()) {
+                // muted streams advance like all others, but their output is discarded
+                self.muted_block.clear();
+                (&mut self.muted_block, None)
+            } else {
+                let (block, send) = match (&self.ducker, stream.duck_role()) {
+                    (Some(_), DuckRole::Trigger) => (&mut self.trigger_block, &mut self.send[..]),
+                    (Some(_), DuckRole::Duckable) => {
+                        (&mut self.duck_block, &mut self.duck_send[..])
+                    }
+                    _ => (&mut self.block, &mut self.send[..]),
+                };
+                let send = self.reverb.is_some().then_some(send);
+                (block, send)
+            };
+            // a stream whose source panics is removed, and the others keep playing
+            let scratch = &mut self.scratch;
+            let playing = catch_panic(|| stream.mix_block(block, scratch, send)).unwrap_or(false);
+            if !playing {
+                done.push(i);
             }
         }
 
+        self.controller
+            .n_streams
+            .fetch_sub(done.len(), Ordering::SeqCst);
         for i in done.into_iter().rev() {
-            self.active_streams.remove(i);
+            let stream = self.active_streams.remove(i);
+            self.soloed.remove(&stream.id());
         }
 
-        Some(mix)
+        if let Some(ducker) = &mut self.ducker {
+            for i in 0..self.block.len() {
+                let trigger = self.trigger_block.get(i);
+                // the omnidirectional component is the level of the triggers at the listener
+                let gain = ducker.process(trigger.components()[0] * 2f32.sqrt());
+                self.block.add(i, trigger);
+                self.block.add(i, self.duck_block.get(i).amplify(gain));
+                self.send[i] += self.duck_send[i] * gain;
+            }
+        }
+
+        // recorded fields are muted by solos like all streams that are not soloed
+        let muted = self.solo_active;
+        let block = if muted {
+            &mut self.muted_block
+        } else {
+            &mut self.block
+        };
+        let n_fields = self.fields.len();
+        self.fields.retain_mut(|field| {
+            if muted {
+                block.clear();
+            }
+            catch_panic(|| field.mix_block(block)).unwrap_or(false)
+        });
+        self.controller
+            .n_streams
+            .fetch_sub(n_fields - self.fields.len(), Ordering::SeqCst);
+
+        if let Some(reverb) = &mut self.reverb {
+            for i in 0..self.block.len() {
+                let mix = reverb.process(self.block.get(i), self.send[i]);
+                self.block.set(i, mix);
+            }
+        }
+
+        if let Some(rot) = &self.rotation {
+            self.block.rotate(rot);
+        }
+
+        for i in 0..self.block.len() {
+            let target_volume = if self.paused { 0.0 } else { self.target_volume };
+            if self.volume != target_volume {
+                let delta = target_volume - self.volume;
+                if delta.abs() <= self.volume_step {
+                    self.volume = target_volume;
+                } else {
+                    self.volume += self.volume_step.copysign(delta);
+                }
+            }
+
+            let gain = self.volume * self.headroom;
+            if gain != 1.0 {
+                self.block.amplify(i, gain);
+            }
+
+            if !self.taps.is_empty() {
+                // taps that panic are removed
+                let components = self.block.get(i).components();
+                self.taps
+                    .retain_mut(|tap| catch_panic(|| tap(components)).is_some());
+            }
+        }
+
+        self.controller
+            .clock
+            .store(now + self.block.len() as u64, Ordering::SeqCst);
+        self.block_position = 0;
+        self.report_block(now);
+    }
+
+    /// Invoke the block callback, if any, for the block that starts at sample `start`
+    fn report_block(&mut self, start: u64) {
+        if let Some(f) = &mut self.block_callback {
+            let info = BlockInfo {
+                start_sample: start,
+                len: self.block.len(),
+                active_sources: self.active_streams.len() + self.fields.len(),
+            };
+            // a callback that panics is removed
+            if catch_panic(|| f(info)).is_none() {
+                self.block_callback = None;
+            }
+        }
     }
 }
 
+impl Iterator for BstreamMixer {
+    type Item = Bformat;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.block_position >= self.block.len() {
+            self.mix_block();
+        }
+
+        let sample = self.block.get(self.block_position);
+        self.block_position += 1;
+        Some(sample)
+    }
+}
+
+enum MixerCommand {
+    SetRotation(Rotation),
+    SetVolume(f32),
+    FadeOut(Duration),
+    AddTap(BformatTap),
+    SetBlockCallback(BlockCallback),
+    Pause,
+    Resume,
+    Clear,
+    UpdatePositions(Vec<PositionUpdate>),
+    Solo(SourceId),
+    Unsolo,
+}
+
+/// time (in seconds) it takes `set_volume` to ramp from 0 to 1
+const VOLUME_RAMP_DURATION: f32 = 0.005;
+
 /// Compose the 3D sound scene
 pub struct BmixerComposer {
     has_pending: AtomicBool,
     pending_streams: Mutex<Vec<Bstream>>,
+    pending_fields: Mutex<Vec<FieldStream>>,
+    commands: Mutex<Vec<MixerCommand>>,
+    pending_commands: AtomicBool,
+    n_streams: AtomicUsize,
+    clock: AtomicU64,
+    volume: Mutex<f32>,
+    listener: Mutex<Listener>,
     sample_rate: u32,
+    constants: SceneConstants,
+    order: AmbisonicOrder,
+    focus: Option<ListenerFocus>,
+    block_size: usize,
+}
+
+/// Listener position and the sources that need to follow it
+struct Listener {
+    position: [f32; 3],
+    velocity: [f32; 3],
+    rotation: Option<Rotation>,
+    sources: HashMap<SourceId, Weak<SoundShared>>,
 }
 
 impl BmixerComposer {
     /// Add a single-channel `Source` to the sound scene at a position relative to the listener
     ///
     /// Returns a controller object that can be used to control the source during playback.
+    /// Sources may have any sample rate; they are resampled to the scene's sample rate before
+    /// they are spatialized, so the doppler effect operates at the scene's rate. Sources with more
+    /// than one channel are mixed down to a single channel by averaging their channels (see
+    /// `sources::Downmix`); use `play_stereo` to spatialize the channels separately.
     pub fn play<I>(&self, input: I, config: BstreamConfig) -> SoundController
     where
         I: Source<Item = f32> + Send + 'static,
     {
-        let (bstream, sound_ctl) = if input.sample_rate() == self.sample_rate {
-            bstream::bstream(input, config)
+        if input.channels() != 1 {
+            self.play_mono(Downmix::new(input), config)
+        } else {
+            self.play_mono(input, config)
+        }
+    }
+
+    /// Add a single-channel `Source` to the sound scene
+    fn play_mono<I>(&self, input: I, mut config: BstreamConfig) -> SoundController
+    where
+        I: Source<Item = f32> + Send + 'static,
+    {
+        // normalization decodes ahead, which must not keep other threads from the listener
+        let input = if input.sample_rate() == self.sample_rate {
+            bstream::normalize(input, &mut config)
         } else {
             let input = UniformSourceIterator::new(input, 1, self.sample_rate);
-            bstream::bstream(input, config)
+            bstream::normalize(input, &mut config)
         };
 
-        self.pending_streams
-            .lock()
-            .expect("Cannot lock pending streams")
-            .push(bstream);
+        let mut listener = lock(&self.listener);
+
+        let config = config
+            .with_scene_constants(&self.constants)
+            .with_listener_position(listener.position)
+            .with_listener_velocity(listener.velocity)
+            .with_listener_rotation(listener.rotation)
+            .with_listener_focus(self.focus)
+            .with_order(self.order);
+
+        let (bstream, sound_ctl) = bstream::normalized_bstream(input, config);
+
+        listener.sources.retain(|_, src| src.strong_count() > 0);
+        listener
+            .sources
+            .insert(sound_ctl.id(), Arc::downgrade(sound_ctl.shared()));
+        drop(listener);
+
+        lock(&self.pending_streams).push(bstream);
+        self.n_streams.fetch_add(1, Ordering::SeqCst);
         self.has_pending.store(true, Ordering::SeqCst);
 
         sound_ctl
     }
+
+    /// Add a recorded first-order *B-format* sound field to the scene
+    ///
+    /// The four channels of `input` hold the components of the field in the given layout. The
+    /// field is mixed into the scene as it is, surrounding the listener, and turns with the
+    /// listener's orientation. It bypasses the reverb and is not subject to the stream limit.
+    /// In second-order scenes, the field contributes to the first-order components only.
+    /// Panics if the input does not have exactly four channels.
+    pub fn play_bformat<I>(&self, input: I, layout: BformatLayout) -> FieldController
+    where
+        I: Source<Item = f32> + Send + 'static,
+    {
+        assert_eq!(
+            input.channels(),
+            4,
+            "B-format input must have four channels"
+        );
+
+        let (field, controller) = if input.sample_rate() == self.sample_rate {
+            FieldStream::new(input, layout)
+        } else {
+            let input = UniformSourceIterator::new(input, 4, self.sample_rate);
+            FieldStream::new(input, layout)
+        };
+
+        lock(&self.pending_fields).push(field);
+        self.n_streams.fetch_add(1, Ordering::SeqCst);
+        self.has_pending.store(true, Ordering::SeqCst);
+
+        controller
+    }
+
+    /// Add a two-channel `Source` to the sound scene as two separate spatial sources
+    ///
+    /// The left and right channels are played with their respective configuration. Panics if
+    /// the input does not have exactly two channels.
+    pub fn play_stereo<I>(
+        &self,
+        input: I,
+        left_config: BstreamConfig,
+        right_config: BstreamConfig,
+    ) -> StereoSoundController
+    where
+        I: Source<Item = f32> + Send + 'static,
+    {
+        assert_eq!(input.channels(), 2, "expected a stereo source");
+
+        let mut channels = deinterleave(input);
+        let right = self.play(channels.pop().unwrap(), right_config);
+        let left = self.play(channels.pop().unwrap(), left_config);
+
+        StereoSoundController::new(left, right)
+    }
+
+    /// Number of streams in the scene
+    ///
+    /// Counts all streams that have been added to the scene and have not yet finished playing
+    /// or been stopped.
+    pub fn active_streams(&self) -> usize {
+        self.n_streams.load(Ordering::SeqCst)
+    }
+
+    /// Current sample of the scene's clock
+    ///
+    /// The clock counts the samples the mixer has produced, not counting time during which
+    /// the scene was paused. Streams played now start at this sample at the earliest; use
+    /// `BstreamConfig::with_start_sample` to start streams at a later sample.
+    pub fn current_sample(&self) -> u64 {
+        self.clock.load(Ordering::SeqCst)
+    }
+
+    /// Number of samples the mixer processes at once
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// Set the master volume of the scene
+    ///
+    /// The volume scales the scene as a whole, independent of the gains of individual streams.
+    /// The volume transitions smoothly to the new value over a few milliseconds. It defaults
+    /// to 1. Negative values are clamped to 0.
+    pub fn set_volume(&self, volume: f32) {
+        let volume = volume.max(0.0);
+        *lock(&self.volume) = volume;
+        self.send_command(MixerCommand::SetVolume(volume));
+    }
+
+    /// Fade the master volume to silence over the given duration
+    ///
+    /// Used when the scene is torn down; the master volume does not recover afterwards.
+    pub(crate) fn fade_out(&self, duration: Duration) {
+        *lock(&self.volume) = 0.0;
+        self.send_command(MixerCommand::FadeOut(duration));
+    }
+
+    /// Master volume of the scene
+    pub fn volume(&self) -> f32 {
+        *lock(&self.volume)
+    }
+
+    /// Register a callback that receives every mixed *B-format* sample
+    ///
+    /// The callback is invoked from the audio thread with the components `[w, x, y, z]` of each
+    /// sample after listener orientation and master volume have been applied, but before the
+    /// sample is decoded to the output channels. `w` is the omnidirectional level, and `x`, `y`,
+    /// and `z` are the level gradients towards the listener's right, front, and top. The
+    /// callback must return quickly to avoid audio dropouts; forward the samples to another
+    /// thread (e.g. through a channel) for expensive processing.
+    pub fn add_bformat_tap<F>(&self, f: F)
+    where
+        F: FnMut([f32; 4]) + Send + 'static,
+    {
+        self.send_command(MixerCommand::AddTap(Box::new(f)));
+    }
+
+    /// Register a callback that is invoked once for every block the mixer produces
+    ///
+    /// The callback runs on the audio thread right after the block was mixed, with the block's
+    /// position on the scene's clock, its length, and the number of sources mixed into it.
+    /// This ties external systems like meters or recorders precisely to the audio clock. While
+    /// the scene is paused, the callback is still invoked for each silent block, but the clock
+    /// does not advance. The callback must be real-time safe: it must not allocate, lock, or
+    /// block, or it causes audio dropouts. Replaces any previously registered block callback.
+    pub fn set_block_callback<F>(&self, f: F)
+    where
+        F: FnMut(BlockInfo) + Send + 'static,
+    {
+        self.send_command(MixerCommand::SetBlockCallback(Box::new(f)));
+    }
+
+    /// Pause the whole scene
+    ///
+    /// The scene fades out over a few milliseconds, after which no stream advances until the
+    /// scene is resumed. Sources paused individually stay paused when the scene resumes.
+    pub fn pause_all(&self) {
+        self.send_command(MixerCommand::Pause);
+    }
+
+    /// Resume a scene paused with `pause_all`
+    pub fn resume_all(&self) {
+        self.send_command(MixerCommand::Resume);
+    }
+
+    /// Mute all sources except the given one and any other soloed sources
+    ///
+    /// Soloing is additive: each call adds a source to the set of audible sources. Muted
+    /// sources keep playing silently, so they are in sync again when `unsolo` restores them.
+    /// Sources played while a solo is active are muted as well. The change takes effect at the
+    /// start of the next block.
+    pub fn solo(&self, controller: &SoundController) {
+        self.send_command(MixerCommand::Solo(controller.id()));
+    }
+
+    /// Make all sources audible again after `solo`
+    pub fn unsolo(&self) {
+        self.send_command(MixerCommand::Unsolo);
+    }
+
+    /// Stop all streams in the scene
+    ///
+    /// Streams are removed when the mixer processes its next block. Streams played after
+    /// calling `clear` are not affected.
+    pub fn clear(&self) {
+        let mut pending = lock(&self.pending_streams);
+        self.n_streams.fetch_sub(pending.len(), Ordering::SeqCst);
+        for stream in pending.drain(..) {
+            stream.stop();
+        }
+        let mut fields = lock(&self.pending_fields);
+        self.n_streams.fetch_sub(fields.len(), Ordering::SeqCst);
+        fields.clear();
+        self.send_command(MixerCommand::Clear);
+    }
+
+    /// Set the position of the listener in world coordinates
+    ///
+    /// Sources that were placed at world positions (see `BstreamConfig::with_world_position` and
+    /// `SoundController::adjust_world_position`) transition smoothly to their new position
+    /// relative to the listener. Sources placed relative to the listener move with it. The
+    /// listener is at the origin by default.
+    pub fn set_listener_position(&self, pos: [f32; 3]) {
+        let mut listener = lock(&self.listener);
+        listener.position = pos;
+        listener.sources.retain(|_, src| match src.upgrade() {
+            Some(src) => {
+                src.set_listener_position(pos);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Set the velocity of the listener in world coordinates
+    ///
+    /// The doppler effect of every source depends on its velocity relative to the listener, so
+    /// a source moving along with the listener does not change pitch. The listener is at rest by
+    /// default.
+    pub fn set_listener_velocity(&self, vel: [f32; 3]) {
+        let mut listener = lock(&self.listener);
+        listener.velocity = vel;
+        listener.sources.retain(|_, src| match src.upgrade() {
+            Some(src) => {
+                src.set_listener_velocity(vel);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Velocity of the listener in world coordinates
+    pub fn listener_velocity(&self) -> [f32; 3] {
+        lock(&self.listener).velocity
+    }
+
+    /// Move many sources to new positions relative to the listener at once
+    ///
+    /// Each source transitions smoothly to its new position, like with
+    /// `SoundController::adjust_position`. All updates reach the mixer together through a single
+    /// synchronization point, which is much cheaper for the audio thread than updating large
+    /// numbers of sources one by one. Ids of sources that have finished playing are ignored.
+    ///
+    /// Position changes take effect in the order they are made: the batch replaces individual
+    /// changes of its sources that have not reached the mixer yet, and individual changes made
+    /// after `update_positions` returns are applied after the batch.
+    pub fn update_positions(&self, updates: &[(SourceId, [f32; 3])]) {
+        let batch: Vec<PositionUpdate> = {
+            let listener = lock(&self.listener);
+            updates
+                .iter()
+                .filter_map(|&(id, pos)| {
+                    let src = listener.sources.get(&id)?.upgrade()?;
+                    if src.is_finished() {
+                        return None;
+                    }
+                    let (weights, speed, cutoff) = src.prepare_move(pos);
+                    Some((id, weights, speed, cutoff))
+                })
+                .collect()
+        };
+
+        if !batch.is_empty() {
+            self.send_command(MixerCommand::UpdatePositions(batch));
+        }
+    }
+
+    /// Move every source in the scene to a position computed from its current one
+    ///
+    /// `f` maps the position of each source relative to the listener to its new position, e.g.
+    /// to swirl all sources around the listener. Sources transition smoothly to their new
+    /// positions, and all of them start moving in the same block, like with
+    /// `update_positions`. Sources placed at world positions are relative to the listener
+    /// afterwards.
+    pub fn map_positions(&self, f: impl Fn([f32; 3]) -> [f32; 3]) {
+        let batch: Vec<PositionUpdate> = {
+            let listener = lock(&self.listener);
+            listener
+                .sources
+                .iter()
+                .filter_map(|(&id, src)| {
+                    let src = src.upgrade()?;
+                    if src.is_finished() {
+                        return None;
+                    }
+                    let (weights, speed, cutoff) = src.prepare_move_with(&f);
+                    Some((id, weights, speed, cutoff))
+                })
+                .collect()
+        };
+
+        if !batch.is_empty() {
+            self.send_command(MixerCommand::UpdatePositions(batch));
+        }
+    }
+
+    /// State of all sources in the scene, ordered by id
+    ///
+    /// Includes every source that is still playing, as well as finished sources whose
+    /// controllers are still alive. Reading the state does not disturb playback.
+    pub fn snapshot(&self) -> Vec<SourceSnapshot> {
+        let listener = lock(&self.listener);
+        let mut sources: Vec<SourceSnapshot> = listener
+            .sources
+            .values()
+            .filter_map(|src| src.upgrade())
+            .map(|src| src.snapshot())
+            .collect();
+        sources.sort_by_key(|src| src.id);
+        sources
+    }
+
+    /// Position of the listener in world coordinates
+    pub fn listener_position(&self) -> [f32; 3] {
+        lock(&self.listener).position
+    }
+
+    /// Set the orientation of the listener's head
+    ///
+    /// The listener looks into direction `forward`, and the top of their head points `up`.
+    /// Both vectors are given in the same coordinates as source positions, and must not be
+    /// parallel. By default the listener looks along the positive y axis with z pointing up.
+    pub fn set_listener_orientation(&self, forward: [f32; 3], up: [f32; 3]) {
+        self.set_listener_rotation(Rotation::from_listener_orientation(forward, up));
+    }
+
+    /// Set the orientation of the listener's head from yaw, pitch and roll (in degrees)
+    ///
+    /// Like `set_listener_orientation`, but takes the angles that head trackers usually report:
+    /// the head turns by `yaw` to the left, tilts by `pitch` upwards, and rolls by `roll` so that
+    /// its top leans to the right. The sound field rotates the opposite way, so sources stay in
+    /// place as the head moves. The new rotation replaces the old one at once at the start of
+    /// the next block, so this is cheap enough to call for every video frame.
+    pub fn set_field_rotation(&self, yaw: f32, pitch: f32, roll: f32) {
+        self.set_listener_rotation(Rotation::from_yaw_pitch_roll(yaw, pitch, roll));
+    }
+
+    /// Rotate the scene into the listener's frame, and let sources know about it
+    fn set_listener_rotation(&self, rot: Rotation) {
+        let mut listener = lock(&self.listener);
+        listener.rotation = Some(rot);
+        listener.sources.retain(|_, src| match src.upgrade() {
+            Some(src) => {
+                src.set_listener_rotation(rot);
+                true
+            }
+            None => false,
+        });
+        drop(listener);
+        self.send_command(MixerCommand::SetRotation(rot));
+    }
+
+    fn send_command(&self, cmd: MixerCommand) {
+        lock(&self.commands).push(cmd);
+        self.pending_commands.store(true, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bformat::Bweights;
+    use crate::sources::{Constant, Ramp};
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn turning_the_listener_right_brings_right_source_to_the_front() {
+        let (mut mixer, composer) =
+            bmixer_with_config(48000, BmixerConfig::new().with_block_size(1));
+        composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+
+        let sample = mixer.next().unwrap();
+        assert!(Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample) > 0.99);
+        assert!(Bweights::new(0.0, 0.0, 1.0, 0.0).dot(sample).abs() < 1e-6);
+
+        composer.set_listener_orientation([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+
+        let sample = mixer.next().unwrap();
+        assert!(Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample).abs() < 1e-6);
+        assert!(Bweights::new(0.0, 0.0, 1.0, 0.0).dot(sample) > 0.99);
+    }
+
+    #[test]
+    fn pausing_the_scene_fades_out_and_holds_streams() {
+        let (mut mixer, composer) =
+            bmixer_with_config(1000, BmixerConfig::new().with_block_size(1));
+        composer.play(Ramp::new(1000), BstreamConfig::new());
+        mixer.nth(9);
+
+        composer.pause_all();
+        let faded: Vec<_> = mixer.by_ref().take(20).map(|s| s.components()[0]).collect();
+        assert!(faded[0] > 0.0);
+        assert_eq!(faded[19], 0.0);
+
+        let paused = mixer.next().unwrap().components()[0];
+        assert_eq!(paused, 0.0);
+
+        composer.resume_all();
+        let resumed = mixer.nth(10).unwrap();
+
+        // the ramp advanced by 11 samples after resuming and only few during the fade-out
+        let position = Bweights::omni_source().dot(resumed) * 2.0 * 1000.0;
+        assert!(position > 20.0 && position < 30.0, "{}", position);
+    }
+
+    #[test]
+    fn clearing_the_scene_removes_all_streams() {
+        let (mut mixer, composer) = bmixer(48000);
+        composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        mixer.next();
+        composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        assert_eq!(composer.active_streams(), 3);
+
+        composer.clear();
+        mixer.nth(1024);
+        assert_eq!(composer.active_streams(), 0);
+        assert_eq!(mixer.active_streams.len(), 0);
+
+        composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        mixer.nth(1024);
+        assert_eq!(composer.active_streams(), 1);
+    }
+
+    #[test]
+    fn normalization_does_not_lock_the_listener() {
+        let (_mixer, composer) = bmixer(48000);
+        let locked = Arc::new(AtomicBool::new(false));
+        let source = {
+            let composer = composer.clone();
+            let locked = locked.clone();
+            Constant::new(1.0, 48000).periodic_access(Duration::from_secs(1), move |_| {
+                if composer.listener.try_lock().is_err() {
+                    locked.store(true, Ordering::SeqCst);
+                }
+            })
+        };
+        composer.play(source, BstreamConfig::new().with_target_rms(0.5));
+        assert!(!locked.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn sources_with_other_sample_rates_keep_their_pitch() {
+        let freq = 441.0;
+        let sine: Vec<f32> = (0..22050)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / 22050.0).sin())
+            .collect();
+
+        let (mixer, composer) = bmixer(48000);
+        composer.play(SamplesBuffer::new(1, 22050, sine), BstreamConfig::new());
+
+        let output: Vec<f32> = mixer.take(48000).map(|s| s.components()[0]).collect();
+        let crossings = output
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+
+        // one second of output contains two zero crossings per period
+        assert!(
+            (crossings as f32 / 2.0 - freq).abs() <= 1.0,
+            "{}",
+            crossings
+        );
+    }
+
+    #[test]
+    fn scheduled_streams_start_at_the_same_sample() {
+        let (mut mixer, composer) = bmixer(48000);
+        mixer.nth(100);
+        let start = composer.current_sample() + 700;
+        assert_eq!(start, 512 + 700);
+
+        composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_start_sample(start),
+        );
+        mixer.nth(499);
+        composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new()
+                .with_position([0.0, 1.0, 0.0])
+                .with_start_sample(start),
+        );
+
+        let output: Vec<Bformat> = mixer.take(2000).collect();
+        let onset = |component: usize| {
+            601 + output
+                .iter()
+                .position(|s| s.components()[component] != 0.0)
+                .unwrap() as u64
+        };
+        assert_eq!(onset(1), start);
+        assert_eq!(onset(2), start);
+    }
+
+    #[test]
+    fn streams_scheduled_in_the_past_start_immediately() {
+        let (mut mixer, composer) = bmixer(48000);
+        mixer.nth(1000);
+        composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_start_sample(10),
+        );
+        mixer.nth(22);
+        assert_ne!(mixer.next().unwrap().components()[0], 0.0);
+    }
+
+    #[test]
+    fn block_processing_matches_sample_by_sample_processing() {
+        let render = |block_size| {
+            let config = BmixerConfig::new().with_block_size(block_size);
+            let (mixer, composer) = bmixer_with_config(1000, config);
+            for i in 0..10 {
+                let pos = [i as f32 - 5.0, 2.0, 0.5];
+                let mut ctl =
+                    composer.play(Ramp::new(1000), BstreamConfig::new().with_position(pos));
+                ctl.adjust_position([pos[1], pos[0], 0.0]);
+                ctl.set_velocity([0.0, i as f32 * 3.0, 0.0]);
+                ctl.adjust_gain(0.1 * i as f32);
+
+                let samples = vec![0.5; 100 * i + 17];
+                composer.play(
+                    SamplesBuffer::new(1, 1000, samples),
+                    BstreamConfig::new().with_position(pos),
+                );
+            }
+            mixer.take(2000).map(|s| s.components()).collect::<Vec<_>>()
+        };
+
+        let reference = render(1);
+        let blocks = render(512);
+        for (a, b) in reference.iter().zip(&blocks) {
+            for (x, y) in a.iter().zip(b) {
+                assert!((x - y).abs() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn bformat_tap_receives_mixed_samples() {
+        let (mut mixer, composer) =
+            bmixer_with_config(1000, BmixerConfig::new().with_block_size(1));
+        composer.play(Constant::new(1.0, 1000), BstreamConfig::new());
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        composer.add_bformat_tap(move |sample| tx.send(sample).unwrap());
+
+        mixer.nth(9);
+        let samples: Vec<_> = rx.try_iter().collect();
+        assert_eq!(samples.len(), 10);
+        for [w, x, y, z] in samples {
+            assert!((w - 1.0 / 2f32.sqrt()).abs() < 1e-6);
+            assert!(x.abs() < 1e-6 && y.abs() < 1e-6 && z.abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn pausing_one_stream_leaves_other_streams_playing() {
+        let (mut mixer, composer) =
+            bmixer_with_config(48000, BmixerConfig::new().with_block_size(1));
+        let left = composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([-1.0, 0.0, 0.0]),
+        );
+        composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+        assert_eq!(x.dot(mixer.next().unwrap()), 0.0);
+
+        left.pause();
+        assert_eq!(x.dot(mixer.next().unwrap()), 1.0);
+
+        left.resume();
+        assert_eq!(x.dot(mixer.next().unwrap()), 0.0);
+    }
+
+    #[test]
+    fn stopped_streams_are_removed_from_the_mix() {
+        let (mut mixer, composer) =
+            bmixer_with_config(48000, BmixerConfig::new().with_block_size(1));
+        let left = composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([-1.0, 0.0, 0.0]),
+        );
+        composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+
+        mixer.next();
+        assert_eq!(mixer.active_streams.len(), 2);
+
+        left.stop();
+        let sample = mixer.next().unwrap();
+        assert_eq!(mixer.active_streams.len(), 1);
+        assert_eq!(Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample), 1.0);
+    }
+
+    #[test]
+    fn streams_can_stop_when_their_controller_is_dropped() {
+        let (mut mixer, composer) =
+            bmixer_with_config(48000, BmixerConfig::new().with_block_size(1));
+        let controller = composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_stop_on_drop(true),
+        );
+
+        mixer.next();
+        assert_eq!(mixer.active_streams.len(), 1);
+
+        drop(controller);
+        mixer.next();
+        assert_eq!(mixer.active_streams.len(), 0);
+    }
+
+    #[test]
+    fn moving_the_listener_changes_direction_of_world_sources() {
+        let (mut mixer, composer) =
+            bmixer_with_config(1000, BmixerConfig::new().with_block_size(1));
+        composer.play(
+            Constant::new(1.0, 1000),
+            BstreamConfig::new().with_world_position([1.0, 0.0, 0.0]),
+        );
+
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+        assert!(x.dot(mixer.next().unwrap()) > 0.99);
+
+        // the listener moves past the source, which now appears on the left
+        composer.set_listener_position([2.0, 0.0, 0.0]);
+        assert_eq!(composer.listener_position(), [2.0, 0.0, 0.0]);
+        let sample = mixer.nth(100).unwrap();
+        assert!(x.dot(sample) < -0.99);
+    }
+
+    #[test]
+    fn batched_updates_move_sources() {
+        let (mut mixer, composer) =
+            bmixer_with_config(1000, BmixerConfig::new().with_block_size(1));
+        let config = || BstreamConfig::new().with_position([1.0, 0.0, 0.0]);
+        let a = composer.play(Constant::new(1.0, 1000), config());
+        let b = composer.play(Constant::new(1.0, 1000), config());
+        let c = composer.play(Constant::new(1.0, 1000), config());
+
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+        assert!(x.dot(mixer.next().unwrap()) > 2.99);
+
+        // a and b move to the left, c stays on the right
+        composer.update_positions(&[(a.id(), [-1.0, 0.0, 0.0]), (b.id(), [-1.0, 0.0, 0.0])]);
+        assert_eq!(a.position(), [-1.0, 0.0, 0.0]);
+        assert_eq!(c.position(), [1.0, 0.0, 0.0]);
+        let sample = mixer.nth(100).unwrap();
+        assert!((x.dot(sample) + 1.0).abs() < 0.01);
+
+        // finished sources are ignored
+        c.stop();
+        mixer.next();
+        composer.update_positions(&[(c.id(), [0.0, 1.0, 0.0])]);
+        assert!(!composer.pending_commands.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn batched_and_individual_moves_apply_in_order() {
+        let (mut mixer, composer) =
+            bmixer_with_config(1000, BmixerConfig::new().with_block_size(1));
+        let mut sound = composer.play(
+            Constant::new(1.0, 1000),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+        mixer.next();
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+
+        // a batch replaces an earlier individual move in the same block
+        sound.adjust_position([0.0, 1.0, 0.0]);
+        composer.update_positions(&[(sound.id(), [-1.0, 0.0, 0.0])]);
+        let sample = mixer.nth(100).unwrap();
+        assert!((x.dot(sample) + 1.0).abs() < 0.01);
+
+        // an individual move after a batch in the same block overrides it
+        composer.update_positions(&[(sound.id(), [0.0, 1.0, 0.0])]);
+        sound.adjust_position([1.0, 0.0, 0.0]);
+        let sample = mixer.nth(100).unwrap();
+        assert!((x.dot(sample) - 1.0).abs() < 0.01);
+        assert_eq!(sound.position(), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mapped_positions_rotate_all_sources() {
+        let (mut mixer, composer) =
+            bmixer_with_config(1000, BmixerConfig::new().with_block_size(1));
+        let positions = [[1.0, 0.0, 0.0], [0.0, 2.0, 0.0], [-1.0, -1.0, 1.0]];
+        let controllers: Vec<_> = positions
+            .iter()
+            .map(|&pos| {
+                composer.play(
+                    Constant::new(1.0, 1000),
+                    BstreamConfig::new().with_position(pos),
+                )
+            })
+            .collect();
+        mixer.next();
+
+        // a quarter turn to the left around the listener
+        composer.map_positions(|[x, y, z]| [-y, x, z]);
+        for (controller, [x, y, z]) in controllers.iter().zip(positions) {
+            assert_eq!(controller.position(), [-y, x, z]);
+        }
+
+        // the mix follows the sources: the one on the right is now in front
+        controllers[1].stop();
+        controllers[2].stop();
+        let y = Bweights::new(0.0, 0.0, 1.0, 0.0);
+        let sample = mixer.nth(100).unwrap();
+        assert!((y.dot(sample) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn snapshot_reflects_sources() {
+        let (mut mixer, composer) = bmixer(1000);
+        let a = composer.play(
+            Constant::new(1.0, 1000),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+        let b = composer.play(
+            Constant::new(1.0, 1000),
+            BstreamConfig::new()
+                .with_position([0.0, 2.0, 0.0])
+                .with_velocity([0.0, 1.0, 0.0]),
+        );
+        b.adjust_gain(0.5);
+
+        let snapshot = composer.snapshot();
+        assert_eq!(
+            snapshot,
+            vec![
+                SourceSnapshot {
+                    id: a.id(),
+                    position: [1.0, 0.0, 0.0],
+                    world_position: None,
+                    velocity: [0.0, 0.0, 0.0],
+                    facing: [0.0, -1.0, 0.0],
+                    gain: 1.0,
+                    finished: false,
+                },
+                SourceSnapshot {
+                    id: b.id(),
+                    position: [0.0, 2.0, 0.0],
+                    world_position: None,
+                    velocity: [0.0, 1.0, 0.0],
+                    facing: [0.0, -1.0, 0.0],
+                    gain: 0.5,
+                    finished: false,
+                },
+            ]
+        );
+
+        a.stop();
+        mixer.next();
+        assert!(composer.snapshot()[0].finished);
+        drop(a);
+        assert_eq!(composer.snapshot().len(), 1);
+    }
+
+    #[test]
+    fn stereo_channels_are_played_at_their_positions() {
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+        let play = |samples: Vec<f32>| {
+            let (mixer, composer) = bmixer(48000);
+            composer.play_stereo(
+                SamplesBuffer::new(2, 48000, samples),
+                BstreamConfig::new().with_position([-1.0, 0.0, 0.0]),
+                BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+            );
+            mixer.take(2).map(|s| x.dot(s)).collect::<Vec<_>>()
+        };
+
+        assert_eq!(play(vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0]), vec![-1.0, -1.0]);
+        assert_eq!(play(vec![0.0, 1.0, 0.0, 1.0, 0.0, 1.0]), vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn multi_channel_sources_are_mixed_down() {
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+        for &sample_rate in &[48000, 24000] {
+            let (mixer, composer) = bmixer(48000);
+            composer.play(
+                SamplesBuffer::new(2, sample_rate, vec![1.0, 0.0, 1.0, 0.0, 1.0, 0.0]),
+                BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+            );
+            let output: Vec<_> = mixer.take(2).map(|s| x.dot(s)).collect();
+            assert_eq!(output, vec![0.5, 0.5]);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn stereo_playback_rejects_mono_sources() {
+        let (_, composer) = bmixer(48000);
+        composer.play_stereo(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new(),
+            BstreamConfig::new(),
+        );
+    }
+
+    #[test]
+    fn composer_counts_active_streams() {
+        let (mut mixer, composer) =
+            bmixer_with_config(48000, BmixerConfig::new().with_block_size(1));
+        for &n in &[3, 5, 7] {
+            composer.play(
+                SamplesBuffer::new(1, 48000, vec![0.0; n]),
+                BstreamConfig::new(),
+            );
+        }
+        assert_eq!(composer.active_streams(), 3);
+
+        let counts: Vec<_> = (0..8)
+            .map(|_| {
+                mixer.next();
+                composer.active_streams()
+            })
+            .collect();
+        assert_eq!(counts, vec![3, 3, 2, 2, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn stream_limit_evicts_oldest_streams() {
+        let (mut mixer, composer) = bmixer_with_config(
+            48000,
+            BmixerConfig::new().with_max_streams(2).with_block_size(1),
+        );
+        let first = composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        mixer.next();
+        composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        mixer.next();
+
+        assert_eq!(composer.active_streams(), 2);
+        assert_eq!(mixer.active_streams.len(), 2);
+
+        // the first stream is no longer playing
+        first.set_gain(0.0);
+        assert!(Bweights::new(1.0, 0.0, 0.0, 0.0).dot(mixer.next().unwrap()) > 1.0);
+    }
+
+    #[test]
+    fn stream_limit_can_evict_quietest_streams() {
+        let config = BmixerConfig::new()
+            .with_max_streams(2)
+            .with_eviction_policy(EvictionPolicy::Quietest);
+        let (mut mixer, composer) = bmixer_with_config(48000, config);
+        let pos = |x| BstreamConfig::new().with_position([x, 0.0, 0.0]);
+        composer.play(Constant::new(1.0, 48000), pos(1.0));
+        composer.play(Constant::new(1.0, 48000), pos(-10.0));
+        composer.play(Constant::new(1.0, 48000), pos(1.0));
+
+        let sample = mixer.next().unwrap();
+        assert_eq!(composer.active_streams(), 2);
+        assert_eq!(Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample), 2.0);
+    }
+
+    #[test]
+    fn stream_limit_evicts_lower_priority_streams_first() {
+        let (mut mixer, composer) = bmixer_with_config(
+            48000,
+            BmixerConfig::new().with_max_streams(2).with_block_size(1),
+        );
+        let low = composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_priority(0),
+        );
+        mixer.next();
+        let high = || BstreamConfig::new().with_priority(10);
+        let first = composer.play(Constant::new(1.0, 48000), high());
+        mixer.next();
+        let second = composer.play(Constant::new(1.0, 48000), high());
+        mixer.next();
+
+        assert_eq!(composer.active_streams(), 2);
+        assert!(low.is_finished());
+        assert!(!first.is_finished());
+        assert!(!second.is_finished());
+
+        // a high-priority stream survives even though it is the oldest
+        composer.play(Constant::new(1.0, 48000), BstreamConfig::new());
+        mixer.next();
+        assert!(!first.is_finished());
+        assert!(!second.is_finished());
+    }
+
+    #[test]
+    fn duck_triggers_attenuate_duckable_streams() {
+        let (mut mixer, composer) =
+            bmixer_with_config(48000, BmixerConfig::new().with_ducking(DuckConfig::new()));
+        composer.play(
+            Constant::new(0.1, 48000),
+            BstreamConfig::new()
+                .with_position([0.0, 1.0, 0.0])
+                .as_duckable(),
+        );
+        composer.play(
+            Constant::new(1.0, 48000).take_duration(Duration::from_millis(200)),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_start_sample(4800)
+                .as_duck_trigger(),
+        );
+
+        // the duckable stream is in front and the trigger on the right
+        let levels: Vec<f32> = (0..72000)
+            .map(|_| Bweights::new(0.0, 0.0, 1.0, 0.0).dot(mixer.next().unwrap()))
+            .collect();
+        assert!((levels[4000] - 0.1).abs() < 1e-6);
+        assert!(levels[14000] < 0.02);
+        assert!(levels[71999] > 0.095);
+        assert!(levels[14400..].windows(2).all(|l| l[1] >= l[0]));
+    }
+
+    #[test]
+    fn ducking_attenuates_the_reverb_send() {
+        let reverb = ReverbConfig::new()
+            .with_mix(1.0)
+            .with_decay_time(Duration::from_millis(50));
+        let config = BmixerConfig::new()
+            .with_ducking(DuckConfig::new())
+            .with_reverb(reverb);
+        let (mut mixer, composer) = bmixer_with_config(48000, config);
+        composer.play(
+            Constant::new(0.1, 48000),
+            BstreamConfig::new()
+                .with_position([0.0, 1.0, 0.0])
+                .as_duckable(),
+        );
+        composer.play(
+            Constant::new(1.0, 48000).take_duration(Duration::from_millis(200)),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_start_sample(4800)
+                .with_reverb_send(0.0)
+                .as_duck_trigger(),
+        );
+
+        // only the duckable stream feeds the reverb, which is all that is heard
+        let energy: Vec<f32> = (0..14400)
+            .map(|_| {
+                mixer
+                    .next()
+                    .unwrap()
+                    .components()
+                    .iter()
+                    .map(|c| c * c)
+                    .sum()
+            })
+            .collect();
+        let before: f32 = energy[2400..4800].iter().sum();
+        let ducked: f32 = energy[12000..14400].iter().sum();
+        assert!(before > 0.0);
+        assert!(ducked < 0.1 * before);
+    }
+
+    #[test]
+    fn sources_report_direction_in_listener_frame() {
+        let (_mixer, composer) = bmixer(48000);
+        let front = composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([0.0, 2.0, 0.0]),
+        );
+        let (azimuth, elevation, distance) = front.listener_relative_direction();
+        assert!(azimuth.abs() < 1e-4 && elevation.abs() < 1e-4);
+        assert!((distance - 2.0).abs() < 1e-4);
+
+        // the listener turns to the left, so the source ends up on their right
+        composer.set_listener_orientation([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+        let (azimuth, elevation, _) = front.listener_relative_direction();
+        assert!((azimuth + 90.0).abs() < 1e-4);
+        assert!(elevation.abs() < 1e-4);
+
+        // sources played later know the orientation too, and report clamped distances
+        let close = composer.play(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new()
+                .with_position([0.0, 0.0, 0.1])
+                .with_min_distance(0.5),
+        );
+        let (_, elevation, distance) = close.listener_relative_direction();
+        assert!((elevation - 90.0).abs() < 1e-4);
+        assert_eq!(distance, 0.5);
+    }
+
+    #[test]
+    fn streams_use_the_scene_speed_of_sound() {
+        let (mut mixer, composer) =
+            bmixer_with_config(1, BmixerConfig::new().with_speed_of_sound(300.0));
+        composer.play(
+            Ramp::new(1),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_velocity([100.0, 0.0, 0.0]),
+        );
+
+        let sample = mixer.nth(10).unwrap();
+        assert!((Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample) - 7.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn scene_doppler_factor_scales_the_pitch_shift() {
+        // deviation of the playback rate from 1, measured on a ramp
+        let deviation = |factor: f32| {
+            let (mut mixer, composer) =
+                bmixer_with_config(1, BmixerConfig::new().with_doppler_factor(factor));
+            composer.play(
+                Ramp::new(1),
+                BstreamConfig::new()
+                    .with_position([1.0, 0.0, 0.0])
+                    .with_velocity([1.0, 0.0, 0.0]),
+            );
+            let sample = mixer.nth(1000).unwrap();
+            1.0 - Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample) / 1000.0
+        };
+
+        let physical = deviation(1.0);
+        assert!(physical > 0.0);
+        assert!((deviation(2.0) / physical - 2.0).abs() < 0.01);
+        assert!(deviation(0.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn streams_use_the_scene_constants() {
+        let constants = SceneConstants::new()
+            .with_speed_of_sound(300.0)
+            .with_reference_distance(2.0);
+        let (mut mixer, composer) =
+            bmixer_with_config(1, BmixerConfig::new().with_constants(constants));
+        let config = || {
+            BstreamConfig::new()
+                .with_position([2.0, 0.0, 0.0])
+                .with_velocity([100.0, 0.0, 0.0])
+        };
+        composer.play(Ramp::new(1), config());
+
+        // full level at the reference distance, and the doppler effect of the slower sound
+        let sample = mixer.nth(10).unwrap();
+        assert!((Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample) - 7.5).abs() < 1e-4);
+
+        // streams can still override the constants
+        let (mut mixer, composer) =
+            bmixer_with_config(1, BmixerConfig::new().with_constants(constants));
+        composer.play(Ramp::new(1), config().with_speed_of_sound(1e9));
+        let sample = mixer.nth(10).unwrap();
+        assert!((Bweights::new(0.0, 1.0, 0.0, 0.0).dot(sample) - 10.0).abs() < 1e-4);
+    }
 }
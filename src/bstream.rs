@@ -1,66 +1,403 @@
 //! Represent audio sources in *B-format*.
 
-use crate::bformat::{Bformat, Bweights};
-use crate::constants::SPEED_OF_SOUND;
-use rodio::{Sample, Source};
-use std::sync::atomic::{AtomicBool, Ordering};
+use crate::bformat::{AmbisonicOrder, Bformat, BformatBlock, Bweights, Rotation};
+use crate::constants::SceneConstants;
+use crate::envelope::{Adsr, Envelope};
+use crate::math;
+use crate::sync::{catch_panic, lock};
+use rodio::{source::UniformSourceIterator, Sample, Source};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 /// Convert a `rodio::Source` to a spatial `Bstream` source with associated controller
 ///
-/// The input source must produce `f32` samples and is expected to have exactly one channel.
+/// The input source must produce `f32` samples and have exactly one channel; this function
+/// panics otherwise. `BmixerComposer::play` mixes down multi-channel sources before they get
+/// here.
+/// The stream keeps the sample rate of its source; `BmixerComposer::play` takes care of
+/// resampling sources to the scene's sample rate.
 pub fn bstream<I: Source<Item = f32> + Send + 'static>(
-    mut source: I,
+    source: I,
+    mut config: BstreamConfig,
+) -> (Bstream, SoundController) {
+    let source = normalize(source, &mut config);
+    normalized_bstream(source, config)
+}
+
+/// Box a source for `normalized_bstream`, applying and clearing the config's target level
+///
+/// Normalization decodes the first half second of the source, so this should be called before
+/// taking any locks that the audio thread waits for.
+pub(crate) fn normalize<I: Source<Item = f32> + Send + 'static>(
+    source: I,
+    config: &mut BstreamConfig,
+) -> Box<dyn Source<Item = f32> + Send> {
+    match config.target_rms.take() {
+        Some(target) => Box::new(Normalized::new(source, target)),
+        None => Box::new(source),
+    }
+}
+
+/// Like `bstream`, for a source that has already been passed through `normalize`
+pub(crate) fn normalized_bstream(
+    mut source: Box<dyn Source<Item = f32> + Send>,
     config: BstreamConfig,
 ) -> (Bstream, SoundController) {
-    assert_eq!(source.channels(), 1);
+    assert_eq!(
+        source.channels(),
+        1,
+        "only single-channel sources can be spatialized"
+    );
 
     let bridge = Arc::new(BstreamBridge {
         commands: Mutex::new(Vec::new()),
         pending_commands: AtomicBool::new(false),
         stopped: AtomicBool::new(false),
+        peak_level: AtomicU32::new(0f32.to_bits()),
+        rms_level: AtomicU32::new(0f32.to_bits()),
+        distance: AtomicU32::new(f32::NAN.to_bits()),
     });
 
-    let (position, weights) = match config.position {
-        Some(p) => (p, Bweights::from_position(p)),
-        None => ([0.0, 0.0, 0.0], Bweights::omni_source()),
+    let defaults = SceneConstants::default();
+    let speed_of_sound = config.speed_of_sound.unwrap_or(defaults.speed_of_sound());
+    let attenuation = Attenuation {
+        model: config.attenuation,
+        custom: config.attenuation_fn.clone(),
+        reference_distance: config
+            .reference_distance
+            .unwrap_or(defaults.reference_distance()),
+        max_distance: config.max_distance,
     };
 
-    let controller = SoundController {
-        bridge: bridge.clone(),
-        position,
+    let world_position = config.world_position;
+    let state = SourceState {
+        position: [0.0, 0.0, 0.0],
+        world_position,
+        listener_position: config.listener_position,
+        listener_velocity: config.listener_velocity,
+        listener_rotation: config.listener_rotation,
+        listener_focus: config.listener_focus,
         velocity: config.velocity,
+        doppler: config.doppler,
         doppler_factor: config.doppler_factor,
-        speed_of_sound: config.speed_of_sound,
+        scene_doppler_factor: config
+            .scene_doppler_factor
+            .unwrap_or(defaults.doppler_factor()),
+        speed_of_sound,
+        attenuation,
+        directivity: config.directivity,
+        facing: config.facing,
+        order: config.order,
+        direct: config.direct,
+        gain: 1.0,
+        speed: 1.0,
+        min_distance: config.min_distance.unwrap_or(defaults.min_distance()),
+        spread: 0.0,
+        air_absorption: config.air_absorption.unwrap_or(defaults.air_absorption()),
+        direction_quantization: config.direction_quantization,
+    };
+
+    let (position, weights) = match (world_position, config.position) {
+        _ if config.direct => ([0.0, 0.0, 0.0], Bweights::omni_source()),
+        (Some(p), _) => {
+            let p = state.relative_position(p);
+            (p, state.position_weights(p))
+        }
+        (None, Some(p)) => (p, state.position_weights(p)),
+        (None, None) => ([0.0, 0.0, 0.0], Bweights::omni_source()),
+    };
+    let air_cutoff = state.air_absorption_cutoff(position);
+
+    // a custom attenuation curve is evaluated by the stream, starting with the initial position
+    let custom_attenuation = match &state.attenuation.custom {
+        Some(_) if !config.direct => {
+            let placed = config.position.is_some() || world_position.is_some();
+            let distance = if placed {
+                state.attenuation_distance(position)
+            } else {
+                f32::NAN
+            };
+            bridge.set_distance(distance);
+            let mut attenuation = state.attenuation.clone();
+            let gain = if placed {
+                attenuation.gain(distance)
+            } else {
+                1.0
+            };
+            Some(CustomAttenuation {
+                attenuation,
+                distance,
+                gain,
+                placement: weights,
+            })
+        }
+        _ => None,
+    };
+    let weights = match &custom_attenuation {
+        Some(c) => weights.amplify(c.gain),
+        None => weights,
+    };
+
+    let shared = Arc::new(SoundShared {
+        id: SourceId::next(),
+        bridge: bridge.clone(),
+        state: Mutex::new(SourceState { position, ..state }),
+        controllers: AtomicUsize::new(1),
+        detached: AtomicBool::new(false),
+    });
+
+    let controller = SoundController {
+        shared: shared.clone(),
+        stop_on_drop: config.stop_on_drop,
     };
 
+    let doppler_factor = if config.doppler && !config.direct {
+        state.doppler_factor * state.scene_doppler_factor
+    } else {
+        0.0
+    };
+
+    let smoothing_samples = config.position_smoothing.as_secs_f32() * source.sample_rate() as f32;
+    let meter_window = (METER_WINDOW * source.sample_rate() as f32).max(1.0) as usize;
+    let fade_in_samples = config.fade_in.as_secs_f32() * source.sample_rate() as f32;
+
+    let previous_sample = source.next().unwrap_or(0.0);
+    let highpass = config
+        .highpass
+        .map(|fc| Highpass::new(fc, source.sample_rate(), previous_sample));
+    let air_lowpass = air_cutoff.map(|fc| lowpass_coefficient(fc, source.sample_rate()));
+    let envelope = config.envelope.map(|e| Adsr::new(&e, source.sample_rate()));
+    let speed = compute_doppler_rate(
+        position,
+        relative_velocity(config.velocity, config.listener_velocity),
+        doppler_factor,
+        speed_of_sound,
+    );
+
     let stream = Bstream {
         bweights: weights,
         target_weights: weights,
-        speed: compute_doppler_rate(
-            position,
-            config.velocity,
-            config.doppler_factor,
-            config.speed_of_sound,
-        ),
+        weight_step: 0.0,
+        smoothing_samples,
+        gain: 1.0,
+        target_gain: 1.0,
+        gain_step: 1.0 / (GAIN_RAMP_DURATION * source.sample_rate() as f32),
+        speed,
+        target_speed: speed,
+        speed_ramp: 0.0,
         sampling_offset: 0.0,
-        previous_sample: source.next().unwrap_or(0.0),
+        previous_sample,
         next_sample: source.next().unwrap_or(0.0),
         bridge,
-        input: Box::new(source),
+        shared,
+        input: source,
         paused: false,
+        highpass,
+        lowpass: None,
+        lowpass_state: 0.0,
+        air_lowpass,
+        air_lowpass_state: previous_sample,
+        reverb_send: config.reverb_send,
+        eq: None,
+        start_sample: config.start_sample,
+        delay: 0,
+        priority: config.priority,
+        duck_role: config.duck_role,
+        meter: Meter::new(meter_window),
+        fade: if fade_in_samples > 0.0 { 0.0 } else { 1.0 },
+        fade_step: if fade_in_samples > 0.0 {
+            1.0 / fade_in_samples
+        } else {
+            0.0
+        },
+        envelope,
+        custom_attenuation,
     };
 
     (stream, controller)
 }
 
+/// Distance attenuation models
+///
+/// Describes how the level of a source decreases with its distance `r` from the listener.
+/// Distances are clamped to the range between the reference distance and the maximum distance
+/// before the attenuation is applied. At the reference distance, sources play at full level.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AttenuationModel {
+    /// Sources do not get quieter with distance.
+    None,
+
+    /// Level is inversely proportional to distance (1/r).
+    InverseDistance,
+
+    /// Level is inversely proportional to squared distance (1/r²).
+    InverseSquare,
+}
+
+/// User-supplied mapping from distance to gain
+type AttenuationFn = Arc<dyn Fn(f32) -> f32 + Send + Sync>;
+
+#[derive(Clone)]
+struct Attenuation {
+    model: AttenuationModel,
+    custom: Option<AttenuationFn>,
+    reference_distance: f32,
+    max_distance: f32,
+}
+
+impl Attenuation {
+    /// Gain of the custom curve, or of the model if there is none
+    ///
+    /// A curve that panics is replaced by the model for good, and non-finite results of the
+    /// curve fall back to the model.
+    fn gain(&mut self, distance: f32) -> f32 {
+        if let Some(f) = &self.custom {
+            match catch_panic(|| f(distance)) {
+                Some(gain) if gain.is_finite() => return gain,
+                Some(_) => {}
+                None => self.custom = None,
+            }
+        }
+        self.model_gain(distance)
+    }
+
+    /// Gain of the weights the controller computes; custom curves are applied by the stream
+    fn placement_gain(&self, distance: f32) -> f32 {
+        if self.custom.is_some() {
+            1.0
+        } else {
+            self.model_gain(distance)
+        }
+    }
+
+    fn model_gain(&self, distance: f32) -> f32 {
+        let relative =
+            distance.max(self.reference_distance).min(self.max_distance) / self.reference_distance;
+        match self.model {
+            AttenuationModel::None => 1.0,
+            AttenuationModel::InverseDistance => 1.0 / relative,
+            AttenuationModel::InverseSquare => 1.0 / (relative * relative),
+        }
+    }
+}
+
+/// Role of a stream in the scene's ducking (see `DuckConfig`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum DuckRole {
+    None,
+    Trigger,
+    Duckable,
+}
+
+/// Directional radiation patterns of sound sources
+///
+/// Describes how the level of a source depends on the angle `θ` between the direction the source
+/// is facing and the direction from the source towards the listener.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Directivity {
+    /// Sources radiate equally in all directions.
+    Omni,
+
+    /// Sources radiate mostly to the front and not at all to the back ((1 + cos θ) / 2).
+    Cardioid,
+
+    /// Sources radiate to the front and to the back but not to the sides (|cos θ|).
+    FigureEight,
+}
+
+impl Directivity {
+    fn gain(self, facing: [f32; 3], pos: [f32; 3]) -> f32 {
+        let dist = (pos[0] * pos[0] + pos[1] * pos[1] + pos[2] * pos[2]).sqrt();
+        let norm = (facing[0] * facing[0] + facing[1] * facing[1] + facing[2] * facing[2]).sqrt();
+        if self == Directivity::Omni || dist == 0.0 || norm == 0.0 {
+            return 1.0;
+        }
+
+        // the listener is at the origin, so it is in direction -pos as seen from the source
+        let cos_theta =
+            -(facing[0] * pos[0] + facing[1] * pos[1] + facing[2] * pos[2]) / (dist * norm);
+
+        match self {
+            Directivity::Omni => 1.0,
+            Directivity::Cardioid => 0.5 * (1.0 + cos_theta),
+            Directivity::FigureEight => cos_theta.abs(),
+        }
+    }
+}
+
+/// Emphasis of sources in the direction the listener is facing
+///
+/// Sources within half the cone angle of the listener's forward direction get the front gain.
+/// Further off-axis, the gain changes linearly with the angle towards the rear gain directly
+/// behind the listener.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct ListenerFocus {
+    // half the opening angle of the cone, in degrees
+    half_angle: f32,
+    front_gain: f32,
+    rear_gain: f32,
+}
+
+impl ListenerFocus {
+    pub(crate) fn new(angle: f32, front_gain: f32, rear_gain: f32) -> Self {
+        ListenerFocus {
+            half_angle: angle.clamp(0.0, 360.0) / 2.0,
+            front_gain,
+            rear_gain,
+        }
+    }
+
+    /// gain of a source in direction `dir` in the listener's frame, where forward is +y
+    fn gain(&self, dir: [f32; 3]) -> f32 {
+        let dist = (dir[0] * dir[0] + dir[1] * dir[1] + dir[2] * dir[2]).sqrt();
+        if dist == 0.0 {
+            return self.front_gain;
+        }
+        let angle = (dir[1] / dist).clamp(-1.0, 1.0).acos().to_degrees();
+        if angle <= self.half_angle {
+            self.front_gain
+        } else {
+            let t = (angle - self.half_angle) / (180.0 - self.half_angle);
+            self.front_gain + t * (self.rear_gain - self.front_gain)
+        }
+    }
+}
+
 /// Initial configuration for constructing `Bstream`s
 pub struct BstreamConfig {
     position: Option<[f32; 3]>,
     velocity: [f32; 3],
+    doppler: bool,
     doppler_factor: f32,
-    speed_of_sound: f32,
+    scene_doppler_factor: Option<f32>,
+    speed_of_sound: Option<f32>,
+    attenuation: AttenuationModel,
+    attenuation_fn: Option<AttenuationFn>,
+    reference_distance: Option<f32>,
+    max_distance: f32,
+    stop_on_drop: bool,
+    position_smoothing: Duration,
+    directivity: Directivity,
+    facing: [f32; 3],
+    world_position: Option<[f32; 3]>,
+    listener_position: [f32; 3],
+    listener_velocity: [f32; 3],
+    listener_rotation: Option<Rotation>,
+    listener_focus: Option<ListenerFocus>,
+    order: AmbisonicOrder,
+    direct: bool,
+    reverb_send: f32,
+    highpass: Option<f32>,
+    target_rms: Option<f32>,
+    air_absorption: Option<bool>,
+    min_distance: Option<f32>,
+    direction_quantization: f32,
+    start_sample: Option<u64>,
+    fade_in: Duration,
+    envelope: Option<Envelope>,
+    priority: u8,
+    duck_role: DuckRole,
 }
 
 impl Default for BstreamConfig {
@@ -68,8 +405,36 @@ impl Default for BstreamConfig {
         BstreamConfig {
             position: None,
             velocity: [0.0, 0.0, 0.0],
+            doppler: true,
             doppler_factor: 1.0,
-            speed_of_sound: SPEED_OF_SOUND,
+            scene_doppler_factor: None,
+            speed_of_sound: None,
+            attenuation: AttenuationModel::InverseDistance,
+            attenuation_fn: None,
+            reference_distance: None,
+            max_distance: f32::INFINITY,
+            stop_on_drop: false,
+            position_smoothing: Duration::from_millis(20),
+            directivity: Directivity::Omni,
+            facing: [0.0, -1.0, 0.0],
+            world_position: None,
+            listener_position: [0.0, 0.0, 0.0],
+            listener_velocity: [0.0, 0.0, 0.0],
+            listener_rotation: None,
+            listener_focus: None,
+            order: AmbisonicOrder::First,
+            direct: false,
+            reverb_send: 1.0,
+            highpass: None,
+            target_rms: None,
+            air_absorption: None,
+            min_distance: None,
+            direction_quantization: 0.0,
+            start_sample: None,
+            fade_in: Duration::from_secs(0),
+            envelope: None,
+            priority: 0,
+            duck_role: DuckRole::None,
         }
     }
 }
@@ -92,15 +457,278 @@ impl BstreamConfig {
         self
     }
 
+    /// Enable or disable the doppler effect for this stream.
+    ///
+    /// A stream without doppler effect never changes pitch, regardless of its velocity.
+    /// The doppler effect is enabled by default.
+    pub fn with_doppler(mut self, enabled: bool) -> Self {
+        self.doppler = enabled;
+        self
+    }
+
     /// Set doppler factor for this stream.
     pub fn with_doppler_factor(mut self, d: f32) -> Self {
         self.doppler_factor = d;
         self
     }
 
-    /// Set speed of sound for this stream.
+    /// Set speed of sound (in m/s) for this stream.
+    ///
+    /// The speed of sound determines the strength of the doppler effect. Very high values
+    /// effectively disable the doppler effect. Defaults to the scene's speed of sound, or to
+    /// `constants::SPEED_OF_SOUND` if the stream is not played in a scene.
     pub fn with_speed_of_sound(mut self, s: f32) -> Self {
-        self.speed_of_sound = s;
+        self.speed_of_sound = Some(s);
+        self
+    }
+
+    /// Set distance attenuation model (defaults to `AttenuationModel::InverseDistance`).
+    pub fn with_attenuation(mut self, model: AttenuationModel) -> Self {
+        self.attenuation = model;
+        self
+    }
+
+    /// Set a custom distance attenuation curve, overriding the attenuation model
+    ///
+    /// The function maps the distance (in m) of the source from the listener to its gain. The
+    /// reference and maximum distance do not apply, but distances are limited to the minimum
+    /// distance (see `with_min_distance`). The function is evaluated on the audio thread once
+    /// per mixer block with the current distance, and once when the source is played, so it
+    /// must be cheap and must not allocate or block. It may be called from several threads,
+    /// hence `Send + Sync`. The gain follows changes of the curve's result as smoothly as
+    /// changes of the position (see `with_position_smoothing`). If the function panics, the
+    /// attenuation model is used instead.
+    pub fn with_attenuation_fn(mut self, f: Arc<dyn Fn(f32) -> f32 + Send + Sync>) -> Self {
+        self.attenuation_fn = Some(f);
+        self
+    }
+
+    /// Set the distance up to which the source plays at full level.
+    ///
+    /// Defaults to the scene's reference distance, or to `constants::REFERENCE_DISTANCE` if the
+    /// stream is not played in a scene.
+    pub fn with_reference_distance(mut self, d: f32) -> Self {
+        self.reference_distance = Some(d);
+        self
+    }
+
+    /// Set the distance beyond which the source does not get any quieter (defaults to infinity).
+    pub fn with_max_distance(mut self, d: f32) -> Self {
+        self.max_distance = d;
+        self
+    }
+
+    /// Set the radius around the listener within which the source loses its direction
+    ///
+    /// Defaults to the scene's minimum distance, or to `constants::MIN_DISTANCE` if the stream is
+    /// not played in a scene. Inside this radius the directional components fade out, so a
+    /// source at the listener's position is heard from all directions equally instead of from
+    /// an undefined direction.
+    /// Distance attenuation treats the source as if it were at least this far away.
+    pub fn with_min_distance(mut self, d: f32) -> Self {
+        self.min_distance = Some(d.max(0.0));
+        self
+    }
+
+    /// Round the direction of the source to a grid with the given spacing (in degrees)
+    ///
+    /// Azimuth and elevation (see the `math` module) are rounded to multiples of `degrees`
+    /// before the source is encoded, so small movements around a direction do not change the
+    /// encoding. This hides jitter of noisy position input, e.g. from tracked controllers, at
+    /// the cost of the source jumping from one grid direction to the next. Distance attenuation
+    /// and directivity use the exact position. 0 (the default) disables the quantization.
+    pub fn with_direction_quantization(mut self, degrees: f32) -> Self {
+        self.direction_quantization = degrees.max(0.0);
+        self
+    }
+
+    /// Set the directivity pattern of the source and the direction it is facing
+    ///
+    /// Defaults to `Directivity::Omni`. The `facing` direction does not need to be normalized.
+    pub fn with_directivity(mut self, pattern: Directivity, facing: [f32; 3]) -> Self {
+        self.directivity = pattern;
+        self.facing = facing;
+        self
+    }
+
+    /// Set the time it takes the stream to transition to a new position (defaults to 20 ms).
+    ///
+    /// A transition is started whenever the position is changed with
+    /// `SoundController::adjust_position`. Longer transitions reduce artifacts of large jumps
+    /// but make the stream react slower to position changes. A zero duration disables
+    /// smoothing.
+    pub fn with_position_smoothing(mut self, duration: Duration) -> Self {
+        self.position_smoothing = duration;
+        self
+    }
+
+    /// Start the stream at the given sample of the scene's clock
+    ///
+    /// See `BmixerComposer::current_sample`. Streams whose start sample has already passed start
+    /// immediately. Without a start sample, streams start at the next block the mixer processes.
+    pub fn with_start_sample(mut self, sample: u64) -> Self {
+        self.start_sample = Some(sample);
+        self
+    }
+
+    /// Fade the stream in from silence over the given duration when it starts (defaults to 0).
+    ///
+    /// The fade is applied on top of the stream's gain. A zero duration starts the stream at
+    /// full level.
+    pub fn with_fade_in(mut self, duration: Duration) -> Self {
+        self.fade_in = duration;
+        self
+    }
+
+    /// Shape the amplitude of the stream with an ADSR envelope (none by default).
+    ///
+    /// The envelope starts with the stream and is applied on top of its gain. The stream holds
+    /// the sustain level until `SoundController::note_off`, and finishes when the release is
+    /// complete.
+    pub fn with_envelope(mut self, envelope: Envelope) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    /// Set the priority of the stream when the scene limits the number of streams (defaults to 0)
+    ///
+    /// The mixer only evicts streams of the lowest priority that is playing, so streams of
+    /// higher priority are protected from lower-priority ones. Among streams of equal priority,
+    /// the eviction policy decides (see `BmixerConfig::with_max_streams`).
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Duck the scene's duckable streams while this stream is loud, e.g. for dialogue
+    ///
+    /// Has no effect unless the scene is configured with a `DuckConfig` (see
+    /// `BmixerConfig::with_ducking`). A stream is either a trigger or duckable, not both.
+    pub fn as_duck_trigger(mut self) -> Self {
+        self.duck_role = DuckRole::Trigger;
+        self
+    }
+
+    /// Attenuate this stream while the scene's duck triggers are loud, e.g. for ambience
+    ///
+    /// The stream's reverb send is attenuated along with it. See `as_duck_trigger`.
+    pub fn as_duckable(mut self) -> Self {
+        self.duck_role = DuckRole::Duckable;
+        self
+    }
+
+    /// Remove frequencies below a cutoff (in Hz) from the source before it is spatialized.
+    ///
+    /// A first-order high-pass filter removes DC offset and rumble, which would otherwise waste
+    /// headroom and may cause speakers to pop. Off by default; 20 Hz is a gentle cutoff that
+    /// leaves audible frequencies untouched.
+    pub fn with_highpass(mut self, cutoff: f32) -> Self {
+        self.highpass = Some(cutoff);
+        self
+    }
+
+    /// Normalize the level of the source to the given RMS amplitude
+    ///
+    /// The source's RMS level is measured over its first 500 ms before the stream starts, and
+    /// a constant gain is applied to all of the source so that this level matches `target`.
+    /// Sources that end within that time are measured as a whole, but streaming sources whose
+    /// level changes later on, e.g. songs with a quiet intro, are only matched at their start.
+    /// Measure long prepared sounds with `sources::PreparedSound::rms` and set the gain instead.
+    /// The gain is applied in addition to the stream's own gain and limited to 40 dB; silent
+    /// sources are not amplified. Off by default.
+    pub fn with_target_rms(mut self, target: f32) -> Self {
+        self.target_rms = Some(target.max(0.0));
+        self
+    }
+
+    /// Enable or disable air absorption for this stream
+    ///
+    /// Air absorbs high frequencies more than low ones, so distant sources sound duller. With
+    /// air absorption, a low-pass filter is applied to the source whose cutoff frequency is
+    /// inversely proportional to its distance from the listener: 20 kHz at 10 m, 2 kHz at
+    /// 100 m, down to 200 Hz at 1 km and beyond. Closer sources are not filtered. The filter
+    /// follows the source as it moves, and is applied in addition to distance attenuation and
+    /// occlusion. Defaults to the scene's setting (see `SceneConstants::with_air_absorption`),
+    /// which is off unless changed.
+    pub fn with_air_absorption(mut self, enabled: bool) -> Self {
+        self.air_absorption = Some(enabled);
+        self
+    }
+
+    /// Stop the stream when its controller is dropped (defaults to false).
+    ///
+    /// If the controller was cloned, the stream stops when the last clone is dropped.
+    pub fn with_stop_on_drop(mut self, stop: bool) -> Self {
+        self.stop_on_drop = stop;
+        self
+    }
+
+    /// Set initial source position in world coordinates
+    ///
+    /// The position is fixed in the world rather than relative to the listener, and takes
+    /// precedence over `with_position`. See `SoundController::set_world_position`.
+    pub fn with_world_position(mut self, pos: [f32; 3]) -> Self {
+        self.world_position = Some(pos);
+        self
+    }
+
+    /// Set the listener position that world positions are relative to.
+    pub(crate) fn with_listener_position(mut self, pos: [f32; 3]) -> Self {
+        self.listener_position = pos;
+        self
+    }
+
+    /// Set the listener velocity that the doppler effect is relative to.
+    pub(crate) fn with_listener_velocity(mut self, vel: [f32; 3]) -> Self {
+        self.listener_velocity = vel;
+        self
+    }
+
+    /// Set the listener orientation that listener-relative directions are reported in.
+    pub(crate) fn with_listener_rotation(mut self, rot: Option<Rotation>) -> Self {
+        self.listener_rotation = rot;
+        self
+    }
+
+    /// Set the scene's emphasis of sources the listener is facing.
+    pub(crate) fn with_listener_focus(mut self, focus: Option<ListenerFocus>) -> Self {
+        self.listener_focus = focus;
+        self
+    }
+
+    /// Set how much of the stream is fed into the scene's reverb (defaults to 1).
+    ///
+    /// The send is applied after the stream's gain but before distance attenuation, so distant
+    /// sources sound more reverberant than close ones. Has no effect in scenes without reverb
+    /// (see `BmixerConfig::with_reverb`). Negative values are clamped to 0.
+    pub fn with_reverb_send(mut self, send: f32) -> Self {
+        self.reverb_send = send.max(0.0);
+        self
+    }
+
+    /// Set the ambisonic order the stream is encoded with.
+    pub(crate) fn with_order(mut self, order: AmbisonicOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Bypass spatialization: the stream plays equally in all directions, regardless of position.
+    pub(crate) fn with_direct(mut self) -> Self {
+        self.direct = true;
+        self
+    }
+
+    /// Use the scene's constants for all values that were not set explicitly.
+    pub(crate) fn with_scene_constants(mut self, constants: &SceneConstants) -> Self {
+        self.speed_of_sound
+            .get_or_insert(constants.speed_of_sound());
+        self.reference_distance
+            .get_or_insert(constants.reference_distance());
+        self.min_distance.get_or_insert(constants.min_distance());
+        self.scene_doppler_factor
+            .get_or_insert(constants.doppler_factor());
+        self.air_absorption
+            .get_or_insert(constants.air_absorption());
         self
     }
 }
@@ -111,18 +739,202 @@ impl BstreamConfig {
 pub struct Bstream {
     input: Box<dyn Source<Item = f32> + Send>,
     bridge: Arc<BstreamBridge>,
+    // keeps the controller state alive while the stream plays, so it can follow the listener
+    shared: Arc<SoundShared>,
 
     bweights: Bweights,
     target_weights: Bweights,
-
-    speed: f32,
-    sampling_offset: f32,
+    weight_step: f32,
+    smoothing_samples: f32,
+
+    gain: f32,
+    target_gain: f32,
+    gain_step: f32,
+
+    // playback rate and fractional read position, in double precision so that long playback
+    // at a constant rate does not drift
+    speed: f64,
+    sampling_offset: f64,
+    // playback rate at the end of a transition, and the number of samples it has left
+    target_speed: f64,
+    speed_ramp: f64,
     previous_sample: f32,
     next_sample: f32,
     paused: bool,
+
+    // rumble filter applied before all others, `None` if bypassed
+    highpass: Option<Highpass>,
+    // coefficient of the occlusion low-pass filter, `None` if the filter is bypassed
+    lowpass: Option<f32>,
+    lowpass_state: f32,
+    // coefficient of the distance-dependent air absorption filter, `None` if bypassed
+    air_lowpass: Option<f32>,
+    air_lowpass_state: f32,
+
+    reverb_send: f32,
+
+    // tone control, `None` if bypassed
+    eq: Option<ShelvingEq>,
+
+    start_sample: Option<u64>,
+    // number of silent samples to emit before the input starts
+    delay: u64,
+
+    // streams of lower priority are evicted first when the mixer has too many streams
+    priority: u8,
+
+    duck_role: DuckRole,
+
+    // amplitude envelope of fade-in and fade-out, and its change per sample
+    fade: f32,
+    fade_step: f32,
+
+    // ADSR envelope, `None` if the stream has none
+    envelope: Option<Adsr>,
+
+    meter: Meter,
+
+    // custom attenuation curve, which the stream evaluates itself; weights from the
+    // controller do not include it
+    custom_attenuation: Option<CustomAttenuation>,
+}
+
+/// Custom attenuation curve of a stream, and the state of its evaluation
+struct CustomAttenuation {
+    attenuation: Attenuation,
+    // distance of the last evaluation, and the resulting gain
+    distance: f32,
+    gain: f32,
+    // target weights without the gain of the curve
+    placement: Bweights,
+}
+
+impl Bstream {
+    /// Current gain of the stream, including distance attenuation
+    pub(crate) fn gain(&self) -> f32 {
+        self.target_gain * self.target_weights.omni_gain()
+    }
+
+    /// Identifier of the stream's source
+    pub(crate) fn id(&self) -> SourceId {
+        self.shared.id
+    }
+
+    /// Eviction priority of the stream
+    pub(crate) fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Role of the stream in the scene's ducking
+    pub(crate) fn duck_role(&self) -> DuckRole {
+        self.duck_role
+    }
+
+    /// Start a smooth transition towards new weights
+    pub(crate) fn set_target(&mut self, bw: Bweights) {
+        let bw = match &mut self.custom_attenuation {
+            Some(c) => {
+                c.placement = bw;
+                bw.amplify(c.gain)
+            }
+            None => bw,
+        };
+        self.approach_weights(bw);
+    }
+
+    /// Jump to new weights
+    fn set_weights(&mut self, bw: Bweights) {
+        self.bweights = match &self.custom_attenuation {
+            Some(c) => bw.amplify(c.gain),
+            None => bw,
+        };
+    }
+
+    /// Evaluate the custom attenuation curve at the current distance of the source
+    ///
+    /// The curve is evaluated on every call if `every_call` is set, otherwise only when the
+    /// distance has changed.
+    fn update_attenuation(&mut self, every_call: bool) {
+        let c = match &mut self.custom_attenuation {
+            Some(c) => c,
+            None => return,
+        };
+        let distance = f32::from_bits(self.bridge.distance.load(Ordering::Relaxed));
+        if distance.is_nan() || (!every_call && distance == c.distance) {
+            return;
+        }
+        c.distance = distance;
+        let gain = c.attenuation.gain(distance);
+        if gain != c.gain {
+            c.gain = gain;
+            let target = c.placement.amplify(gain);
+            self.approach_weights(target);
+        }
+    }
+
+    /// Transition towards new weights over the position smoothing time
+    fn approach_weights(&mut self, bw: Bweights) {
+        self.target_weights = bw;
+        self.weight_step = if self.smoothing_samples > 0.0 {
+            self.bweights.distance(&bw) / self.smoothing_samples
+        } else {
+            f32::INFINITY
+        };
+    }
+
+    /// Set the playback rate of the input, which implements the doppler effect
+    ///
+    /// During a transition started by `ramp_speed`, the transition continues towards the new
+    /// rate over its remaining time instead.
+    pub(crate) fn set_speed(&mut self, speed: f64) {
+        self.target_speed = speed;
+        if self.speed_ramp == 0.0 {
+            self.speed = speed;
+        }
+    }
+
+    /// Change the playback rate linearly over the given duration
+    fn ramp_speed(&mut self, speed: f64, duration: Duration) {
+        let samples = duration.as_secs_f64() * self.input.sample_rate() as f64;
+        self.speed_ramp = samples.floor();
+        self.set_speed(speed);
+    }
+
+    /// Set the cutoff frequency (in Hz) of the air absorption filter, `None` to bypass it
+    pub(crate) fn set_air_absorption(&mut self, cutoff: Option<f32>) {
+        if self.air_lowpass.is_none() {
+            self.air_lowpass_state = self.previous_sample;
+        }
+        let sr = self.input.sample_rate();
+        self.air_lowpass = cutoff.map(|fc| lowpass_coefficient(fc, sr));
+    }
+
+    /// Delay the start of the stream according to its start sample, given the current sample
+    /// of the scene's clock
+    pub(crate) fn schedule(&mut self, now: u64) {
+        if let Some(start) = self.start_sample {
+            self.delay = start.saturating_sub(now);
+        }
+    }
+
+    /// Stop the stream without playing it further
+    pub(crate) fn stop(self) {
+        self.bridge.stopped.store(true, Ordering::SeqCst);
+    }
 }
 
-impl Bstream {}
+impl Drop for Bstream {
+    fn drop(&mut self) {
+        // a stream that is dropped will not play any more, e.g. when it was removed from a mixer
+        self.bridge.stopped.store(true, Ordering::SeqCst);
+        self.bridge
+            .peak_level
+            .store(0f32.to_bits(), Ordering::Relaxed);
+        self.bridge
+            .rms_level
+            .store(0f32.to_bits(), Ordering::Relaxed);
+    }
+}
 
 impl Source for Bstream {
     #[inline(always)]
@@ -147,38 +959,116 @@ impl Source for Bstream {
     }
 }
 
-impl Iterator for Bstream {
-    type Item = Bformat;
+impl Bstream {
+    /// Apply pending commands from the controller; returns false if the stream was stopped.
+    fn process_commands(&mut self) -> bool {
+        if !self.bridge.pending_commands.load(Ordering::SeqCst) {
+            return true;
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.bridge.pending_commands.load(Ordering::SeqCst) {
-            let mut commands = self.bridge.commands.lock().unwrap();
-
-            for cmd in commands.drain(..) {
-                match cmd {
-                    Command::SetWeights(bw) => self.bweights = bw,
-                    Command::SetTarget(bw) => self.target_weights = bw,
-                    Command::SetSpeed(s) => self.speed = s,
-                    Command::Stop => {
+        let bridge = self.bridge.clone();
+        let mut commands = lock(&bridge.commands);
+
+        for cmd in commands.drain(..) {
+            match cmd {
+                Command::SetWeights(bw) => self.set_weights(bw),
+                Command::SetTarget(bw) => self.set_target(bw),
+                Command::SetSpeed(s) => self.set_speed(s),
+                Command::RampSpeed(s, duration) => self.ramp_speed(s, duration),
+                Command::SetGain(g) => {
+                    self.gain = g;
+                    self.target_gain = g;
+                }
+                Command::SetTargetGain(g) => self.target_gain = g,
+                Command::Stop => {
+                    self.bridge.stopped.store(true, Ordering::SeqCst);
+                    return false;
+                }
+                Command::FadeOut(duration) => {
+                    // a stream that is still silent has nothing left to fade
+                    if self.fade <= 0.0 {
+                        self.bridge.stopped.store(true, Ordering::SeqCst);
+                        return false;
+                    }
+                    let samples = duration.as_secs_f32() * self.input.sample_rate() as f32;
+                    self.fade_step = -self.fade / samples.max(1.0);
+                }
+                Command::NoteOff => match &mut self.envelope {
+                    Some(envelope) => envelope.note_off(),
+                    None => {
                         self.bridge.stopped.store(true, Ordering::SeqCst);
-                        return None;
+                        return false;
                     }
-                    Command::Pause => self.paused = true,
-                    Command::Resume => self.paused = false,
+                },
+                Command::Pause => self.paused = true,
+                Command::Resume => self.paused = false,
+                Command::SetOcclusion(amount) => {
+                    if amount > 0.0 {
+                        if self.lowpass.is_none() {
+                            self.lowpass_state = self.previous_sample;
+                        }
+                        let cutoff = OCCLUSION_MAX_CUTOFF
+                            * (OCCLUSION_MIN_CUTOFF / OCCLUSION_MAX_CUTOFF).powf(amount);
+                        let sr = self.input.sample_rate();
+                        self.lowpass = Some(lowpass_coefficient(cutoff, sr));
+                    } else {
+                        self.lowpass = None;
+                    }
+                }
+                Command::SetAirAbsorption(cutoff) => self.set_air_absorption(cutoff),
+                Command::SetEq(low_db, high_db) => {
+                    self.eq = if low_db == 0.0 && high_db == 0.0 {
+                        None
+                    } else {
+                        let sr = self.input.sample_rate();
+                        Some(ShelvingEq::new(low_db, high_db, sr, self.previous_sample))
+                    };
+                }
+                Command::Crossfade(new_source, duration) => {
+                    let sample_rate = self.input.sample_rate();
+                    let new_source = UniformSourceIterator::new(new_source, 1, sample_rate);
+                    let length = duration.as_secs_f32() * sample_rate as f32;
+                    let old_source = std::mem::replace(&mut self.input, Box::new(Silence));
+                    self.input = Box::new(Crossfade {
+                        old: Some(old_source),
+                        new: Box::new(new_source),
+                        position: 0,
+                        length: length as usize,
+                    });
                 }
             }
-
-            self.bridge.pending_commands.store(false, Ordering::SeqCst);
         }
 
-        if self.paused {
-            self.bweights = self.target_weights; // during pause we can allow the source to jump
-            return Some(Bformat::zero_value());
-        }
+        self.bridge.pending_commands.store(false, Ordering::SeqCst);
+        true
+    }
 
+    /// Move weights and gain towards their targets by one sample
+    #[inline(always)]
+    fn approach_targets(&mut self) {
         // adjusting the weights slowly avoids audio artifacts but prevents very fast position
         // changes
-        self.bweights.approach(&self.target_weights, 0.001);
+        self.bweights
+            .approach(&self.target_weights, self.weight_step);
+
+        if self.gain != self.target_gain {
+            let delta = self.target_gain - self.gain;
+            if delta.abs() <= self.gain_step {
+                self.gain = self.target_gain;
+            } else {
+                self.gain += self.gain_step.copysign(delta);
+            }
+        }
+    }
+
+    /// Next sample of the resampled and filtered input
+    #[inline(always)]
+    fn next_input(&mut self) -> Option<f32> {
+        if self.fade <= 0.0 && self.fade_step < 0.0 {
+            // the fade-out is complete
+            self.bridge.stopped.store(true, Ordering::SeqCst);
+            return None;
+        }
 
         while self.sampling_offset >= 1.0 {
             match self.input.next() {
@@ -194,121 +1084,1246 @@ impl Iterator for Bstream {
             self.sampling_offset -= 1.0;
         }
 
-        let x = self.next_sample * self.sampling_offset
-            + self.previous_sample * (1.0 - self.sampling_offset);
+        let offset = self.sampling_offset as f32;
+        let mut x = self.next_sample * offset + self.previous_sample * (1.0 - offset);
 
-        self.sampling_offset += self.speed;
-        Some(self.bweights.scale(x))
-    }
-}
+        if let Some(highpass) = &mut self.highpass {
+            x = highpass.process(x);
+        }
+
+        if let Some(alpha) = self.lowpass {
+            self.lowpass_state += alpha * (x - self.lowpass_state);
+            x = self.lowpass_state;
+        }
+
+        if let Some(alpha) = self.air_lowpass {
+            self.air_lowpass_state += alpha * (x - self.air_lowpass_state);
+            x = self.air_lowpass_state;
+        }
+
+        if let Some(eq) = &mut self.eq {
+            x = eq.process(x);
+        }
+
+        if let Some(envelope) = &mut self.envelope {
+            match envelope.next() {
+                Some(level) => x *= level,
+                None => {
+                    // the release is complete
+                    self.bridge.stopped.store(true, Ordering::SeqCst);
+                    return None;
+                }
+            }
+        }
+
+        if self.fade_step != 0.0 {
+            x *= self.fade;
+            self.fade = (self.fade + self.fade_step).clamp(0.0, 1.0);
+            if self.fade == 1.0 {
+                self.fade_step = 0.0;
+            }
+        }
+
+        self.sampling_offset += self.speed;
+        if self.speed_ramp > 0.0 {
+            self.speed += (self.target_speed - self.speed) / self.speed_ramp;
+            self.speed_ramp -= 1.0;
+        }
+        Some(x)
+    }
+
+    /// Add the next `block.len()` samples of the stream to `block`
+    ///
+    /// Returns false if the stream ended before the block was filled. Commands from the
+    /// controller are applied once at the start of the block. `scratch` is used as temporary
+    /// storage for the input samples. If a reverb `send` buffer is given, the stream's reverb
+    /// send is added to it.
+    pub(crate) fn mix_block(
+        &mut self,
+        block: &mut BformatBlock,
+        scratch: &mut Vec<f32>,
+        mut send: Option<&mut [f32]>,
+    ) -> bool {
+        if !self.process_commands() {
+            return false;
+        }
+        self.update_attenuation(true);
+
+        if self.paused {
+            self.bweights = self.target_weights; // during pause we can allow the source to jump
+            self.gain = self.target_gain;
+            return true;
+        }
+
+        let n = block.len();
+
+        // leave the beginning of the block silent if the stream has not started yet
+        let mut i = self.delay.min(n as u64) as usize;
+        self.delay -= i as u64;
+
+        // transition sample by sample while weights or gain change...
+        while i < n && (self.bweights != self.target_weights || self.gain != self.target_gain) {
+            self.approach_targets();
+            match self.next_input() {
+                Some(x) => {
+                    let x = x * self.gain;
+                    block.add(i, self.bweights.scale(x));
+                    self.meter.add(x, &self.bridge);
+                    if let Some(send) = send.as_deref_mut() {
+                        send[i] += x * self.reverb_send;
+                    }
+                }
+                None => return false,
+            }
+            i += 1;
+        }
+
+        // ...then process the remaining samples at once
+        scratch.clear();
+        while scratch.len() < n - i {
+            match self.next_input() {
+                Some(x) => scratch.push(x * self.gain),
+                None => break,
+            }
+        }
+        block.add_weighted(i, &self.bweights, scratch);
+        for &x in scratch.iter() {
+            self.meter.add(x, &self.bridge);
+        }
+        if let Some(send) = send {
+            for (s, x) in send[i..].iter_mut().zip(scratch.iter()) {
+                *s += x * self.reverb_send;
+            }
+        }
+
+        i + scratch.len() == n
+    }
+}
+
+impl Iterator for Bstream {
+    type Item = Bformat;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.process_commands() {
+            return None;
+        }
+        self.update_attenuation(false);
+
+        if self.paused {
+            self.bweights = self.target_weights; // during pause we can allow the source to jump
+            self.gain = self.target_gain;
+            return Some(Bformat::zero_value());
+        }
+
+        if self.delay > 0 {
+            self.delay -= 1;
+            return Some(Bformat::zero_value());
+        }
+
+        self.approach_targets();
+
+        let x = self.next_input()? * self.gain;
+        self.meter.add(x, &self.bridge);
+        Some(self.bweights.scale(x))
+    }
+}
+
+/// Measures the level of a stream over consecutive windows
+struct Meter {
+    window: usize,
+    count: usize,
+    peak: f32,
+    sum_squares: f32,
+}
+
+impl Meter {
+    fn new(window: usize) -> Self {
+        Meter {
+            window,
+            count: 0,
+            peak: 0.0,
+            sum_squares: 0.0,
+        }
+    }
+
+    /// Add a sample, and publish the levels to the controller when the window is complete
+    #[inline(always)]
+    fn add(&mut self, x: f32, bridge: &BstreamBridge) {
+        self.peak = self.peak.max(x.abs());
+        self.sum_squares += x * x;
+        self.count += 1;
+
+        if self.count == self.window {
+            let rms = (self.sum_squares / self.window as f32).sqrt();
+            bridge
+                .peak_level
+                .store(self.peak.to_bits(), Ordering::Relaxed);
+            bridge.rms_level.store(rms.to_bits(), Ordering::Relaxed);
+            self.count = 0;
+            self.peak = 0.0;
+            self.sum_squares = 0.0;
+        }
+    }
+}
+
+enum Command {
+    SetWeights(Bweights),
+    SetTarget(Bweights),
+    SetSpeed(f64),
+    RampSpeed(f64, Duration),
+    SetGain(f32),
+    SetTargetGain(f32),
+    Stop,
+    FadeOut(Duration),
+    NoteOff,
+    Pause,
+    Resume,
+    Crossfade(Box<dyn Source<Item = f32> + Send>, Duration),
+    SetOcclusion(f32),
+    SetAirAbsorption(Option<f32>),
+    SetEq(f32, f32),
+}
+
+/// cutoff frequency (in Hz) of the occlusion filter for barely occluded sources
+const OCCLUSION_MAX_CUTOFF: f32 = 20000.0;
+
+/// cutoff frequency (in Hz) of the occlusion filter for fully occluded sources
+const OCCLUSION_MIN_CUTOFF: f32 = 200.0;
+
+/// product of distance (in m) and cutoff frequency (in Hz) of the air absorption filter
+const AIR_ABSORPTION_SCALE: f32 = 200_000.0;
+
+/// cutoff frequency (in Hz) above which the air absorption filter is bypassed
+const AIR_ABSORPTION_MAX_CUTOFF: f32 = 20000.0;
+
+/// lowest cutoff frequency (in Hz) of the air absorption filter, reached at 1 km
+const AIR_ABSORPTION_MIN_CUTOFF: f32 = 200.0;
+
+/// Coefficient of a one-pole low-pass filter with the given cutoff frequency (in Hz)
+fn lowpass_coefficient(cutoff: f32, sample_rate: u32) -> f32 {
+    1.0 - (-2.0 * std::f32::consts::PI * cutoff / sample_rate as f32).exp()
+}
+
+/// corner frequency (in Hz) of the low-shelf filter of `SoundController::set_eq`
+const EQ_LOW_CORNER: f32 = 250.0;
+
+/// corner frequency (in Hz) of the high-shelf filter of `SoundController::set_eq`
+const EQ_HIGH_CORNER: f32 = 4000.0;
+
+/// First-order low-shelf and high-shelf filters
+struct ShelvingEq {
+    low_gain: f32,
+    high_gain: f32,
+    low_alpha: f32,
+    high_alpha: f32,
+    low_state: f32,
+    high_state: f32,
+}
+
+impl ShelvingEq {
+    fn new(low_gain_db: f32, high_gain_db: f32, sample_rate: u32, x: f32) -> Self {
+        let alpha = |fc: f32| 1.0 - (-2.0 * std::f32::consts::PI * fc / sample_rate as f32).exp();
+        ShelvingEq {
+            low_gain: 10f32.powf(low_gain_db / 20.0),
+            high_gain: 10f32.powf(high_gain_db / 20.0),
+            low_alpha: alpha(EQ_LOW_CORNER),
+            high_alpha: alpha(EQ_HIGH_CORNER),
+            low_state: x,
+            high_state: x,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, x: f32) -> f32 {
+        self.low_state += self.low_alpha * (x - self.low_state);
+        self.high_state += self.high_alpha * (x - self.high_state);
+        let low = self.low_state;
+        let high = x - self.high_state;
+        x + (self.low_gain - 1.0) * low + (self.high_gain - 1.0) * high
+    }
+}
+
+/// First-order high-pass filter
+struct Highpass {
+    coeff: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl Highpass {
+    fn new(cutoff: f32, sample_rate: u32, x: f32) -> Self {
+        Highpass {
+            coeff: (-2.0 * std::f32::consts::PI * cutoff / sample_rate as f32).exp(),
+            previous_input: x,
+            previous_output: 0.0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, x: f32) -> f32 {
+        let y = self.coeff * (self.previous_output + x - self.previous_input);
+        self.previous_input = x;
+        self.previous_output = y;
+        y
+    }
+}
+
+/// Mixes from an old to a new source with constant power
+struct Crossfade {
+    old: Option<Box<dyn Source<Item = f32> + Send>>,
+    new: Box<dyn Source<Item = f32> + Send>,
+    position: usize,
+    length: usize,
+}
+
+impl Iterator for Crossfade {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.length {
+            self.old = None;
+            return self.new.next();
+        }
+
+        let t = self.position as f32 / self.length as f32 * std::f32::consts::FRAC_PI_2;
+        self.position += 1;
+
+        // an old source that ended before the crossfade is over contributes silence
+        let old = match self.old.as_mut().and_then(|old| old.next()) {
+            Some(x) => x,
+            None => {
+                self.old = None;
+                0.0
+            }
+        };
+        let new = self.new.next()?;
+
+        Some(old * t.cos() + new * t.sin())
+    }
+}
+
+impl Source for Crossfade {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.new.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Source with a constant gain that brings its initial RMS level to a target
+///
+/// The samples used to measure the level are buffered and played before the rest of the input.
+struct Normalized<I> {
+    lookahead: std::vec::IntoIter<f32>,
+    input: I,
+    gain: f32,
+}
+
+impl<I: Source<Item = f32>> Normalized<I> {
+    fn new(mut input: I, target: f32) -> Self {
+        let n = (NORMALIZATION_LOOKAHEAD * input.sample_rate() as f32) as usize;
+        let lookahead: Vec<f32> = input.by_ref().take(n).collect();
+
+        let sum_squares: f32 = lookahead.iter().map(|x| x * x).sum();
+        let rms = (sum_squares / lookahead.len().max(1) as f32).sqrt();
+        let gain = if rms > 0.0 {
+            (target / rms).min(MAX_NORMALIZATION_GAIN)
+        } else {
+            1.0
+        };
+
+        Normalized {
+            lookahead: lookahead.into_iter(),
+            input,
+            gain,
+        }
+    }
+}
+
+impl<I: Source<Item = f32>> Iterator for Normalized<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = match self.lookahead.next() {
+            Some(x) => x,
+            None => self.input.next()?,
+        };
+        Some(x * self.gain)
+    }
+}
+
+impl<I: Source<Item = f32>> Source for Normalized<I> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+/// duration (in seconds) over which `BstreamConfig::with_target_rms` measures a source's level
+const NORMALIZATION_LOOKAHEAD: f32 = 0.5;
+
+/// highest gain that `BstreamConfig::with_target_rms` applies
+const MAX_NORMALIZATION_GAIN: f32 = 100.0;
+
+/// Placeholder source while the input of a stream is replaced
+struct Silence;
+
+impl Iterator for Silence {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        None
+    }
+}
+
+impl Source for Silence {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        1
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Lightweight identifier of a sound source
+///
+/// Every source gets a unique id when it is created (see `SoundController::id`). Ids are used
+/// to address many sources at once, e.g. with `BmixerComposer::update_positions`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceId(u64);
+
+impl SourceId {
+    fn next() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        SourceId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// State of a sound source at one point in time
+///
+/// Obtained with `BmixerComposer::snapshot`, mainly for debugging and testing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSnapshot {
+    /// Identifier of the source
+    pub id: SourceId,
+    /// Position relative to the listener
+    pub position: [f32; 3],
+    /// Position in world coordinates, if the source has one
+    pub world_position: Option<[f32; 3]>,
+    /// Velocity of the source
+    pub velocity: [f32; 3],
+    /// Direction the source is facing
+    pub facing: [f32; 3],
+    /// Gain last set on the controller
+    pub gain: f32,
+    /// Whether the source has finished playing
+    pub finished: bool,
+}
+
+/// Bridges a Bstream and its controller across threads
+pub struct BstreamBridge {
+    commands: Mutex<Vec<Command>>,
+    pending_commands: AtomicBool,
+    stopped: AtomicBool,
+    // levels of the most recent meter window, stored as `f32` bits
+    peak_level: AtomicU32,
+    rms_level: AtomicU32,
+    // distance for the custom attenuation curve, stored as `f32` bits; NaN until the source is
+    // placed
+    distance: AtomicU32,
+}
+
+impl BstreamBridge {
+    fn set_distance(&self, distance: f32) {
+        self.distance.store(distance.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Controls playback and position of a spatial audio source
+///
+/// Cloning a controller is cheap and yields another handle to the same source, so several
+/// parts of a program can control one source. All handles share the source's state: a
+/// position set through one handle is returned by `position` on every other handle, and the
+/// most recent change wins regardless of which handle made it.
+pub struct SoundController {
+    shared: Arc<SoundShared>,
+    stop_on_drop: bool,
+}
+
+/// State of a `SoundController` that is shared with the mixer
+pub(crate) struct SoundShared {
+    id: SourceId,
+    bridge: Arc<BstreamBridge>,
+    state: Mutex<SourceState>,
+    // number of `SoundController` handles to the source
+    controllers: AtomicUsize,
+    // set by `SoundController::detach`; the source is no longer stopped on drop
+    detached: AtomicBool,
+}
+
+struct SourceState {
+    position: [f32; 3],
+    world_position: Option<[f32; 3]>,
+    listener_position: [f32; 3],
+    listener_velocity: [f32; 3],
+    // orientation of the listener's head, `None` for the default orientation
+    listener_rotation: Option<Rotation>,
+    listener_focus: Option<ListenerFocus>,
+    velocity: [f32; 3],
+    doppler: bool,
+    doppler_factor: f32,
+    // scales the doppler effect of all sources in the scene, see `SceneConstants`
+    scene_doppler_factor: f32,
+    speed_of_sound: f32,
+    attenuation: Attenuation,
+    directivity: Directivity,
+    facing: [f32; 3],
+    order: AmbisonicOrder,
+    direct: bool,
+    gain: f32,
+    // playback speed factor set by the user, on top of the doppler effect
+    speed: f32,
+    min_distance: f32,
+    // angular spread of the source, in degrees
+    spread: f32,
+    air_absorption: bool,
+    // grid spacing of the encoded direction, in degrees; 0 disables the quantization
+    direction_quantization: f32,
+}
+
+impl SourceState {
+    /// compute weights of a source at given position
+    fn position_weights(&self, pos: [f32; 3]) -> Bweights {
+        if self.direct {
+            return Bweights::omni_source();
+        }
+        let dist = (pos[0] * pos[0] + pos[1] * pos[1] + pos[2] * pos[2]).sqrt();
+        let gain = self.attenuation.placement_gain(dist.max(self.min_distance))
+            * self.directivity.gain(self.facing, pos)
+            * self.focus_gain(pos);
+        let dir = if self.direction_quantization > 0.0 {
+            quantize_direction(pos, self.direction_quantization)
+        } else {
+            pos
+        };
+        let weights = Bweights::from_direction_with_order(dir, gain, self.order);
+        let weights = if self.spread > 0.0 {
+            // average of the spherical harmonics over a cap with the spread as opening angle
+            let c = (self.spread / 2.0).to_radians().cos();
+            weights.scale_orders((1.0 + c) / 2.0, c * (1.0 + c) / 2.0)
+        } else {
+            weights
+        };
+        if dist < self.min_distance {
+            weights.scale_directional(dist / self.min_distance)
+        } else {
+            weights
+        }
+    }
+
+    /// distance at which a source at given position is attenuated
+    fn attenuation_distance(&self, pos: [f32; 3]) -> f32 {
+        let dist = (pos[0] * pos[0] + pos[1] * pos[1] + pos[2] * pos[2]).sqrt();
+        dist.max(self.min_distance)
+    }
+
+    /// gain of the listener focus for a source at given position
+    fn focus_gain(&self, pos: [f32; 3]) -> f32 {
+        match (&self.listener_focus, &self.listener_rotation) {
+            (None, _) => 1.0,
+            (Some(focus), Some(rot)) => focus.gain(rot.apply(pos)),
+            (Some(focus), None) => focus.gain(pos),
+        }
+    }
+
+    /// cutoff frequency of the air absorption filter for a source at given position, `None` if
+    /// the filter is bypassed
+    fn air_absorption_cutoff(&self, pos: [f32; 3]) -> Option<f32> {
+        if !self.air_absorption || self.direct {
+            return None;
+        }
+        let dist = (pos[0] * pos[0] + pos[1] * pos[1] + pos[2] * pos[2]).sqrt();
+        let cutoff = AIR_ABSORPTION_SCALE / dist.max(self.min_distance);
+        if cutoff >= AIR_ABSORPTION_MAX_CUTOFF {
+            None
+        } else {
+            Some(cutoff.max(AIR_ABSORPTION_MIN_CUTOFF))
+        }
+    }
+
+    /// compute rate at which the input is played, including the doppler effect
+    fn playback_rate(&self) -> f64 {
+        if !self.doppler || self.direct {
+            return self.speed as f64;
+        }
+        self.speed as f64
+            * compute_doppler_rate(
+                self.position,
+                relative_velocity(self.velocity, self.listener_velocity),
+                self.doppler_factor * self.scene_doppler_factor,
+                self.speed_of_sound,
+            )
+    }
+
+    /// position relative to the listener of a source at given world position
+    fn relative_position(&self, world_pos: [f32; 3]) -> [f32; 3] {
+        [
+            world_pos[0] - self.listener_position[0],
+            world_pos[1] - self.listener_position[1],
+            world_pos[2] - self.listener_position[2],
+        ]
+    }
+}
+
+impl SoundShared {
+    fn send_command(&self, cmd: Command) {
+        lock(&self.bridge.commands).push(cmd);
+        self.bridge.pending_commands.store(true, Ordering::SeqCst);
+    }
+
+    /// move the source to a new position relative to the listener
+    fn move_to(&self, state: &mut SourceState, pos: [f32; 3], immediate: bool) {
+        state.position = pos;
+        self.bridge.set_distance(state.attenuation_distance(pos));
+        let weights = state.position_weights(pos);
+        let rate = state.playback_rate();
+        {
+            let mut cmds = lock(&self.bridge.commands);
+            cmds.push(Command::SetSpeed(rate));
+            if state.air_absorption {
+                cmds.push(Command::SetAirAbsorption(state.air_absorption_cutoff(pos)));
+            }
+            if immediate {
+                cmds.push(Command::SetWeights(weights));
+            }
+            cmds.push(Command::SetTarget(weights));
+        }
+        self.bridge.pending_commands.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether the stream has stopped playing
+    pub(crate) fn is_finished(&self) -> bool {
+        self.bridge.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Current state of the source
+    pub(crate) fn snapshot(&self) -> SourceSnapshot {
+        let state = lock(&self.state);
+        SourceSnapshot {
+            id: self.id,
+            position: state.position,
+            world_position: state.world_position,
+            velocity: state.velocity,
+            facing: state.facing,
+            gain: state.gain,
+            finished: self.is_finished(),
+        }
+    }
+
+    /// Set a new position relative to the listener without notifying the stream
+    ///
+    /// Returns the weights, playback rate and air absorption cutoff the stream needs to
+    /// transition to. Earlier moves that the stream has not received yet are discarded, so
+    /// that they do not override the new position.
+    pub(crate) fn prepare_move(&self, pos: [f32; 3]) -> (Bweights, f64, Option<f32>) {
+        self.prepare_move_with(|_| pos)
+    }
+
+    /// Like `prepare_move`, but computes the new position from the current one
+    pub(crate) fn prepare_move_with(
+        &self,
+        f: impl FnOnce([f32; 3]) -> [f32; 3],
+    ) -> (Bweights, f64, Option<f32>) {
+        let mut state = lock(&self.state);
+        let pos = f(state.position);
+        state.world_position = None;
+        state.position = pos;
+        self.bridge.set_distance(state.attenuation_distance(pos));
+        lock(&self.bridge.commands).retain(|cmd| {
+            !matches!(
+                cmd,
+                Command::SetWeights(_)
+                    | Command::SetTarget(_)
+                    | Command::SetSpeed(_)
+                    | Command::SetAirAbsorption(_)
+            )
+        });
+        (
+            state.position_weights(pos),
+            state.playback_rate(),
+            state.air_absorption_cutoff(pos),
+        )
+    }
+
+    /// Update the listener velocity, and adjust the doppler effect to it.
+    pub(crate) fn set_listener_velocity(&self, listener: [f32; 3]) {
+        let rate = {
+            let mut state = lock(&self.state);
+            state.listener_velocity = listener;
+            state.playback_rate()
+        };
+        self.send_command(Command::SetSpeed(rate));
+    }
+
+    /// Update the orientation of the listener's head, and the focus gain that depends on it
+    pub(crate) fn set_listener_rotation(&self, rot: Rotation) {
+        let mut state = lock(&self.state);
+        state.listener_rotation = Some(rot);
+        if state.listener_focus.is_some() {
+            let pos = state.position;
+            self.move_to(&mut state, pos, false);
+        }
+    }
+
+    /// Update the listener position, and adjust the source if it has a fixed world position.
+    pub(crate) fn set_listener_position(&self, listener: [f32; 3]) {
+        let mut state = lock(&self.state);
+        state.listener_position = listener;
+        if let Some(world_pos) = state.world_position {
+            let pos = state.relative_position(world_pos);
+            self.move_to(&mut state, pos, false);
+        }
+    }
+}
+
+impl SoundController {
+    /// Set source position relative to listener
+    ///
+    /// The source jumps to the new position without any smoothing. Abruptly changing the
+    /// position of a sound source may cause popping artifacts. Use this function only to set
+    /// the source's initial position or to deliberately snap it to a new place, and dynamically
+    /// adjust the position with `adjust_position`.
+    pub fn set_position(&mut self, pos: [f32; 3]) {
+        let mut state = lock(&self.shared.state);
+        state.world_position = None;
+        self.shared.move_to(&mut state, pos, true);
+    }
+
+    /// Set source position relative to listener in spherical coordinates
+    ///
+    /// Angles are in degrees; see the `math` module for their conventions. Like `set_position`,
+    /// the source jumps to the new position.
+    pub fn set_position_spherical(&mut self, azimuth: f32, elevation: f32, distance: f32) {
+        self.set_position(math::spherical_to_cartesian(azimuth, elevation, distance))
+    }
+
+    /// Set source position relative to listener, bypassing any smoothing
+    ///
+    /// Same as `set_position`; the name makes the intent explicit at the call site.
+    pub fn set_position_immediate(&mut self, pos: [f32; 3]) {
+        self.set_position(pos)
+    }
+
+    /// Adjust source position relative to listener
+    ///
+    /// The source transitions smoothly from its current to the new position over the time
+    /// configured with `BstreamConfig::with_position_smoothing`. Use this function to
+    /// dynamically change the position of a sound source while it is playing.
+    pub fn adjust_position(&mut self, pos: [f32; 3]) {
+        let mut state = lock(&self.shared.state);
+        state.world_position = None;
+        self.shared.move_to(&mut state, pos, false);
+    }
+
+    /// Set source position in world coordinates
+    ///
+    /// Like `set_position`, but the position is fixed in the world rather than relative to the
+    /// listener. The source's position relative to the listener is updated whenever the
+    /// listener moves.
+    pub fn set_world_position(&mut self, pos: [f32; 3]) {
+        let mut state = lock(&self.shared.state);
+        state.world_position = Some(pos);
+        let pos = state.relative_position(pos);
+        self.shared.move_to(&mut state, pos, true);
+    }
+
+    /// Adjust source position in world coordinates
+    ///
+    /// Like `adjust_position`, but the position is fixed in the world rather than relative to
+    /// the listener.
+    pub fn adjust_world_position(&mut self, pos: [f32; 3]) {
+        let mut state = lock(&self.shared.state);
+        state.world_position = Some(pos);
+        let pos = state.relative_position(pos);
+        self.shared.move_to(&mut state, pos, false);
+    }
+
+    /// Current source position relative to listener
+    ///
+    /// This is the position most recently set with `set_position` or `adjust_position`. While
+    /// the source transitions smoothly to a new position, the target position is returned.
+    pub fn position(&self) -> [f32; 3] {
+        lock(&self.shared.state).position
+    }
+
+    /// Direction and distance of the source as the listener hears it
+    ///
+    /// Returns azimuth and elevation (in degrees, see the `math` module) and distance in the
+    /// frame of the listener's head, taking the listener's position and orientation into
+    /// account. Sources closer than their minimum distance are reported at the minimum distance,
+    /// like they are attenuated.
+    pub fn listener_relative_direction(&self) -> (f32, f32, f32) {
+        let state = lock(&self.shared.state);
+        let pos = match &state.listener_rotation {
+            Some(rot) => rot.apply(state.position),
+            None => state.position,
+        };
+        let (azimuth, elevation, distance) = math::cartesian_to_spherical(pos);
+        (azimuth, elevation, distance.max(state.min_distance))
+    }
+
+    /// Current source position in world coordinates
+    ///
+    /// Returns `None` if the source's position was set relative to the listener.
+    pub fn world_position(&self) -> Option<[f32; 3]> {
+        lock(&self.shared.state).world_position
+    }
+
+    /// Current source velocity relative to listener
+    pub fn velocity(&self) -> [f32; 3] {
+        lock(&self.shared.state).velocity
+    }
+
+    /// Set source velocity
+    ///
+    /// The velocity relative to the listener's velocity (see
+    /// `BmixerComposer::set_listener_velocity`) determines how much doppler effect to apply
+    /// but has no effect on the source's position. Use
+    /// `adjust_position` to update the source's position.
+    pub fn set_velocity(&mut self, vel: [f32; 3]) {
+        let rate = {
+            let mut state = lock(&self.shared.state);
+            state.velocity = vel;
+            state.playback_rate()
+        };
+        self.send_command(Command::SetSpeed(rate));
+    }
+
+    /// Change the source velocity linearly over the given duration
+    ///
+    /// Like `set_velocity`, but the doppler shift slides smoothly to its new value instead of
+    /// stepping, as if the source accelerated. The source's position is not affected. Position
+    /// or velocity changes during the transition do not cut it short: the doppler shift slides
+    /// to the new value over the remaining time.
+    pub fn accelerate_to(&self, vel: [f32; 3], duration: Duration) {
+        let rate = {
+            let mut state = lock(&self.shared.state);
+            state.velocity = vel;
+            state.playback_rate()
+        };
+        self.send_command(Command::RampSpeed(rate, duration));
+    }
+
+    /// Set the playback speed of the source (defaults to 1)
+    ///
+    /// The source is resampled by the given factor, which changes both its pitch and its
+    /// duration: at 0.5 it plays at half speed and an octave lower, at 2 twice as fast and an
+    /// octave higher. The speed is combined with the doppler effect. Negative factors are
+    /// clamped to 0, which freezes the source.
+    pub fn set_speed(&self, factor: f32) {
+        let rate = {
+            let mut state = lock(&self.shared.state);
+            state.speed = factor.max(0.0);
+            state.playback_rate()
+        };
+        self.send_command(Command::SetSpeed(rate));
+    }
+
+    /// Stop playback
+    ///
+    /// The source is removed from the scene when the mixer processes its next sample.
+    pub fn stop(&self) {
+        self.send_command(Command::Stop);
+    }
+
+    /// Let the source play to its end without a handle
+    ///
+    /// Consumes the controller. The source is not stopped when this or any other handle to it
+    /// is dropped, even if it was configured with `BstreamConfig::with_stop_on_drop`. Other
+    /// handles can still control the source, and stop it explicitly.
+    pub fn detach(self) {
+        self.shared.detached.store(true, Ordering::SeqCst);
+    }
+
+    /// Fade the source out over the given duration, then stop it
+    ///
+    /// The source is finished (see `is_finished`) once the fade is complete.
+    pub fn fade_out(&self, duration: Duration) {
+        self.send_command(Command::FadeOut(duration));
+    }
+
+    /// Start the release stage of the source's envelope, then stop it
+    ///
+    /// The source is finished (see `is_finished`) once the release is complete. Sources
+    /// without an envelope (see `BstreamConfig::with_envelope`) stop at once.
+    pub fn note_off(&self) {
+        self.send_command(Command::NoteOff);
+    }
+
+    /// Pause playback
+    ///
+    /// A paused source emits silence without affecting other sources in the scene. It keeps its
+    /// position and continues where it left off when resumed.
+    pub fn pause(&self) {
+        self.send_command(Command::Pause);
+    }
+
+    /// Resume playback of a paused source
+    pub fn resume(&self) {
+        self.send_command(Command::Resume);
+    }
+
+    /// Whether the source has finished playing
+    ///
+    /// Becomes true once the source is exhausted, stopped, or otherwise removed from the
+    /// scene, and stays true afterwards. Paused sources are not finished.
+    pub fn is_finished(&self) -> bool {
+        self.shared.is_finished()
+    }
+
+    /// Block the calling thread until the source has finished playing
+    ///
+    /// Only the caller waits; the audio thread keeps running. Never returns for sources that
+    /// play forever, and never returns if the scene is an offline context that no other thread
+    /// renders.
+    pub fn wait(&self) {
+        while !self.is_finished() {
+            std::thread::sleep(WAIT_POLL_INTERVAL);
+        }
+    }
+
+    /// Peak level of the source
+    ///
+    /// The level is the highest absolute sample value within the most recent 50 ms window. It is
+    /// measured after the source's gain and fades but before distance attenuation and encoding,
+    /// and updated whenever a window is complete. Levels are raw values without any decay, so
+    /// meters that should fall back smoothly need to apply their own ballistics. Sources that
+    /// have finished playing report 0.
+    pub fn peak_level(&self) -> f32 {
+        f32::from_bits(self.shared.bridge.peak_level.load(Ordering::Relaxed))
+    }
+
+    /// Root mean square level of the source
+    ///
+    /// Measured over the same 50 ms windows as `peak_level`.
+    pub fn rms_level(&self) -> f32 {
+        f32::from_bits(self.shared.bridge.rms_level.load(Ordering::Relaxed))
+    }
+
+    /// Set gain of the source
+    ///
+    /// The gain scales the source's contribution to the sound scene, independent of the source's
+    /// own amplitude. A gain of 0 silences the source without removing it. Negative gains are
+    /// clamped to 0. The gain changes abruptly, which may cause popping artifacts; use
+    /// `adjust_gain` to change the gain during playback.
+    pub fn set_gain(&self, gain: f32) {
+        lock(&self.shared.state).gain = gain.max(0.0);
+        self.send_command(Command::SetGain(gain.max(0.0)));
+    }
+
+    /// Adjust gain of the source
+    ///
+    /// Like `set_gain`, but the source transitions smoothly to the new gain over a few
+    /// milliseconds.
+    pub fn adjust_gain(&self, gain: f32) {
+        lock(&self.shared.state).gain = gain.max(0.0);
+        self.send_command(Command::SetTargetGain(gain.max(0.0)));
+    }
+
+    /// Gain of the source, as last set with `set_gain` or `adjust_gain`
+    pub fn gain(&self) -> f32 {
+        lock(&self.shared.state).gain
+    }
+
+    /// Set doppler factor
+    pub fn set_doppler_factor(&mut self, factor: f32) {
+        lock(&self.shared.state).doppler_factor = factor;
+    }
+
+    /// Set the direction the source is facing
+    ///
+    /// Only has an audible effect if the source was configured with a directivity pattern
+    /// other than `Directivity::Omni`. The level transitions smoothly to the new value.
+    pub fn set_facing(&mut self, dir: [f32; 3]) {
+        let weights = {
+            let mut state = lock(&self.shared.state);
+            state.facing = dir;
+            state.position_weights(state.position)
+        };
+        self.send_command(Command::SetTarget(weights));
+    }
+
+    /// Set source position relative to listener and the direction the source is facing
+    ///
+    /// Both are updated together, so the stream never renders the new position with the old
+    /// facing direction or vice versa. Like `set_position`, the source jumps to the new
+    /// position.
+    pub fn set_transform(&self, position: [f32; 3], facing: [f32; 3]) {
+        let mut state = lock(&self.shared.state);
+        state.world_position = None;
+        state.facing = facing;
+        self.shared.move_to(&mut state, position, true);
+    }
+
+    /// Spread the source over an angle (in degrees) around its direction
+    ///
+    /// At 0 (the default) the source is a point; larger angles make it sound wider, as if it
+    /// was distributed evenly over a cone with that opening angle. At 360 the source is heard
+    /// from all directions equally. Values outside the range [0, 360] are clamped. The spread
+    /// transitions smoothly to the new value.
+    pub fn set_spread(&self, degrees: f32) {
+        let weights = {
+            let mut state = lock(&self.shared.state);
+            state.spread = degrees.clamp(0.0, 360.0);
+            state.position_weights(state.position)
+        };
+        self.send_command(Command::SetTarget(weights));
+    }
+
+    /// Set how much the source is occluded
+    ///
+    /// Occluded sources sound muffled: a low-pass filter is applied whose cutoff frequency
+    /// drops from 20 kHz for `amount` close to 0 to 200 Hz for `amount == 1`. The filter is
+    /// applied in addition to distance attenuation. An `amount` of 0 (the default) bypasses the
+    /// filter. Values outside the range [0, 1] are clamped.
+    pub fn set_occlusion(&self, amount: f32) {
+        self.send_command(Command::SetOcclusion(amount.clamp(0.0, 1.0)));
+    }
+
+    /// Set the tone of the source
+    ///
+    /// Boosts or cuts low frequencies by `low_gain_db` with a low-shelf filter at 250 Hz, and
+    /// high frequencies by `high_gain_db` with a high-shelf filter at 4 kHz. The filters are
+    /// first-order, so the shelves have gentle slopes. Setting both gains to 0 dB (the default)
+    /// bypasses the filters.
+    pub fn set_eq(&self, low_gain_db: f32, high_gain_db: f32) {
+        self.send_command(Command::SetEq(low_gain_db, high_gain_db));
+    }
 
-#[derive(Debug)]
-enum Command {
-    SetWeights(Bweights),
-    SetTarget(Bweights),
-    SetSpeed(f32),
-    Stop,
-    Pause,
-    Resume,
-}
+    /// Replace the audio of the source, crossfading from the old to the new audio
+    ///
+    /// The old audio fades out while the new audio fades in over the given `duration`, keeping
+    /// the source's position and gain. The crossfade keeps the power of uncorrelated signals
+    /// constant. If the old audio ends before the crossfade is over, the new audio simply fades
+    /// in. Has no effect if the source has already finished playing. The new source must have
+    /// exactly one channel; it is resampled to the sample rate of the old source if necessary.
+    pub fn crossfade_to<I>(&self, new_source: I, duration: Duration)
+    where
+        I: Source<Item = f32> + Send + 'static,
+    {
+        assert_eq!(new_source.channels(), 1);
+        self.send_command(Command::Crossfade(Box::new(new_source), duration));
+    }
 
-/// Bridges a Bstream and its controller across threads
-pub struct BstreamBridge {
-    commands: Mutex<Vec<Command>>,
-    pending_commands: AtomicBool,
-    stopped: AtomicBool,
+    /// Identifier of the source
+    pub fn id(&self) -> SourceId {
+        self.shared.id
+    }
+
+    /// State shared with the mixer
+    pub(crate) fn shared(&self) -> &Arc<SoundShared> {
+        &self.shared
+    }
+
+    fn send_command(&self, cmd: Command) {
+        self.shared.send_command(cmd);
+    }
 }
 
-/// Controls playback and position of a spatial audio source
-pub struct SoundController {
-    bridge: Arc<BstreamBridge>,
-    position: [f32; 3],
-    velocity: [f32; 3],
-    doppler_factor: f32,
-    speed_of_sound: f32,
+/// Controls playback and position of a stereo source that is split into two spatial sources
+#[derive(Clone)]
+pub struct StereoSoundController {
+    left: SoundController,
+    right: SoundController,
 }
 
-impl SoundController {
-    /// Set source position relative to listener
-    ///
-    /// Abruptly changing the position of a sound source may cause
-    /// popping artifacts. Use this function only to set the source's
-    /// initial position, and dynamically adjust the position with
-    /// `adjust_position`.
-    pub fn set_position(&mut self, pos: [f32; 3]) {
-        self.position = pos;
-        let weights = Bweights::from_position(pos);
-        let rate = self.doppler_rate();
-        {
-            let mut cmds = self.bridge.commands.lock().unwrap();
-            cmds.push(Command::SetSpeed(rate));
-            cmds.push(Command::SetWeights(weights));
-            cmds.push(Command::SetTarget(weights));
-        }
-        self.bridge.pending_commands.store(true, Ordering::SeqCst);
+impl StereoSoundController {
+    pub(crate) fn new(left: SoundController, right: SoundController) -> Self {
+        StereoSoundController { left, right }
     }
-    /// Adjust source position relative to listener
+
+    /// Controller of the left channel
+    pub fn left(&mut self) -> &mut SoundController {
+        &mut self.left
+    }
+
+    /// Controller of the right channel
+    pub fn right(&mut self) -> &mut SoundController {
+        &mut self.right
+    }
+
+    /// Set positions of both channels relative to listener
     ///
-    /// The source transitions smoothly to the new position.
-    /// Use this function to dynamically change the position of a
-    /// sound source while it is playing.
-    pub fn adjust_position(&mut self, pos: [f32; 3]) {
-        self.position = pos;
-        let weights = Bweights::from_position(pos);
-        let rate = self.doppler_rate();
-        {
-            let mut cmds = self.bridge.commands.lock().unwrap();
-            cmds.push(Command::SetSpeed(rate));
-            cmds.push(Command::SetTarget(weights));
-        }
-        self.bridge.pending_commands.store(true, Ordering::SeqCst);
+    /// See `SoundController::set_position`.
+    pub fn set_position(&mut self, left_pos: [f32; 3], right_pos: [f32; 3]) {
+        self.left.set_position(left_pos);
+        self.right.set_position(right_pos);
     }
 
-    /// Set source velocity relative to listener
+    /// Adjust positions of both channels relative to listener
     ///
-    /// The velocity determines how much doppler effect to apply
-    /// but has no effect on the source's position. Use
-    /// `adjust_position` to update the source's position.
+    /// See `SoundController::adjust_position`.
+    pub fn adjust_position(&mut self, left_pos: [f32; 3], right_pos: [f32; 3]) {
+        self.left.adjust_position(left_pos);
+        self.right.adjust_position(right_pos);
+    }
+
+    /// Set velocity of both channels relative to listener
     pub fn set_velocity(&mut self, vel: [f32; 3]) {
-        self.velocity = vel;
-        let rate = self.doppler_rate();
-        self.send_command(Command::SetSpeed(rate));
+        self.left.set_velocity(vel);
+        self.right.set_velocity(vel);
+    }
+
+    /// Change the velocity of both channels smoothly (see `SoundController::accelerate_to`)
+    pub fn accelerate_to(&self, vel: [f32; 3], duration: Duration) {
+        self.left.accelerate_to(vel, duration);
+        self.right.accelerate_to(vel, duration);
     }
 
     /// Stop playback
     pub fn stop(&self) {
-        self.send_command(Command::Stop);
+        self.left.stop();
+        self.right.stop();
+    }
+
+    /// Set the playback speed of both channels (see `SoundController::set_speed`)
+    pub fn set_speed(&self, factor: f32) {
+        self.left.set_speed(factor);
+        self.right.set_speed(factor);
+    }
+
+    /// Let both channels play to their end without a handle (see `SoundController::detach`)
+    pub fn detach(self) {
+        self.left.detach();
+        self.right.detach();
+    }
+
+    /// Release the envelopes of both channels (see `SoundController::note_off`)
+    pub fn note_off(&self) {
+        self.left.note_off();
+        self.right.note_off();
+    }
+
+    /// Fade both channels out over the given duration, then stop them
+    pub fn fade_out(&self, duration: Duration) {
+        self.left.fade_out(duration);
+        self.right.fade_out(duration);
     }
 
     /// Pause playback
     pub fn pause(&self) {
-        self.send_command(Command::Pause);
+        self.left.pause();
+        self.right.pause();
     }
 
     /// Resume playback
     pub fn resume(&self) {
-        self.send_command(Command::Resume);
+        self.left.resume();
+        self.right.resume();
     }
 
-    /// Set doppler factor
-    pub fn set_doppler_factor(&mut self, factor: f32) {
-        self.doppler_factor = factor;
+    /// Peak level of the louder channel (see `SoundController::peak_level`)
+    pub fn peak_level(&self) -> f32 {
+        self.left.peak_level().max(self.right.peak_level())
     }
 
-    fn send_command(&self, cmd: Command) {
-        self.bridge.commands.lock().unwrap().push(cmd);
-        self.bridge.pending_commands.store(true, Ordering::SeqCst);
+    /// Root mean square level of the louder channel (see `SoundController::rms_level`)
+    pub fn rms_level(&self) -> f32 {
+        self.left.rms_level().max(self.right.rms_level())
     }
 
-    /// compute doppler rate
-    fn doppler_rate(&self) -> f32 {
-        compute_doppler_rate(
-            self.position,
-            self.velocity,
-            self.doppler_factor,
-            self.speed_of_sound,
-        )
+    /// Whether both channels have finished playing
+    pub fn is_finished(&self) -> bool {
+        self.left.is_finished() && self.right.is_finished()
+    }
+
+    /// Block the calling thread until both channels have finished playing (see
+    /// `SoundController::wait`)
+    pub fn wait(&self) {
+        self.left.wait();
+        self.right.wait();
+    }
+
+    /// Set gain of both channels
+    pub fn set_gain(&self, gain: f32) {
+        self.left.set_gain(gain);
+        self.right.set_gain(gain);
+    }
+
+    /// Adjust gain of both channels
+    pub fn adjust_gain(&self, gain: f32) {
+        self.left.adjust_gain(gain);
+        self.right.adjust_gain(gain);
+    }
+}
+
+impl Clone for SoundController {
+    fn clone(&self) -> Self {
+        self.shared.controllers.fetch_add(1, Ordering::SeqCst);
+        SoundController {
+            shared: self.shared.clone(),
+            stop_on_drop: self.stop_on_drop,
+        }
+    }
+}
+
+impl Drop for SoundController {
+    fn drop(&mut self) {
+        let last = self.shared.controllers.fetch_sub(1, Ordering::SeqCst) == 1;
+        if self.stop_on_drop && last && !self.shared.detached.load(Ordering::SeqCst) {
+            self.stop();
+        }
+    }
+}
+
+/// unit vector in the direction of `pos`, with azimuth and elevation rounded to multiples of
+/// `step` (in degrees)
+fn quantize_direction(pos: [f32; 3], step: f32) -> [f32; 3] {
+    let (azimuth, elevation, distance) = math::cartesian_to_spherical(pos);
+    if distance == 0.0 {
+        return pos;
     }
+    let round = |angle: f32| (angle / step).round() * step;
+    math::spherical_to_cartesian(round(azimuth), round(elevation).clamp(-90.0, 90.0), 1.0)
+}
+
+/// velocity of a source relative to the listener
+fn relative_velocity(source: [f32; 3], listener: [f32; 3]) -> [f32; 3] {
+    [
+        source[0] - listener[0],
+        source[1] - listener[1],
+        source[2] - listener[2],
+    ]
 }
 
 /// compute doppler rate
@@ -317,31 +2332,39 @@ fn compute_doppler_rate(
     velocity: [f32; 3],
     doppler_factor: f32,
     speed_of_sound: f32,
-) -> f32 {
+) -> f64 {
+    let position = position.map(f64::from);
+    let velocity = velocity.map(f64::from);
     let dist =
         (position[0] * position[0] + position[1] * position[1] + position[2] * position[2]).sqrt();
 
-    let relative_velocity;
-
-    if dist.abs() < EPS {
-        relative_velocity =
-            (velocity[0] * velocity[0] + velocity[1] * velocity[1] + velocity[2] * velocity[2])
-                .sqrt();
+    let relative_velocity = if dist.abs() < EPS as f64 {
+        (velocity[0] * velocity[0] + velocity[1] * velocity[1] + velocity[2] * velocity[2]).sqrt()
     } else {
-        relative_velocity =
-            (position[0] * velocity[0] + position[1] * velocity[1] + position[2] * velocity[2])
-                / dist;
-    }
+        (position[0] * velocity[0] + position[1] * velocity[1] + position[2] * velocity[2]) / dist
+    };
 
-    speed_of_sound / (speed_of_sound + doppler_factor * relative_velocity)
+    let speed_of_sound = speed_of_sound as f64;
+    speed_of_sound / (speed_of_sound + doppler_factor as f64 * relative_velocity)
 }
 
 const EPS: f32 = 1e-6;
 
+/// time (in seconds) it takes `adjust_gain` to ramp from 0 to 1
+const GAIN_RAMP_DURATION: f32 = 0.005;
+
+/// time between two checks of `SoundController::wait` whether the source has finished
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// length (in seconds) of the window over which `SoundController::peak_level` and
+/// `SoundController::rms_level` are measured
+const METER_WINDOW: f32 = 0.05;
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::sources::Ramp;
+    use crate::sources::{Constant, Ramp, SineSweep};
+    use rodio::buffer::SamplesBuffer;
 
     #[test]
     fn no_doppler_effect_if_velocity_is_zero() {
@@ -360,7 +2383,7 @@ mod tests {
 
         let rate = compute_doppler_rate(position, velocity, 1.0, 1.0);
 
-        assert_eq!(rate, 1.0 / (1.0 + f32::sqrt(3.0)));
+        assert_eq!(rate, 1.0 / (1.0 + f64::sqrt(3.0)));
     }
 
     #[test]
@@ -378,6 +2401,174 @@ mod tests {
         assert_eq!(stream.next(), Some(3.0));
     }
 
+    #[test]
+    fn cloned_controllers_share_the_source() {
+        let (mut stream, controller) = bstream(
+            Ramp::new(1),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_stop_on_drop(true),
+        );
+
+        let mut clone = controller.clone();
+        clone.set_position([0.0, 3.0, 0.0]);
+        assert_eq!(controller.position(), [0.0, 3.0, 0.0]);
+
+        // the stream keeps playing until the last handle is dropped
+        drop(clone);
+        assert!(stream.next().is_some());
+        drop(controller);
+        assert!(stream.next().is_none());
+    }
+
+    #[test]
+    fn sources_at_the_listener_position_are_omnidirectional() {
+        for order in &[AmbisonicOrder::First, AmbisonicOrder::Second] {
+            let (mut stream, _controller) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([0.0, 0.0, 0.0])
+                    .with_order(*order),
+            );
+
+            let sample = stream.nth(10).unwrap();
+            let [w, x, y, z] = sample.components();
+            assert!(w.is_finite() && w > 0.0);
+            assert_eq!([x, y, z], [0.0, 0.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn directions_fade_out_within_min_distance() {
+        let x_gain = |pos| {
+            let (mut stream, _controller) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position(pos)
+                    .with_attenuation(AttenuationModel::None)
+                    .with_min_distance(0.5),
+            );
+            stream.nth(10).unwrap().components()[1]
+        };
+
+        assert!((x_gain([1.0, 0.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!((x_gain([0.25, 0.0, 0.0]) - 0.5).abs() < 1e-6);
+        assert!((x_gain([1e-9, 0.0, 0.0])).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sources_are_finished_when_stopped_or_exhausted() {
+        let (mut stream, controller) = bstream(Constant::new(1.0, 48000), BstreamConfig::new());
+        controller.pause();
+        stream.next();
+        assert!(!controller.is_finished());
+        controller.stop();
+        assert!(!controller.is_finished());
+        stream.next();
+        assert!(controller.is_finished());
+
+        let source = SamplesBuffer::new(1, 48000, vec![1.0; 3]);
+        let (mut stream, controller) = bstream(source, BstreamConfig::new());
+        while stream.next().is_some() {
+            assert!(!controller.is_finished());
+        }
+        assert!(controller.is_finished());
+        stream.next();
+        assert!(controller.is_finished());
+    }
+
+    #[test]
+    fn sources_fade_in_and_out() {
+        let config = BstreamConfig::new().with_fade_in(Duration::from_millis(10));
+        let (mut stream, controller) = bstream(Constant::new(1.0, 1000), config);
+
+        let omni = Bweights::omni_source();
+        let full = omni.dot(omni.scale(1.0));
+        let fade_in: Vec<f32> = (0..12).map(|_| omni.dot(stream.next().unwrap())).collect();
+        assert_eq!(fade_in[0], 0.0);
+        assert!(fade_in.windows(2).all(|w| w[1] >= w[0]));
+        assert_eq!(fade_in[11], full);
+
+        controller.fade_out(Duration::from_millis(10));
+        let fade_out: Vec<f32> = stream.by_ref().map(|s| omni.dot(s)).take(20).collect();
+        assert!(fade_out.len() <= 11);
+        assert!(fade_out.windows(2).all(|w| w[1] < w[0]));
+        assert!(*fade_out.last().unwrap() <= 0.1 * full + 1e-6);
+        assert!(controller.is_finished());
+    }
+
+    #[test]
+    fn envelopes_release_after_note_off() {
+        let envelope = Envelope::new()
+            .with_attack(Duration::from_millis(5))
+            .with_sustain(0.5)
+            .with_release(Duration::from_millis(10));
+        let config = BstreamConfig::new().with_envelope(envelope);
+        let (mut stream, controller) = bstream(Constant::new(1.0, 1000), config);
+
+        let omni = Bweights::omni_source();
+        let full = omni.dot(omni.scale(1.0));
+        let attack: Vec<f32> = (0..20).map(|_| omni.dot(stream.next().unwrap())).collect();
+        assert_eq!(attack[0], 0.0);
+        assert!((attack[5] - full).abs() < 1e-6);
+        assert!((attack[19] - 0.5 * full).abs() < 1e-6);
+
+        controller.note_off();
+        let release: Vec<f32> = stream.by_ref().map(|s| omni.dot(s)).take(20).collect();
+        assert_eq!(release.len(), 10);
+        assert!(release.windows(2).all(|w| w[1] < w[0]));
+        assert!(*release.last().unwrap() <= 0.1 * full);
+        assert!(controller.is_finished());
+    }
+
+    #[test]
+    fn silent_sources_stop_when_faded_out() {
+        let config = BstreamConfig::new().with_fade_in(Duration::from_millis(10));
+        let (mut stream, controller) = bstream(Constant::new(1.0, 1000), config);
+
+        controller.fade_out(Duration::from_millis(10));
+        assert!(stream.next().is_none());
+        assert!(controller.is_finished());
+    }
+
+    #[test]
+    fn meters_report_level_of_source() {
+        let sine = (0..10000)
+            .map(|i| 0.5 * (2.0 * std::f32::consts::PI * i as f32 / 48.0).sin())
+            .collect::<Vec<_>>();
+        let (mut stream, mut controller) =
+            bstream(SamplesBuffer::new(1, 48000, sine), BstreamConfig::new());
+        controller.set_position([0.0, 3.0, 0.0]);
+        controller.set_gain(2.0);
+        assert_eq!(controller.peak_level(), 0.0);
+
+        // two meter windows of 2400 samples
+        for _ in 0..4800 {
+            stream.next();
+        }
+        assert!((controller.peak_level() - 1.0).abs() < 0.01);
+        assert!((controller.rms_level() - 1.0 / 2f32.sqrt()).abs() < 0.01);
+
+        drop(stream);
+        assert_eq!(controller.peak_level(), 0.0);
+    }
+
+    #[test]
+    fn speed_changes_pitch() {
+        let tone = rodio::source::SineWave::new(440);
+        let (stream, controller) = bstream(tone, BstreamConfig::new());
+        controller.set_speed(2.0);
+
+        // count zero crossings of the omnidirectional component in one second
+        let omni = Bweights::omni_source();
+        let output: Vec<f32> = stream.take(48000).map(|s| omni.dot(s)).collect();
+        let crossings = output
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        assert!((crossings as i32 - 2 * 880).abs() <= 2, "{}", crossings);
+    }
+
     #[test]
     fn pausing_a_source_makes_it_emit_zeros() {
         let (mut stream, controller) = bstream(
@@ -419,6 +2610,627 @@ mod tests {
         assert_eq!(stream.next(), Some(3.0));
     }
 
+    #[test]
+    fn zero_gain_silences_a_source() {
+        let (stream, controller) = bstream(
+            Constant::new(1.0, 1000),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+
+        controller.set_gain(0.0);
+
+        assert!(extract_x_component(stream).take(10).all(|x| x == 0.0));
+    }
+
+    #[test]
+    fn adjusting_the_gain_ramps_smoothly() {
+        let (stream, controller) = bstream(
+            Constant::new(1.0, 1000),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+
+        controller.adjust_gain(0.5);
+
+        let output: Vec<_> = extract_x_component(stream).take(10).collect();
+        assert_eq!(output[0], 0.8);
+        assert_eq!(output[1], 0.6);
+        assert_eq!(output[2], 0.5);
+        assert_eq!(output[9], 0.5);
+    }
+
+    #[test]
+    fn doppler_effect_depends_on_speed_of_sound() {
+        let config = BstreamConfig::new()
+            .with_position([1.0, 0.0, 0.0])
+            .with_velocity([100.0, 0.0, 0.0]);
+
+        let (slow, _) = bstream(Ramp::new(1), config.with_speed_of_sound(300.0));
+        let slow: Vec<_> = extract_x_component(slow).take(11).collect();
+        assert!((slow[10] - 7.5).abs() < 1e-4);
+
+        let config = BstreamConfig::new()
+            .with_position([1.0, 0.0, 0.0])
+            .with_velocity([100.0, 0.0, 0.0]);
+
+        let (fast, _) = bstream(Ramp::new(1), config.with_speed_of_sound(1e9));
+        let fast: Vec<_> = extract_x_component(fast).take(11).collect();
+        assert!((fast[10] - 10.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn doppler_effect_depends_on_relative_velocity() {
+        let config = || {
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_velocity([100.0, 0.0, 0.0])
+                .with_speed_of_sound(300.0)
+        };
+
+        // listener and source move together
+        let (stream, _) = bstream(
+            Ramp::new(1),
+            config().with_listener_velocity([100.0, 0.0, 0.0]),
+        );
+        let output: Vec<_> = extract_x_component(stream).take(11).collect();
+        assert!((output[10] - 10.0).abs() < 1e-4);
+
+        // the listener moves away from a resting source
+        let (stream, mut controller) = bstream(Ramp::new(1), config());
+        controller.set_velocity([0.0, 0.0, 0.0]);
+        controller
+            .shared()
+            .set_listener_velocity([-100.0, 0.0, 0.0]);
+        let output: Vec<_> = extract_x_component(stream).take(11).collect();
+        assert!((output[10] - 7.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn doppler_effect_does_not_drift_over_long_playback() {
+        let sample_rate = 48000;
+        let frequency = 100.0;
+        let tone = SineSweep::new(frequency, frequency, Duration::from_secs(62), sample_rate);
+        let (stream, _) = bstream(
+            tone,
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_velocity([10.0, 0.0, 0.0])
+                .with_speed_of_sound(343.0),
+        );
+
+        // one minute into the output, it still follows the analytically resampled tone
+        let rate = 343.0 / 353.0;
+        let start = 60 * sample_rate as usize;
+        let output: Vec<f32> = extract_x_component(stream).skip(start).take(100).collect();
+        assert_eq!(output.len(), 100);
+        for (i, x) in output.iter().enumerate() {
+            let t = (start + i) as f64 / sample_rate as f64;
+            let expected = (2.0 * std::f64::consts::PI * frequency as f64 * rate * t).sin();
+            assert!((*x as f64 - expected).abs() < 1e-4, "{} {}", x, expected);
+        }
+    }
+
+    #[test]
+    fn disabled_doppler_effect_does_not_change_pitch() {
+        let (stream, mut controller) = bstream(
+            Ramp::new(1),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_velocity([100.0, 0.0, 0.0])
+                .with_doppler(false),
+        );
+        controller.set_velocity([-50.0, 0.0, 0.0]);
+
+        let output: Vec<_> = extract_x_component(stream).take(10).collect();
+        assert_eq!(output, (0..10).map(|i| i as f32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn attenuation_models_reduce_level_with_distance() {
+        let level = |model, distance| {
+            let (mut stream, _) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([distance, 0.0, 0.0])
+                    .with_attenuation(model),
+            );
+            Bweights::new(0.0, 1.0, 0.0, 0.0).dot(stream.next().unwrap())
+        };
+
+        let ratio = level(AttenuationModel::InverseDistance, 1.0)
+            / level(AttenuationModel::InverseDistance, 10.0);
+        assert!((ratio - 10.0).abs() < 1e-4);
+
+        let ratio = level(AttenuationModel::InverseSquare, 1.0)
+            / level(AttenuationModel::InverseSquare, 10.0);
+        assert!((ratio - 100.0).abs() < 1e-2);
+
+        let ratio = level(AttenuationModel::None, 1.0) / level(AttenuationModel::None, 10.0);
+        assert_eq!(ratio, 1.0);
+    }
+
+    #[test]
+    fn custom_attenuation_curves_override_the_model() {
+        // full level up to 2 m, then a linear falloff to silence at 10 m
+        let curve = Arc::new(|d: f32| (1.0 - (d - 2.0).max(0.0) / 8.0).max(0.0));
+        let level = |distance| {
+            let (mut stream, _) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([distance, 0.0, 0.0])
+                    .with_attenuation(AttenuationModel::InverseSquare)
+                    .with_attenuation_fn(curve.clone()),
+            );
+            Bweights::new(0.0, 1.0, 0.0, 0.0).dot(stream.next().unwrap())
+        };
+
+        assert_eq!(level(1.0), 1.0);
+        assert_eq!(level(2.0), 1.0);
+        assert_eq!(level(6.0), 0.5);
+        assert_eq!(level(10.0), 0.0);
+        assert_eq!(level(20.0), 0.0);
+    }
+
+    #[test]
+    fn custom_attenuation_curves_are_evaluated_by_the_stream() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let curve = Arc::new(move |d: f32| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            1.0 / d
+        });
+        let (mut stream, mut controller) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_position_smoothing(Duration::from_secs(0))
+                .with_attenuation_fn(curve),
+        );
+        let mut block = BformatBlock::new(16, AmbisonicOrder::First);
+        let mut scratch = Vec::new();
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+
+        // moving the source does not evaluate the curve on the controlling thread
+        let evaluated = calls.load(Ordering::SeqCst);
+        controller.set_position([4.0, 0.0, 0.0]);
+        assert_eq!(calls.load(Ordering::SeqCst), evaluated);
+
+        // each block evaluates the curve at the current distance
+        stream.mix_block(&mut block, &mut scratch, None);
+        assert_eq!(calls.load(Ordering::SeqCst), evaluated + 1);
+        assert!((x.dot(block.get(15)) - 0.25).abs() < 1e-6);
+        block.clear();
+        stream.mix_block(&mut block, &mut scratch, None);
+        assert_eq!(calls.load(Ordering::SeqCst), evaluated + 2);
+    }
+
+    #[test]
+    fn faulty_attenuation_curves_fall_back_to_the_model() {
+        let play = |curve: AttenuationFn| {
+            bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([1.0, 0.0, 0.0])
+                    .with_position_smoothing(Duration::from_secs(0))
+                    .with_attenuation(AttenuationModel::InverseDistance)
+                    .with_reference_distance(1.0)
+                    .with_attenuation_fn(curve),
+            )
+        };
+        let mut block = BformatBlock::new(16, AmbisonicOrder::First);
+        let mut scratch = Vec::new();
+        let x = Bweights::new(0.0, 1.0, 0.0, 0.0);
+
+        // non-finite gains are replaced by the model's
+        let (mut stream, mut controller) =
+            play(Arc::new(|d: f32| if d > 2.0 { f32::NAN } else { 1.0 }));
+        controller.set_position([4.0, 0.0, 0.0]);
+        stream.mix_block(&mut block, &mut scratch, None);
+        assert!((x.dot(block.get(15)) - 0.25).abs() < 1e-6);
+
+        // a curve that panics is not called again
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let (mut stream, mut controller) = play(Arc::new(move |d: f32| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            assert!(d <= 2.0, "attenuation");
+            1.0
+        }));
+        controller.set_position([4.0, 0.0, 0.0]);
+        for _ in 0..3 {
+            block.clear();
+            stream.mix_block(&mut block, &mut scratch, None);
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!((x.dot(block.get(15)) - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn spread_reduces_directional_components() {
+        let (mut stream, controller) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new()
+                .with_position([1.0, 0.0, 0.0])
+                .with_position_smoothing(Duration::from_secs(0)),
+        );
+        let mut directionality = |spread| {
+            controller.set_spread(spread);
+            let s = stream.next().unwrap();
+            Bweights::new(0.0, 1.0, 0.0, 0.0).dot(s) / Bweights::omni_source().dot(s)
+        };
+
+        let ratios: Vec<f32> = [0.0, 90.0, 180.0, 360.0]
+            .iter()
+            .map(|&spread| directionality(spread))
+            .collect();
+        assert!(ratios.windows(2).all(|r| r[1] < r[0]), "{:?}", ratios);
+        assert!((ratios[2] / ratios[0] - 0.5).abs() < 1e-4);
+        assert!(ratios[3].abs() < 1e-4);
+    }
+
+    #[test]
+    fn direction_quantization_hides_jitter() {
+        let encode = |quantization| {
+            let (mut stream, mut controller) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([0.0, 2.0, 0.0])
+                    .with_direction_quantization(quantization),
+            );
+            [
+                (0.0, 0.0),
+                (1.5, -0.5),
+                (-2.0, 1.0),
+                (0.5, 2.0),
+                (-1.0, -1.5),
+            ]
+            .iter()
+            .map(|&(az, el)| {
+                controller.set_position_spherical(30.0 + az, 10.0 + el, 2.0);
+                stream.next().unwrap().components()
+            })
+            .collect::<Vec<_>>()
+        };
+
+        // the distance is the same for all positions, so only the direction changes the gains
+        let max_change = |encoded: Vec<[f32; 4]>| {
+            encoded
+                .iter()
+                .flat_map(|s| s.iter().zip(&encoded[0]).map(|(a, b)| (a - b).abs()))
+                .fold(0.0, f32::max)
+        };
+        assert!(max_change(encode(0.0)) > 1e-2);
+        assert!(max_change(encode(10.0)) < 1e-6);
+
+        let expected = Bweights::from_direction(math::spherical_to_cartesian(30.0, 10.0, 1.0), 0.5);
+        for (a, b) in encode(10.0)[0].iter().zip(&expected.components()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn highpass_removes_dc_offset() {
+        let sr = 48000;
+        let tone = rodio::source::SineWave::new(1000).map(|x| x + 0.5);
+        let tone = SamplesBuffer::new(1, sr, tone.take(sr as usize).collect::<Vec<_>>());
+        let (stream, _) = bstream(tone, BstreamConfig::new().with_highpass(20.0));
+
+        let w: Vec<f32> = stream.map(|s| s.components()[0]).collect();
+        let tail = &w[sr as usize / 2..];
+        let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+        let peak = tail.iter().fold(0.0, |p: f32, x| p.max(x.abs()));
+        assert!(mean.abs() < 1e-3, "mean {}", mean);
+        assert!(peak > 0.6, "peak {}", peak);
+    }
+
+    #[test]
+    fn attenuation_is_limited_by_reference_and_max_distance() {
+        let level = |distance| {
+            let (mut stream, _) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([distance, 0.0, 0.0])
+                    .with_reference_distance(2.0)
+                    .with_max_distance(8.0),
+            );
+            Bweights::new(0.0, 1.0, 0.0, 0.0).dot(stream.next().unwrap())
+        };
+
+        assert_eq!(level(0.5), 1.0);
+        assert_eq!(level(2.0), 1.0);
+        assert_eq!(level(4.0), 0.5);
+        assert_eq!(level(8.0), 0.25);
+        assert_eq!(level(100.0), 0.25);
+    }
+
+    #[test]
+    fn controller_reports_target_position_and_velocity() {
+        let (_, mut controller) = bstream(
+            Ramp::new(1),
+            BstreamConfig::new().with_position([1.0, 0.0, 0.0]),
+        );
+        assert_eq!(controller.position(), [1.0, 0.0, 0.0]);
+        assert_eq!(controller.velocity(), [0.0, 0.0, 0.0]);
+
+        controller.adjust_position([0.0, 2.0, 0.0]);
+        controller.set_velocity([0.0, 1.0, 0.0]);
+        assert_eq!(controller.position(), [0.0, 2.0, 0.0]);
+        assert_eq!(controller.velocity(), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn immediate_position_changes_are_not_smoothed() {
+        let config = BstreamConfig::new()
+            .with_position([-1.0, 0.0, 0.0])
+            .with_position_smoothing(Duration::from_millis(10));
+
+        let (stream, mut controller) = bstream(Constant::new(1.0, 1000), config);
+        controller.set_position_immediate([1.0, 0.0, 0.0]);
+        let immediate: Vec<_> = extract_x_component(stream).take(2).collect();
+        assert_eq!(immediate, vec![1.0, 1.0]);
+
+        let config = BstreamConfig::new()
+            .with_position([-1.0, 0.0, 0.0])
+            .with_position_smoothing(Duration::from_millis(10));
+
+        let (stream, mut controller) = bstream(Constant::new(1.0, 1000), config);
+        controller.adjust_position([1.0, 0.0, 0.0]);
+        let smoothed: Vec<_> = extract_x_component(stream).take(2).collect();
+        assert!(smoothed[0] < smoothed[1] && smoothed[1] < 0.0);
+    }
+
+    #[test]
+    fn position_changes_are_smoothed() {
+        let (stream, mut controller) = bstream(
+            Constant::new(1.0, 1000),
+            BstreamConfig::new()
+                .with_position([-1.0, 0.0, 0.0])
+                .with_position_smoothing(Duration::from_millis(10)),
+        );
+
+        controller.adjust_position([1.0, 0.0, 0.0]);
+
+        let output: Vec<_> = extract_x_component(stream).take(12).collect();
+        assert!(output.windows(2).all(|w| (w[1] - w[0]).abs() < 0.2 + 1e-6));
+        assert!(output[..9].iter().all(|&x| x < 1.0));
+        assert_eq!(output[10], 1.0);
+    }
+
+    #[test]
+    fn cardioid_facing_away_is_quieter() {
+        let pos = [0.0, 2.0, 0.0];
+        let config = |facing| {
+            BstreamConfig::new()
+                .with_position(pos)
+                .with_directivity(Directivity::Cardioid, facing)
+        };
+
+        let (toward, _c1) = bstream(Constant::new(1.0, 1000), config([0.0, -1.0, 0.0]));
+        let (away, _c2) = bstream(Constant::new(1.0, 1000), config([0.0, 1.0, 0.0]));
+        let (side, mut c3) = bstream(Constant::new(1.0, 1000), config([1.0, 0.0, 0.0]));
+
+        assert!(toward.gain() > side.gain());
+        assert!(side.gain() > away.gain());
+        assert!(away.gain() < 1e-6);
+
+        c3.set_facing([0.0, -1.0, 0.0]);
+        let y: Vec<_> = side
+            .map(|bf| Bweights::new(0.0, 0.0, 1.0, 0.0).dot(bf))
+            .take(100)
+            .collect();
+        assert!((y[99] - 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn crossfade_keeps_energy_constant() {
+        // the two signals are orthogonal over every window of four samples
+        let old: Vec<f32> = (0..400).map(|i| [1.0, -1.0][i % 2]).collect();
+        let new: Vec<f32> = (0..400).map(|i| [1.0, 1.0, -1.0, -1.0][i % 4]).collect();
+
+        let (stream, controller) = bstream(SamplesBuffer::new(1, 1000, old), BstreamConfig::new());
+        controller.crossfade_to(SamplesBuffer::new(1, 1000, new), Duration::from_millis(100));
+
+        let output: Vec<_> = stream
+            .map(|bf| 2f32.sqrt() * Bweights::new(1.0, 0.0, 0.0, 0.0).dot(bf))
+            .take(200)
+            .collect();
+
+        for block in output.chunks(4) {
+            let energy: f32 = block.iter().map(|x| x * x).sum::<f32>() / 4.0;
+            assert!((energy - 1.0).abs() < 0.05);
+        }
+
+        // the new audio plays alone after the crossfade
+        let expected = [-1.0, -1.0, 1.0, 1.0];
+        assert!(output[160..164]
+            .iter()
+            .zip(&expected)
+            .all(|(x, e)| (x - e).abs() < 1e-6));
+    }
+
+    #[test]
+    fn crossfade_from_ended_source_fades_in_new_source() {
+        let (stream, controller) = bstream(
+            SamplesBuffer::new(1, 1000, vec![1.0; 3]),
+            BstreamConfig::new(),
+        );
+        controller.crossfade_to(Constant::new(1.0, 1000), Duration::from_millis(10));
+
+        let output: Vec<_> = stream
+            .map(|bf| 2f32.sqrt() * Bweights::new(1.0, 0.0, 0.0, 0.0).dot(bf))
+            .take(20)
+            .collect();
+
+        assert_eq!(output.len(), 20);
+        assert!(output[5] < 1.0);
+        assert!((output[15] - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn occlusion_reduces_high_frequencies() {
+        let energy = |amount: f32| {
+            let signal: Vec<f32> = (0..2000).map(|i| [1.0, -1.0][i % 2]).collect();
+            let (stream, controller) =
+                bstream(SamplesBuffer::new(1, 48000, signal), BstreamConfig::new());
+            controller.set_occlusion(amount);
+            stream
+                .skip(100)
+                .take(1000)
+                .map(|bf| Bweights::new(1.0, 0.0, 0.0, 0.0).dot(bf).powi(2))
+                .sum::<f32>()
+        };
+
+        assert_eq!(energy(0.0), energy(-1.0));
+        assert!(energy(0.0) > 2.0 * energy(0.3));
+        assert!(energy(0.3) > 2.0 * energy(0.6));
+        assert!(energy(0.6) > 2.0 * energy(1.0));
+        assert_eq!(energy(1.0), energy(2.0));
+    }
+
+    #[test]
+    fn target_rms_normalizes_sources_to_the_same_level() {
+        let rms = |amplitude: f32| {
+            let tone = rodio::source::SineWave::new(440)
+                .amplify(amplitude)
+                .take_duration(Duration::from_secs(1));
+            let (stream, _) = bstream(tone, BstreamConfig::new().with_target_rms(0.2));
+            let w: Vec<f32> = stream
+                .map(|bf| Bweights::new(2f32.sqrt(), 0.0, 0.0, 0.0).dot(bf))
+                .collect();
+            (w.iter().map(|x| x * x).sum::<f32>() / w.len() as f32).sqrt()
+        };
+
+        let quiet = rms(0.05);
+        let loud = rms(0.8);
+        assert!((quiet - 0.2).abs() < 0.002);
+        assert!((loud - 0.2).abs() < 0.002);
+
+        // silence stays silent
+        let (stream, _) = bstream(
+            Constant::new(0.0, 48000),
+            BstreamConfig::new().with_target_rms(0.2),
+        );
+        assert!(stream.take(100).all(|bf| bf.components() == [0.0; 4]));
+    }
+
+    #[test]
+    fn air_absorption_dulls_distant_sources() {
+        // ratio of high-frequency (differenced) energy to total energy, independent of level
+        let brightness = |stream: Bstream| {
+            let w: Vec<f32> = stream
+                .skip(1000)
+                .take(20000)
+                .map(|bf| Bweights::new(1.0, 0.0, 0.0, 0.0).dot(bf))
+                .collect();
+            let total: f32 = w.iter().map(|x| x * x).sum();
+            let high: f32 = w.windows(2).map(|d| (d[1] - d[0]).powi(2)).sum();
+            high / total
+        };
+        let play = |distance: f32, air_absorption: bool| {
+            bstream(
+                crate::sources::Noise::with_seed(5, 48000),
+                BstreamConfig::new()
+                    .with_position([0.0, distance, 0.0])
+                    .with_doppler(false)
+                    .with_air_absorption(air_absorption),
+            )
+        };
+
+        let near = brightness(play(1.0, true).0);
+        assert_eq!(near, brightness(play(1.0, false).0));
+        let far = brightness(play(100.0, true).0);
+        assert!(near > 2.0 * far);
+        assert!((brightness(play(100.0, false).0) - near).abs() < 1e-3 * near);
+
+        // the filter follows the source as it moves away
+        let (stream, mut controller) = play(1.0, true);
+        controller.set_position([0.0, 1000.0, 0.0]);
+        assert!(brightness(stream) < far);
+    }
+
+    #[test]
+    fn eq_changes_spectral_tilt() {
+        // ratio of high-frequency (differenced) energy to total energy
+        let tilt = |low_db: f32, high_db: f32| {
+            let (stream, controller) = bstream(
+                crate::sources::Noise::with_seed(3, 48000),
+                BstreamConfig::new(),
+            );
+            controller.set_eq(low_db, high_db);
+            let w: Vec<f32> = stream
+                .skip(1000)
+                .take(20000)
+                .map(|bf| Bweights::new(1.0, 0.0, 0.0, 0.0).dot(bf))
+                .collect();
+            let total: f32 = w.iter().map(|x| x * x).sum();
+            let high: f32 = w.windows(2).map(|d| (d[1] - d[0]).powi(2)).sum();
+            high / total
+        };
+
+        let flat = tilt(0.0, 0.0);
+        assert!(tilt(-12.0, 12.0) > 1.1 * flat);
+        assert!(tilt(12.0, -12.0) < 0.5 * flat);
+    }
+
+    #[test]
+    fn eq_at_zero_db_is_bypassed() {
+        let render = |eq: Option<(f32, f32)>| {
+            let (stream, controller) = bstream(
+                crate::sources::Noise::with_seed(3, 48000),
+                BstreamConfig::new(),
+            );
+            if let Some((low, high)) = eq {
+                controller.set_eq(low, high);
+                controller.set_eq(0.0, 0.0);
+            }
+            stream
+                .take(100)
+                .map(|bf| bf.components())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(render(None), render(Some((6.0, -6.0))));
+    }
+
+    #[test]
+    fn acceleration_slides_the_pitch() {
+        let config = BstreamConfig::new()
+            .with_position([1.0, 0.0, 0.0])
+            .with_speed_of_sound(300.0);
+        let (stream, controller) = bstream(Ramp::new(1000), config);
+        controller.accelerate_to([100.0, 0.0, 0.0], Duration::from_millis(100));
+
+        // the playback rate is the slope of the ramp
+        let output: Vec<_> = extract_x_component(stream).take(200).collect();
+        let rates: Vec<_> = output.windows(2).map(|w| (w[1] - w[0]) * 1000.0).collect();
+        assert!((rates[0] - 1.0).abs() < 0.01);
+        assert!(rates.windows(2).all(|r| r[1] <= r[0] + 1e-3));
+        assert!(rates.windows(2).all(|r| r[0] - r[1] < 0.01));
+        assert!((rates[50] - 0.875).abs() < 0.01);
+        assert!((rates[150] - 0.75).abs() < 1e-3);
+    }
+
+    #[test]
+    fn transforms_update_position_and_facing_together() {
+        let (_stream, controller) = bstream(Constant::new(1.0, 48000), BstreamConfig::new());
+        let shared = controller.shared().clone();
+
+        let writer = std::thread::spawn(move || {
+            for i in 1..=1000 {
+                let x = i as f32;
+                controller.set_transform([x, 1.0, 0.0], [-x, 0.0, 1.0]);
+            }
+        });
+
+        while !writer.is_finished() {
+            let snapshot = shared.snapshot();
+            assert_eq!(snapshot.facing[0], -snapshot.position[0]);
+        }
+        writer.join().unwrap();
+
+        let snapshot = shared.snapshot();
+        assert_eq!(snapshot.position[0], 1000.0);
+        assert_eq!(snapshot.facing[0], -1000.0);
+    }
+
     fn extract_x_component(stream: impl Iterator<Item = Bformat>) -> impl Iterator<Item = f32> {
         stream.map(|bsample| Bweights::new(0.0, 1.0, 0.0, 0.0).dot(bsample))
     }
@@ -1 +1,153 @@
+//! Physical constants and default values of the sound scene
+//!
+//! The constants are the defaults of a `SceneConstants` object, which is used by every scene.
+//! They can be overridden per scene with `AmbisonicBuilder::with_constants` or
+//! `BmixerConfig::with_constants`, e.g. to simulate sound propagation in other media.
+//! Individual streams can override them again through their `BstreamConfig`.
+
+/// Speed of sound (in m/s) in air, used for the doppler effect by default
 pub const SPEED_OF_SOUND: f32 = 343.5; // m/s in air
+
+/// Distance (in m) up to which sources play at full level by default
+///
+/// See `BstreamConfig::with_reference_distance`.
+pub const REFERENCE_DISTANCE: f32 = 1.0;
+
+/// Radius (in m) around the listener within which sources lose their direction by default
+///
+/// See `BstreamConfig::with_min_distance`.
+pub const MIN_DISTANCE: f32 = 0.1;
+
+/// Physical constants of a sound scene
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "SceneConstantsRaw")
+)]
+pub struct SceneConstants {
+    speed_of_sound: f32,
+    reference_distance: f32,
+    min_distance: f32,
+    doppler_factor: f32,
+    air_absorption: bool,
+}
+
+impl Default for SceneConstants {
+    fn default() -> Self {
+        SceneConstants {
+            speed_of_sound: SPEED_OF_SOUND,
+            reference_distance: REFERENCE_DISTANCE,
+            min_distance: MIN_DISTANCE,
+            doppler_factor: 1.0,
+            air_absorption: false,
+        }
+    }
+}
+
+impl SceneConstants {
+    /// Create new `SceneConstants` with the default values of this module.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the speed of sound (in m/s), which determines the strength of the doppler effect.
+    pub fn with_speed_of_sound(mut self, s: f32) -> Self {
+        self.speed_of_sound = s;
+        self
+    }
+
+    /// Set the distance up to which sources play at full level.
+    pub fn with_reference_distance(mut self, d: f32) -> Self {
+        self.reference_distance = d;
+        self
+    }
+
+    /// Set the radius around the listener within which sources lose their direction.
+    pub fn with_min_distance(mut self, d: f32) -> Self {
+        self.min_distance = d.max(0.0);
+        self
+    }
+
+    /// Scale the doppler effect of all sources (defaults to 1).
+    ///
+    /// At 1 the pitch shift is physically correct, 0 disables the doppler effect, and larger
+    /// values exaggerate it. The factor multiplies the relative velocity of source and listener,
+    /// like OpenAL's `AL_DOPPLER_FACTOR`, and applies on top of each source's own doppler
+    /// factor (see `BstreamConfig::with_doppler_factor`).
+    pub fn with_doppler_factor(mut self, factor: f32) -> Self {
+        self.doppler_factor = factor;
+        self
+    }
+
+    /// Enable or disable air absorption for sources by default (defaults to false).
+    ///
+    /// See `BstreamConfig::with_air_absorption`.
+    pub fn with_air_absorption(mut self, enabled: bool) -> Self {
+        self.air_absorption = enabled;
+        self
+    }
+
+    /// Speed of sound (in m/s)
+    pub fn speed_of_sound(&self) -> f32 {
+        self.speed_of_sound
+    }
+
+    /// Distance up to which sources play at full level
+    pub fn reference_distance(&self) -> f32 {
+        self.reference_distance
+    }
+
+    /// Radius around the listener within which sources lose their direction
+    pub fn min_distance(&self) -> f32 {
+        self.min_distance
+    }
+
+    /// Factor that scales the doppler effect of all sources
+    pub fn doppler_factor(&self) -> f32 {
+        self.doppler_factor
+    }
+
+    /// Whether sources are subject to air absorption by default
+    pub fn air_absorption(&self) -> bool {
+        self.air_absorption
+    }
+}
+
+/// Deserialized fields of `SceneConstants`, which are applied through its setters
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(default)]
+struct SceneConstantsRaw {
+    speed_of_sound: f32,
+    reference_distance: f32,
+    min_distance: f32,
+    doppler_factor: f32,
+    air_absorption: bool,
+}
+
+#[cfg(feature = "serde")]
+impl Default for SceneConstantsRaw {
+    fn default() -> Self {
+        let constants = SceneConstants::default();
+        SceneConstantsRaw {
+            speed_of_sound: constants.speed_of_sound,
+            reference_distance: constants.reference_distance,
+            min_distance: constants.min_distance,
+            doppler_factor: constants.doppler_factor,
+            air_absorption: constants.air_absorption,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SceneConstantsRaw> for SceneConstants {
+    fn from(raw: SceneConstantsRaw) -> Self {
+        SceneConstants::new()
+            .with_speed_of_sound(raw.speed_of_sound)
+            .with_reference_distance(raw.reference_distance)
+            .with_min_distance(raw.min_distance)
+            .with_doppler_factor(raw.doppler_factor)
+            .with_air_absorption(raw.air_absorption)
+    }
+}
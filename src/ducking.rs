@@ -0,0 +1,173 @@
+//! Sidechain compression that ducks some sources under others
+
+use std::time::Duration;
+
+/// Settings of the scene's ducking compressor
+///
+/// Streams configured with `BstreamConfig::as_duck_trigger` (e.g. dialogue) control the level of
+/// streams configured with `BstreamConfig::as_duckable` (e.g. ambience and effects). The
+/// compressor follows the level of the mixed triggers as heard by the listener. While it
+/// exceeds the threshold, the duckable streams are attenuated: each dB of trigger level above
+/// the threshold reduces them by `1 - 1 / ratio` dB. Streams with neither role, and the reverb,
+/// are not affected.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "DuckConfigRaw")
+)]
+pub struct DuckConfig {
+    threshold: f32,
+    ratio: f32,
+    attack: Duration,
+    release: Duration,
+}
+
+impl Default for DuckConfig {
+    fn default() -> Self {
+        DuckConfig {
+            threshold: 0.05,
+            ratio: 4.0,
+            attack: Duration::from_millis(10),
+            release: Duration::from_millis(300),
+        }
+    }
+}
+
+impl DuckConfig {
+    /// Create new `DuckConfig` with default settings.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the trigger amplitude above which ducking starts (defaults to 0.05, about -26 dB).
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.abs();
+        self
+    }
+
+    /// Set the compression ratio (defaults to 4).
+    ///
+    /// A ratio of 1 disables ducking; large ratios attenuate the duckable streams strongly
+    /// even for triggers slightly above the threshold. Values below 1 are clamped.
+    pub fn with_ratio(mut self, ratio: f32) -> Self {
+        self.ratio = ratio.max(1.0);
+        self
+    }
+
+    /// Set the time constant of the ducking when a trigger gets louder (defaults to 10 ms).
+    pub fn with_attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    /// Set the time constant of the recovery when a trigger gets quieter (defaults to 300 ms).
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+}
+
+/// Deserialized fields of a `DuckConfig`, which are applied through its setters
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(default)]
+struct DuckConfigRaw {
+    threshold: f32,
+    ratio: f32,
+    attack: Duration,
+    release: Duration,
+}
+
+#[cfg(feature = "serde")]
+impl Default for DuckConfigRaw {
+    fn default() -> Self {
+        let config = DuckConfig::default();
+        DuckConfigRaw {
+            threshold: config.threshold,
+            ratio: config.ratio,
+            attack: config.attack,
+            release: config.release,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<DuckConfigRaw> for DuckConfig {
+    fn from(raw: DuckConfigRaw) -> Self {
+        DuckConfig::new()
+            .with_threshold(raw.threshold)
+            .with_ratio(raw.ratio)
+            .with_attack(raw.attack)
+            .with_release(raw.release)
+    }
+}
+
+/// Compressor that computes the gain of the duckable streams from the trigger level
+pub(crate) struct Ducker {
+    threshold: f32,
+    // exponent of the gain reduction, `1 - 1 / ratio`
+    slope: f32,
+    // per-sample coefficients of the exponential level follower
+    attack: f32,
+    release: f32,
+    envelope: f32,
+}
+
+impl Ducker {
+    pub fn new(config: &DuckConfig, sample_rate: u32) -> Self {
+        let coefficient = |t: Duration| {
+            let samples = t.as_secs_f32() * sample_rate as f32;
+            if samples > 0.0 {
+                (-1.0 / samples).exp()
+            } else {
+                0.0
+            }
+        };
+        Ducker {
+            threshold: config.threshold,
+            slope: 1.0 - 1.0 / config.ratio,
+            attack: coefficient(config.attack),
+            release: coefficient(config.release),
+            envelope: 0.0,
+        }
+    }
+
+    /// Gain of the duckable streams, given the next sample of the trigger signal
+    #[inline(always)]
+    pub fn process(&mut self, trigger: f32) -> f32 {
+        let level = trigger.abs();
+        let coefficient = if level > self.envelope {
+            self.attack
+        } else {
+            self.release
+        };
+        self.envelope = level + (self.envelope - level) * coefficient;
+
+        if self.envelope > self.threshold {
+            (self.threshold / self.envelope).powf(self.slope)
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loud_triggers_reduce_the_gain_by_the_ratio() {
+        let config = DuckConfig::new()
+            .with_threshold(0.1)
+            .with_ratio(2.0)
+            .with_attack(Duration::from_secs(0));
+        let mut ducker = Ducker::new(&config, 1000);
+
+        assert_eq!(ducker.process(0.05), 1.0);
+
+        // 20 dB above the threshold reduce the gain by 10 dB
+        let gain = ducker.process(1.0);
+        assert!((gain - 0.1f32.sqrt()).abs() < 1e-6);
+    }
+}
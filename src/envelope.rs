@@ -0,0 +1,213 @@
+//! Amplitude envelopes of individual sources
+
+use std::time::Duration;
+
+/// Attack-decay-sustain-release envelope of a source's amplitude
+///
+/// When the source starts, its amplitude rises linearly from 0 to 1 over the attack time, then
+/// falls linearly to the sustain level over the decay time, and stays there. After
+/// `SoundController::note_off`, it falls linearly to 0 over the release time, and the source
+/// finishes. A note off during the attack or decay releases from the current amplitude.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "EnvelopeRaw")
+)]
+pub struct Envelope {
+    attack: Duration,
+    decay: Duration,
+    sustain: f32,
+    release: Duration,
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope {
+            attack: Duration::from_millis(5),
+            decay: Duration::from_secs(0),
+            sustain: 1.0,
+            release: Duration::from_millis(50),
+        }
+    }
+}
+
+impl Envelope {
+    /// Create new `Envelope` with default settings.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the time to rise to full amplitude (defaults to 5 ms).
+    pub fn with_attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    /// Set the time to fall from full amplitude to the sustain level (defaults to 0).
+    pub fn with_decay(mut self, decay: Duration) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    /// Set the amplitude held until the note off (defaults to 1).
+    ///
+    /// Values are clamped to the range from 0 to 1.
+    pub fn with_sustain(mut self, level: f32) -> Self {
+        self.sustain = level.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the time to fall to silence after the note off (defaults to 50 ms).
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+}
+
+/// Deserialized fields of an `Envelope`, which are applied through its setters
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(default)]
+struct EnvelopeRaw {
+    attack: Duration,
+    decay: Duration,
+    sustain: f32,
+    release: Duration,
+}
+
+#[cfg(feature = "serde")]
+impl Default for EnvelopeRaw {
+    fn default() -> Self {
+        let envelope = Envelope::default();
+        EnvelopeRaw {
+            attack: envelope.attack,
+            decay: envelope.decay,
+            sustain: envelope.sustain,
+            release: envelope.release,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<EnvelopeRaw> for Envelope {
+    fn from(raw: EnvelopeRaw) -> Self {
+        Envelope::new()
+            .with_attack(raw.attack)
+            .with_decay(raw.decay)
+            .with_sustain(raw.sustain)
+            .with_release(raw.release)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// Envelope generator that computes the amplitude sample by sample
+pub(crate) struct Adsr {
+    // durations of the stages, in samples
+    attack: f32,
+    decay: f32,
+    sustain: f32,
+    release: f32,
+    stage: Stage,
+    level: f32,
+    // change of the level per sample in the release stage, and the samples it has left
+    release_step: f32,
+    release_remaining: f32,
+}
+
+impl Adsr {
+    pub fn new(envelope: &Envelope, sample_rate: u32) -> Self {
+        let samples = |t: Duration| t.as_secs_f32() * sample_rate as f32;
+        let attack = samples(envelope.attack);
+        // without an attack, the source starts at full amplitude
+        let (stage, level) = if attack < 1.0 {
+            (Stage::Decay, 1.0)
+        } else {
+            (Stage::Attack, 0.0)
+        };
+        Adsr {
+            attack,
+            decay: samples(envelope.decay),
+            sustain: envelope.sustain,
+            release: samples(envelope.release),
+            stage,
+            level,
+            release_step: 0.0,
+            release_remaining: 0.0,
+        }
+    }
+
+    /// Start the release stage
+    pub fn note_off(&mut self) {
+        if self.stage != Stage::Release {
+            self.stage = Stage::Release;
+            self.release_remaining = self.release.ceil().max(1.0);
+            self.release_step = self.level / self.release_remaining;
+        }
+    }
+
+    /// Amplitude of the next sample, `None` once the release is complete
+    #[inline(always)]
+    pub fn next(&mut self) -> Option<f32> {
+        let level = self.level;
+        match self.stage {
+            Stage::Attack => {
+                self.level += 1.0 / self.attack.max(1.0);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= (1.0 - self.sustain) / self.decay.max(1.0);
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {}
+            Stage::Release => {
+                if self.release_remaining <= 0.0 {
+                    return None;
+                }
+                self.level = (self.level - self.release_step).max(0.0);
+                self.release_remaining -= 1.0;
+            }
+        }
+        Some(level)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_passes_through_its_stages() {
+        let envelope = Envelope::new()
+            .with_attack(Duration::from_millis(4))
+            .with_decay(Duration::from_millis(2))
+            .with_sustain(0.5)
+            .with_release(Duration::from_millis(5));
+        let mut adsr = Adsr::new(&envelope, 1000);
+
+        let levels: Vec<f32> = (0..10).map(|_| adsr.next().unwrap()).collect();
+        assert_eq!(
+            levels,
+            [0.0, 0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.5, 0.5, 0.5]
+        );
+
+        adsr.note_off();
+        let release: Vec<f32> = std::iter::from_fn(|| adsr.next()).collect();
+        assert_eq!(release.len(), 5);
+        assert_eq!(release[0], 0.5);
+        assert!((release[4] - 0.1).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,203 @@
+//! Playback of recorded first-order *B-format* sound fields
+
+use crate::bformat::{Bformat, BformatBlock, Rotation};
+use crate::sync::lock;
+use rodio::{Sample, Source};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Channel order and normalization of a first-order *B-format* recording
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BformatLayout {
+    /// AmbiX: channels in ACN order W, Y, Z, X with SN3D normalization (default)
+    #[default]
+    Ambix,
+
+    /// Furse-Malham: channels in the order W, X, Y, Z, with W attenuated by 3 dB
+    Fuma,
+}
+
+impl BformatLayout {
+    /// Convert a frame of four channels to a sample in the crate's convention
+    ///
+    /// In both layouts, X points to the front, Y to the left, and Z up.
+    fn decode(self, c: [f32; 4]) -> Bformat {
+        match self {
+            BformatLayout::Ambix => {
+                Bformat::from_components([c[0] / 2f32.sqrt(), -c[1], c[3], c[2]])
+            }
+            BformatLayout::Fuma => Bformat::from_components([c[0], -c[2], c[1], c[3]]),
+        }
+    }
+}
+
+/// Settings of a sound field that its controller changes during playback
+struct FieldParams {
+    rotation: Option<Rotation>,
+    gain: f32,
+    stop: bool,
+}
+
+/// State shared between a sound field and its controller
+struct FieldShared {
+    params: Mutex<FieldParams>,
+    pending: AtomicBool,
+    stopped: AtomicBool,
+}
+
+/// A recorded sound field that plays in the mixer
+///
+/// The field is added to the scene's *B-format* bus as it is, without encoding it at a
+/// position.
+pub(crate) struct FieldStream {
+    input: Box<dyn Source<Item = f32> + Send>,
+    layout: BformatLayout,
+    shared: Arc<FieldShared>,
+    rotation: Option<Rotation>,
+    gain: f32,
+}
+
+impl FieldStream {
+    /// Construct a sound field from a four-channel source, and its controller
+    pub(crate) fn new<I>(input: I, layout: BformatLayout) -> (Self, FieldController)
+    where
+        I: Source<Item = f32> + Send + 'static,
+    {
+        let shared = Arc::new(FieldShared {
+            params: Mutex::new(FieldParams {
+                rotation: None,
+                gain: 1.0,
+                stop: false,
+            }),
+            pending: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        });
+        let stream = FieldStream {
+            input: Box::new(input),
+            layout,
+            shared: shared.clone(),
+            rotation: None,
+            gain: 1.0,
+        };
+        (stream, FieldController { shared })
+    }
+
+    /// Add the next `block.len()` samples of the field to `block`
+    ///
+    /// Returns false if the field ended or was stopped. Changes from the controller are
+    /// applied once at the start of the block.
+    pub(crate) fn mix_block(&mut self, block: &mut BformatBlock) -> bool {
+        if self.shared.pending.swap(false, Ordering::SeqCst) {
+            let params = lock(&self.shared.params);
+            if params.stop {
+                return false;
+            }
+            self.rotation = params.rotation;
+            self.gain = params.gain;
+        }
+
+        for i in 0..block.len() {
+            let mut frame = [0.0; 4];
+            for c in &mut frame {
+                *c = match self.input.next() {
+                    Some(x) => x,
+                    None => return false,
+                };
+            }
+
+            let mut sample = self.layout.decode(frame);
+            if let Some(rot) = &self.rotation {
+                sample = sample.rotate_first_order(rot);
+            }
+            block.add(i, sample.amplify(self.gain));
+        }
+        true
+    }
+}
+
+impl Drop for FieldStream {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Controls playback of a recorded sound field
+///
+/// The field can be turned, but not moved: it always surrounds the listener.
+pub struct FieldController {
+    shared: Arc<FieldShared>,
+}
+
+impl FieldController {
+    fn update(&self, f: impl FnOnce(&mut FieldParams)) {
+        f(&mut lock(&self.shared.params));
+        self.shared.pending.store(true, Ordering::SeqCst);
+    }
+
+    /// Turn the field so that the front of the recording points into direction `forward` and
+    /// its top points `up`
+    ///
+    /// Both vectors are given in the same coordinates as source positions, and must not be
+    /// parallel. By default the recording's front is along the positive y axis and its top
+    /// along the positive z axis.
+    pub fn set_orientation(&self, forward: [f32; 3], up: [f32; 3]) {
+        let rot = Rotation::from_listener_orientation(forward, up).inverse();
+        self.update(|params| params.rotation = Some(rot));
+    }
+
+    /// Set the gain of the field (defaults to 1)
+    pub fn set_gain(&self, gain: f32) {
+        self.update(|params| params.gain = gain);
+    }
+
+    /// Stop playback; the field is removed from the scene at the start of the next block
+    pub fn stop(&self) {
+        self.update(|params| params.stop = true);
+    }
+
+    /// Whether the field has finished playing
+    pub fn is_finished(&self) -> bool {
+        self.shared.stopped.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bformat::AmbisonicOrder;
+    use rodio::buffer::SamplesBuffer;
+
+    fn first_sample(frame: [f32; 4], layout: BformatLayout) -> [f32; 4] {
+        let (mut stream, _) =
+            FieldStream::new(SamplesBuffer::new(4, 48000, frame.to_vec()), layout);
+        let mut block = BformatBlock::new(1, AmbisonicOrder::First);
+        stream.mix_block(&mut block);
+        block.get(0).components()
+    }
+
+    #[test]
+    fn layouts_decode_to_the_same_field() {
+        // a plane wave from the left, encoded like a source at [-1, 0, 0]
+        let expected = [1.0 / 2f32.sqrt(), -1.0, 0.0, 0.0];
+
+        let ambix = first_sample([1.0, 1.0, 0.0, 0.0], BformatLayout::Ambix);
+        let fuma = first_sample([1.0 / 2f32.sqrt(), 0.0, 1.0, 0.0], BformatLayout::Fuma);
+        for i in 0..4 {
+            assert!((ambix[i] - expected[i]).abs() < 1e-6);
+            assert!((fuma[i] - expected[i]).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn stopped_fields_end() {
+        let input = SamplesBuffer::new(4, 48000, vec![0.0; 400]);
+        let (mut stream, controller) = FieldStream::new(input, BformatLayout::Ambix);
+        let mut block = BformatBlock::new(10, AmbisonicOrder::First);
+        assert!(stream.mix_block(&mut block));
+
+        controller.stop();
+        assert!(!stream.mix_block(&mut block));
+        drop(stream);
+        assert!(controller.is_finished());
+    }
+}
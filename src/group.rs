@@ -0,0 +1,178 @@
+//! Sets of sources that move and change level together
+
+use crate::bmixer::BmixerComposer;
+use crate::bstream::{BstreamConfig, SoundController};
+use rodio::Source;
+use std::sync::Arc;
+
+/// Sources that share a position and a gain
+///
+/// Each member is played at a fixed offset from the group's position, which is relative to the
+/// listener. Moving the group moves all members by the same amount, and the group's gain
+/// applies to every member. Members that have finished playing are dropped from the group.
+pub struct SoundGroup {
+    composer: Arc<BmixerComposer>,
+    position: [f32; 3],
+    gain: f32,
+    members: Vec<Member>,
+}
+
+struct Member {
+    controller: SoundController,
+    offset: [f32; 3],
+}
+
+impl Member {
+    fn position(&self, group: [f32; 3]) -> [f32; 3] {
+        [
+            group[0] + self.offset[0],
+            group[1] + self.offset[1],
+            group[2] + self.offset[2],
+        ]
+    }
+}
+
+impl SoundGroup {
+    /// Create an empty group at the listener's position
+    pub fn new(composer: Arc<BmixerComposer>) -> Self {
+        SoundGroup {
+            composer,
+            position: [0.0, 0.0, 0.0],
+            gain: 1.0,
+            members: Vec::new(),
+        }
+    }
+
+    /// Add a single-channel `Source` to the group at an offset from the group's position
+    ///
+    /// The source starts playing with the group's gain. Returns a controller for the source;
+    /// position changes made with it are overridden when the group moves.
+    pub fn play_at<I>(&mut self, input: I, offset: [f32; 3]) -> SoundController
+    where
+        I: Source<Item = f32> + Send + 'static,
+    {
+        self.remove_finished();
+        let mut member = Member {
+            controller: self.composer.play(input, BstreamConfig::new()),
+            offset,
+        };
+        member
+            .controller
+            .set_position(member.position(self.position));
+        if self.gain != 1.0 {
+            member.controller.set_gain(self.gain);
+        }
+        let controller = member.controller.clone();
+        self.members.push(member);
+        controller
+    }
+
+    /// Move the group to a new position relative to the listener
+    ///
+    /// All members jump to their new positions, like with `SoundController::set_position`.
+    pub fn set_position(&mut self, pos: [f32; 3]) {
+        self.position = pos;
+        self.remove_finished();
+        for member in &mut self.members {
+            let pos = member.position(pos);
+            member.controller.set_position(pos);
+        }
+    }
+
+    /// Move the group smoothly to a new position relative to the listener
+    ///
+    /// All members transition to their new positions, like with
+    /// `SoundController::adjust_position`.
+    pub fn adjust_position(&mut self, pos: [f32; 3]) {
+        self.position = pos;
+        self.remove_finished();
+        for member in &mut self.members {
+            let pos = member.position(pos);
+            member.controller.adjust_position(pos);
+        }
+    }
+
+    /// Current position of the group relative to the listener
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
+    /// Set the gain of all members (see `SoundController::set_gain`)
+    pub fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+        self.remove_finished();
+        for member in &self.members {
+            member.controller.set_gain(gain);
+        }
+    }
+
+    /// Current gain of the group
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+
+    /// Stop all members
+    pub fn stop(&mut self) {
+        for member in self.members.drain(..) {
+            member.controller.stop();
+        }
+    }
+
+    /// Number of members that are still playing
+    pub fn len(&self) -> usize {
+        self.members
+            .iter()
+            .filter(|m| !m.controller.is_finished())
+            .count()
+    }
+
+    /// Whether no member is playing
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn remove_finished(&mut self) {
+        self.members.retain(|m| !m.controller.is_finished());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bmixer::bmixer;
+    use crate::sources::Constant;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn moving_the_group_moves_all_members() {
+        let (_mixer, composer) = bmixer(48000);
+        let mut group = SoundGroup::new(composer);
+        group.set_position([1.0, 1.0, 0.0]);
+        let engine = group.play_at(Constant::new(1.0, 48000), [0.0, 0.0, 0.0]);
+        let exhaust = group.play_at(Constant::new(1.0, 48000), [0.0, -2.0, 0.5]);
+        assert_eq!(engine.position(), [1.0, 1.0, 0.0]);
+        assert_eq!(exhaust.position(), [1.0, -1.0, 0.5]);
+
+        group.adjust_position([4.0, 1.0, 0.0]);
+        assert_eq!(engine.position(), [4.0, 1.0, 0.0]);
+        assert_eq!(exhaust.position(), [4.0, -1.0, 0.5]);
+
+        group.set_gain(0.5);
+        assert_eq!(engine.gain(), 0.5);
+        assert_eq!(exhaust.gain(), 0.5);
+    }
+
+    #[test]
+    fn finished_members_leave_the_group() {
+        let (mut mixer, composer) = bmixer(48000);
+        let mut group = SoundGroup::new(composer);
+        group.play_at(SamplesBuffer::new(1, 48000, vec![0.0; 10]), [1.0, 0.0, 0.0]);
+        group.play_at(Constant::new(1.0, 48000), [1.0, 0.0, 0.0]);
+        assert_eq!(group.len(), 2);
+
+        mixer.by_ref().take(1024).count();
+        assert_eq!(group.len(), 1);
+        group.stop();
+        assert!(group.is_empty());
+    }
+}
@@ -39,40 +39,393 @@ their audio playback equipment. This leads to a clear separation of audio scene
 rendering. For details, see [Wikipedia](https://en.wikipedia.org/wiki/Ambisonics).
 
 In its current state, the library allows spatial composition of single-channel `rodio` sources
-into a first-order *B-format* stream. The chosen renderer then decodes the *B-format* stream
-into audio signals for playback.
+into a first-order *B-format* stream, or optionally a second-order stream for sharper
+localization (see `AmbisonicBuilder::with_order`). The chosen renderer then decodes the
+*B-format* stream into audio signals for playback.
 
 Currently, the following renderers are available:
 
 - Stereo: simple and efficient playback on two stereo speakers or headphones
 - HRTF: realistic 3D sound over headphones using head related transfer functions
-
-Although at the moment only stereo output is supported, the *B-format* abstraction should make
-it easy to implement arbitrary speaker configurations in the future.
+- Multi-speaker: playback on an arbitrary arrangement of speakers around the listener
+- 5.1 surround: playback on a standard 5.1 speaker setup
+- AmbiX: first-order *B-format* output for further processing in other ambisonics tools
 */
 
 mod bformat;
 mod bmixer;
 mod bstream;
+mod ducking;
+mod envelope;
+mod field;
+mod group;
+mod limiter;
 mod renderer;
+mod reverb;
+mod sync;
+mod timeline;
 
 pub mod constants;
+pub mod math;
+#[cfg(feature = "osc")]
+pub mod osc;
 pub mod sources;
-pub use bmixer::{bmixer, BmixerComposer, BstreamMixer};
-pub use bstream::{bstream, Bstream, BstreamConfig, SoundController};
-pub use renderer::{BstreamHrtfRenderer, BstreamStereoRenderer, HrtfConfig, StereoConfig};
+pub use bformat::AmbisonicOrder;
+pub use bmixer::{
+    bmixer, bmixer_with_config, BlockInfo, BmixerComposer, BmixerConfig, BstreamMixer,
+    EvictionPolicy,
+};
+pub use bstream::{
+    bstream, AttenuationModel, Bstream, BstreamConfig, Directivity, SoundController, SourceId,
+    SourceSnapshot, StereoSoundController,
+};
+pub use ducking::DuckConfig;
+pub use envelope::Envelope;
+pub use field::{BformatLayout, FieldController};
+pub use group::SoundGroup;
+pub use limiter::LimiterConfig;
+pub use renderer::{
+    BstreamAmbixRenderer, BstreamHrtfRenderer, BstreamMultiSpeakerRenderer, BstreamStereoRenderer,
+    BstreamSurround51Renderer, BstreamTransauralRenderer, DecoderWeighting, HrirMeasurement,
+    HrtfConfig, MultiSpeakerConfig, PanLaw, StereoConfig, Surround51Config, TransauralConfig,
+};
+pub use reverb::ReverbConfig;
 pub use rodio;
+pub use timeline::{Timeline, TimelineEvent};
 
+use cpal::traits::{HostTrait, StreamTrait};
+use rodio::DeviceTrait;
+use std::error::Error;
 use std::f32;
-use std::sync::Arc;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sync::lock;
+
+/// Errors that can occur while building an `Ambisonic` context
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The output stream could not be opened on the audio device
+    Stream(rodio::StreamError),
+
+    /// The playback sink could not be attached to the output stream
+    Play(rodio::PlayError),
+
+    /// The output devices could not be enumerated
+    Devices(rodio::DevicesError),
+
+    /// No output device matched the requested name
+    DeviceNotFound(String),
+
+    /// The output device's stream configuration could not be queried
+    StreamConfig(cpal::DefaultStreamConfigError),
+
+    /// The stream configurations supported by the output device could not be queried
+    SupportedStreamConfigs(cpal::SupportedStreamConfigsError),
+
+    /// The output stream could not be created with the chosen configuration
+    BuildStream(cpal::BuildStreamError),
+
+    /// The sample rate of the ambisonic mix is zero or implausibly high
+    InvalidSampleRate(u32),
+
+    /// The mixer's block size is zero
+    InvalidBlockSize(usize),
+
+    /// The output channel map of the playback configuration does not contain each channel
+    /// exactly once (see `MultiSpeakerConfig::with_output_channel_map`)
+    InvalidChannelMap(Vec<usize>),
+
+    /// The multi-speaker configuration has no speakers (see `MultiSpeakerConfig::with_speaker`)
+    NoSpeakers,
+
+    /// The subwoofer channel of a multi-speaker configuration is beyond the last output
+    /// channel (see `MultiSpeakerConfig::with_subwoofer`)
+    InvalidSubwooferChannel(usize),
+
+    /// The HRTFs of the playback configuration were measured at a different sample rate than
+    /// the scene's (see `HrtfConfig::sample_rate`)
+    HrtfSampleRate {
+        /// Sample rate of the HRTFs
+        hrtf: u32,
+        /// Sample rate of the ambisonic mix
+        scene: u32,
+    },
+
+    /// The previous audio output still holds the renderer, so the scene cannot be attached to
+    /// the new output yet (see `Ambisonic::reconnect`)
+    RendererNotReleased,
+
+    /// A manual clock was combined with an audio device or stream handle, which would play in
+    /// real time (see `AmbisonicBuilder::with_manual_clock`)
+    ManualClockWithOutput,
+
+    /// The playback configuration has a different number of output channels than the one
+    /// playing (see `Ambisonic::set_playback_configuration`)
+    ChannelCountChanged {
+        /// Number of output channels that are playing
+        current: u16,
+        /// Number of output channels of the new configuration
+        requested: u16,
+    },
+
+    /// The output device does not support the requested sample rate
+    /// (see `AmbisonicBuilder::with_exact_sample_rate`)
+    UnsupportedSampleRate {
+        /// Sample rate of the ambisonic mix
+        requested: u32,
+        /// Sample rate the device runs at by default
+        device: u32,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Stream(e) => write!(f, "failed to open output stream: {}", e),
+            BuildError::Play(e) => write!(f, "failed to create sink: {}", e),
+            BuildError::Devices(e) => write!(f, "failed to enumerate output devices: {}", e),
+            BuildError::DeviceNotFound(name) => {
+                write!(f, "no output device matches the name \"{}\"", name)
+            }
+            BuildError::StreamConfig(e) => write!(f, "failed to query output config: {}", e),
+            BuildError::SupportedStreamConfigs(e) => {
+                write!(f, "failed to query supported output configs: {}", e)
+            }
+            BuildError::BuildStream(e) => write!(f, "failed to create output stream: {}", e),
+            BuildError::InvalidSampleRate(sr) => write!(f, "invalid sample rate {} Hz", sr),
+            BuildError::InvalidBlockSize(n) => write!(f, "invalid block size {}", n),
+            BuildError::InvalidChannelMap(map) => write!(f, "invalid output channel map {:?}", map),
+            BuildError::NoSpeakers => write!(f, "multi-speaker configuration has no speakers"),
+            BuildError::InvalidSubwooferChannel(channel) => {
+                write!(f, "subwoofer channel {} is out of range", channel)
+            }
+            BuildError::HrtfSampleRate { hrtf, scene } => write!(
+                f,
+                "HRTFs are sampled at {} Hz but the scene runs at {} Hz",
+                hrtf, scene
+            ),
+            BuildError::RendererNotReleased => {
+                write!(f, "renderer was not released by the old output")
+            }
+            BuildError::ManualClockWithOutput => {
+                write!(f, "a manual clock cannot drive an audio output")
+            }
+            BuildError::ChannelCountChanged { current, requested } => write!(
+                f,
+                "playback configuration has {} output channels instead of {}",
+                requested, current
+            ),
+            BuildError::UnsupportedSampleRate { requested, device } => write!(
+                f,
+                "requested sample rate {} Hz is not supported by the device (default {} Hz)",
+                requested, device
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Stream(e) => Some(e),
+            BuildError::Play(e) => Some(e),
+            BuildError::Devices(e) => Some(e),
+            BuildError::StreamConfig(e) => Some(e),
+            BuildError::SupportedStreamConfigs(e) => Some(e),
+            BuildError::BuildStream(e) => Some(e),
+            BuildError::DeviceNotFound(_)
+            | BuildError::InvalidSampleRate(_)
+            | BuildError::InvalidBlockSize(_)
+            | BuildError::InvalidChannelMap(_)
+            | BuildError::NoSpeakers
+            | BuildError::InvalidSubwooferChannel(_)
+            | BuildError::HrtfSampleRate { .. }
+            | BuildError::RendererNotReleased
+            | BuildError::ManualClockWithOutput
+            | BuildError::ChannelCountChanged { .. }
+            | BuildError::UnsupportedSampleRate { .. } => None,
+        }
+    }
+}
+
+impl From<rodio::StreamError> for BuildError {
+    fn from(e: rodio::StreamError) -> Self {
+        BuildError::Stream(e)
+    }
+}
+
+impl From<rodio::PlayError> for BuildError {
+    fn from(e: rodio::PlayError) -> Self {
+        BuildError::Play(e)
+    }
+}
+
+impl From<rodio::DevicesError> for BuildError {
+    fn from(e: rodio::DevicesError) -> Self {
+        BuildError::Devices(e)
+    }
+}
+
+impl From<cpal::DefaultStreamConfigError> for BuildError {
+    fn from(e: cpal::DefaultStreamConfigError) -> Self {
+        BuildError::StreamConfig(e)
+    }
+}
+
+impl From<cpal::SupportedStreamConfigsError> for BuildError {
+    fn from(e: cpal::SupportedStreamConfigsError) -> Self {
+        BuildError::SupportedStreamConfigs(e)
+    }
+}
+
+impl From<cpal::BuildStreamError> for BuildError {
+    fn from(e: cpal::BuildStreamError) -> Self {
+        BuildError::BuildStream(e)
+    }
+}
+
+/// Find the first item whose name contains `pattern`, ignoring case
+fn find_by_name<T>(
+    items: impl IntoIterator<Item = T>,
+    name_of: impl Fn(&T) -> Option<String>,
+    pattern: &str,
+) -> Option<T> {
+    let pattern = pattern.to_lowercase();
+    items.into_iter().find(|item| {
+        name_of(item)
+            .map(|name| name.to_lowercase().contains(&pattern))
+            .unwrap_or(false)
+    })
+}
+
+/// Description of an audio output device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Name of the device, which can be passed to `AmbisonicBuilder::with_device_name`
+    pub name: String,
+
+    /// Whether this is the system's default output device
+    pub is_default: bool,
+}
+
+/// List the output devices of the default audio host
+///
+/// Intended for device pickers: the name of a device can be passed to
+/// `AmbisonicBuilder::with_device_name`. The default device is always included if there is
+/// one; other devices whose name cannot be queried are skipped. Returns an empty list if the
+/// host has no output devices or they cannot be enumerated.
+pub fn available_output_devices() -> Vec<DeviceInfo> {
+    let host = cpal::default_host();
+    let default_name = host.default_output_device().and_then(|d| d.name().ok());
+    let mut devices: Vec<DeviceInfo> = match host.output_devices() {
+        Ok(devices) => devices
+            .filter_map(|d| d.name().ok())
+            .map(|name| DeviceInfo {
+                is_default: false,
+                name,
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    // some hosts, e.g. ALSA, do not always enumerate their default device
+    if let Some(name) = default_name {
+        match devices.iter_mut().find(|d| d.name == name) {
+            Some(device) => device.is_default = true,
+            None => devices.insert(
+                0,
+                DeviceInfo {
+                    name,
+                    is_default: true,
+                },
+            ),
+        }
+    }
+    devices
+}
 
 /// Configure playback parameters
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PlaybackConfiguration {
     /// Stereo playback
     Stereo(StereoConfig),
 
     /// Headphone playback using head related transfer functions
     Hrtf(HrtfConfig),
+
+    /// Playback of the HRTF rendering on a stereo speaker pair with cross-talk cancellation
+    Transaural(TransauralConfig),
+
+    /// Playback on an arbitrary arrangement of speakers
+    MultiSpeaker(MultiSpeakerConfig),
+
+    /// Playback on a 5.1 surround speaker setup
+    Surround51(Surround51Config),
+
+    /// Four-channel first-order AmbiX output (ACN channel order, SN3D normalization)
+    Ambix,
+}
+
+impl PlaybackConfiguration {
+    /// Number of output channels
+    fn channels(&self) -> u16 {
+        match self {
+            PlaybackConfiguration::Stereo(_)
+            | PlaybackConfiguration::Hrtf(_)
+            | PlaybackConfiguration::Transaural(_) => 2,
+            PlaybackConfiguration::MultiSpeaker(cfg) => cfg.n_channels() as u16,
+            PlaybackConfiguration::Surround51(_) => 6,
+            PlaybackConfiguration::Ambix => 4,
+        }
+    }
+
+    /// Check that the configuration can render a scene at `sample_rate`
+    ///
+    /// HRTFs must be sampled at the scene's rate, multi-speaker layouts must have speakers, the
+    /// output channel map, if any, must contain each output channel exactly once, and the
+    /// subwoofer, if any, must be on one of the output channels.
+    fn validate(&self, sample_rate: u32) -> Result<(), BuildError> {
+        let hrtf = match self {
+            PlaybackConfiguration::Hrtf(cfg) => Some(cfg),
+            PlaybackConfiguration::Transaural(cfg) => Some(cfg.hrtf()),
+            _ => None,
+        };
+        if let Some(hrtf) = hrtf {
+            if hrtf.sample_rate() != sample_rate {
+                return Err(BuildError::HrtfSampleRate {
+                    hrtf: hrtf.sample_rate(),
+                    scene: sample_rate,
+                });
+            }
+        }
+
+        if let PlaybackConfiguration::MultiSpeaker(cfg) = self {
+            if cfg.n_speakers() == 0 {
+                return Err(BuildError::NoSpeakers);
+            }
+            match cfg.subwoofer() {
+                Some((channel, _)) if channel > cfg.n_speakers() => {
+                    return Err(BuildError::InvalidSubwooferChannel(channel));
+                }
+                _ => {}
+            }
+        }
+
+        let map = match self {
+            PlaybackConfiguration::MultiSpeaker(cfg) => cfg.output_channel_map(),
+            PlaybackConfiguration::Surround51(cfg) => cfg.output_channel_map(),
+            _ => None,
+        };
+        match map {
+            Some(map) if renderer::invert_channel_map(map, self.channels() as usize).is_none() => {
+                Err(BuildError::InvalidChannelMap(map.to_vec()))
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl Default for PlaybackConfiguration {
@@ -93,11 +446,112 @@ impl From<HrtfConfig> for PlaybackConfiguration {
     }
 }
 
+impl From<TransauralConfig> for PlaybackConfiguration {
+    fn from(cfg: TransauralConfig) -> Self {
+        PlaybackConfiguration::Transaural(cfg)
+    }
+}
+
+impl From<MultiSpeakerConfig> for PlaybackConfiguration {
+    fn from(cfg: MultiSpeakerConfig) -> Self {
+        PlaybackConfiguration::MultiSpeaker(cfg)
+    }
+}
+
+impl From<Surround51Config> for PlaybackConfiguration {
+    fn from(cfg: Surround51Config) -> Self {
+        PlaybackConfiguration::Surround51(cfg)
+    }
+}
+
+/// Device-independent settings of a scene
+///
+/// The settings bundle everything about a scene that can be stored in a configuration file,
+/// and are applied to a builder with `AmbisonicBuilder::with_settings`. With the `serde`
+/// feature enabled, they can be serialized and deserialized; fields missing from the input
+/// take their default values.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct SceneSettings {
+    /// Sample rate (in Hz) of the ambisonic mix
+    pub sample_rate: u32,
+
+    /// Ambisonic order of the scene
+    pub order: AmbisonicOrder,
+
+    /// Number of samples the mixer processes at once, or `None` for the default
+    pub block_size: Option<usize>,
+
+    /// Playback configuration
+    pub playback: PlaybackConfiguration,
+
+    /// Physical constants of the scene
+    pub constants: constants::SceneConstants,
+
+    /// Shared room reverb, if any
+    pub reverb: Option<ReverbConfig>,
+
+    /// Ducking of duckable sources under duck triggers, if any
+    pub ducking: Option<DuckConfig>,
+
+    /// Static attenuation (in dB) of the mix bus
+    pub headroom_db: f32,
+
+    /// Limiter of the rendered output, if any
+    pub limiter: Option<LimiterConfig>,
+
+    /// Maximum number of simultaneously playing sources, or `None` for no limit
+    pub max_sources: Option<usize>,
+
+    /// Policy for stopping sources when there are too many
+    pub eviction_policy: EvictionPolicy,
+
+    /// Duration of the fade out when the context is dropped
+    pub drop_fade: Duration,
+}
+
+impl Default for SceneSettings {
+    fn default() -> Self {
+        SceneSettings {
+            sample_rate: 48000,
+            order: AmbisonicOrder::First,
+            block_size: None,
+            playback: PlaybackConfiguration::default(),
+            constants: constants::SceneConstants::default(),
+            reverb: None,
+            ducking: None,
+            headroom_db: 0.0,
+            limiter: None,
+            max_sources: None,
+            eviction_policy: EvictionPolicy::Oldest,
+            drop_fade: Duration::from_secs(0),
+        }
+    }
+}
+
+/// lowest sample rate (in Hz) of the ambisonic mix that `AmbisonicBuilder` accepts
+const MIN_SAMPLE_RATE: u32 = 1000;
+
+/// highest sample rate (in Hz) of the ambisonic mix that `AmbisonicBuilder` accepts
+const MAX_SAMPLE_RATE: u32 = 768_000;
+
 /// A builder object for creating `Ambisonic` contexts
 pub struct AmbisonicBuilder {
     device: Option<rodio::Device>,
+    device_name: Option<String>,
+    stream_handle: Option<rodio::OutputStreamHandle>,
     sample_rate: u32,
+    exact_sample_rate: bool,
+    manual_clock: bool,
+    block_size: Option<usize>,
+    mixer_config: BmixerConfig,
+    order: AmbisonicOrder,
     config: PlaybackConfiguration,
+    limiter: Option<LimiterConfig>,
+    drop_fade: Duration,
 }
 
 impl AmbisonicBuilder {
@@ -107,45 +561,176 @@ impl AmbisonicBuilder {
     }
 
     /// Build the ambisonic context
+    ///
+    /// Panics if no audio output is available. Use `try_build` to handle this case gracefully.
     pub fn build(self) -> Ambisonic {
-        let (stream, stream_handle) = if let Some(device) = self.device {
-            rodio::OutputStream::try_from_device(&device).unwrap()
-        } else {
-            rodio::OutputStream::try_default().unwrap()
+        self.try_build().expect("Cannot build ambisonic context")
+    }
+
+    /// Build the ambisonic context, or return an error if the audio output cannot be opened
+    pub fn try_build(mut self) -> Result<Ambisonic, BuildError> {
+        self.validate()?;
+
+        if self.manual_clock {
+            if self.device.is_some() || self.device_name.is_some() || self.stream_handle.is_some() {
+                return Err(BuildError::ManualClockWithOutput);
+            }
+            return Ok(self.build_offline());
+        }
+
+        let drop_fade = self.drop_fade;
+
+        if let Some(handle) = self.stream_handle.take() {
+            let output = Output::shared(handle, self.sample_rate)?;
+            let (composer, renderer_switch) = self.attach_renderer(&output.sink);
+            return Ok(Ambisonic {
+                output: Mutex::new(output),
+                composer,
+                renderer_switch,
+                rendered_frames: AtomicU64::new(0),
+                drop_fade,
+            });
+        }
+
+        if let Some(name) = self.device_name.take() {
+            let devices = cpal::default_host().output_devices()?;
+            let device = find_by_name(devices, |d| d.name().ok(), &name)
+                .ok_or(BuildError::DeviceNotFound(name))?;
+            self.device = Some(device);
+        }
+
+        let device = match self.device.take() {
+            Some(device) => device,
+            None => cpal::default_host()
+                .default_output_device()
+                .ok_or(rodio::StreamError::NoDevice)?,
         };
 
-        let sink = rodio::Sink::try_new(&stream_handle).unwrap();
+        let exact_rate = Some(self.sample_rate).filter(|_| self.exact_sample_rate);
+        let output = Output::open(&device, exact_rate)?;
 
-        let (mixer, controller) = bmixer::bmixer(self.sample_rate);
+        let (composer, renderer_switch) = self.attach_renderer(&output.sink);
 
-        match self.config {
-            PlaybackConfiguration::Stereo(cfg) => {
-                let output = renderer::BstreamStereoRenderer::new(mixer, cfg);
-                sink.append(output);
-            }
+        Ok(Ambisonic {
+            output: Mutex::new(output),
+            composer,
+            renderer_switch,
+            rendered_frames: AtomicU64::new(0),
+            drop_fade,
+        })
+    }
 
-            PlaybackConfiguration::Hrtf(cfg) => {
-                let output = renderer::BstreamHrtfRenderer::new(mixer, cfg);
-                sink.append(output);
-            }
+    /// Build an ambisonic context for offline rendering
+    ///
+    /// The context does not play on an audio device. Instead, the rendered output is obtained
+    /// with `Ambisonic::render_offline` or `Ambisonic::drain_output`. Any device selected with
+    /// `with_device` is ignored. Offline contexts need no sound card, which makes them useful for
+    /// testing audio logic deterministically, e.g. on CI. Panics if the settings are invalid
+    /// (see `try_build`).
+    pub fn build_offline(self) -> Ambisonic {
+        if let Err(e) = self.validate() {
+            panic!("Cannot build ambisonic context: {}", e);
         }
+        let output = Output::offline(self.sample_rate);
+        let drop_fade = self.drop_fade;
+        let (composer, renderer_switch) = self.attach_renderer(&output.sink);
 
         Ambisonic {
-            sink,
-            output_stream: stream,
-            composer: controller,
+            output: Mutex::new(output),
+            composer,
+            renderer_switch,
+            rendered_frames: AtomicU64::new(0),
+            drop_fade,
+        }
+    }
+
+    /// Check the settings that do not depend on the audio device
+    fn validate(&self) -> Result<(), BuildError> {
+        if !(MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&self.sample_rate) {
+            return Err(BuildError::InvalidSampleRate(self.sample_rate));
+        }
+        if self.block_size == Some(0) {
+            return Err(BuildError::InvalidBlockSize(0));
         }
+        self.config.validate(self.sample_rate)
+    }
+
+    /// Construct the mixer and append the configured renderer to the sink
+    ///
+    /// Returns the composer and the renderer switch.
+    fn attach_renderer(self, sink: &rodio::Sink) -> (Arc<BmixerComposer>, Arc<RendererSwitch>) {
+        let mut mixer_config = self.mixer_config.with_order(self.order);
+        if let Some(n) = self.block_size {
+            mixer_config = mixer_config.with_block_size(n);
+        }
+        let (mixer, controller) = bmixer::bmixer_with_config(self.sample_rate, mixer_config);
+
+        let switch = Arc::new(RendererSwitch {
+            pending: Mutex::new(None),
+            has_pending: AtomicBool::new(false),
+            pending_channels: AtomicU16::new(0),
+            retired: Mutex::new(None),
+            channels: AtomicU16::new(self.config.channels()),
+            sample_rate: self.sample_rate,
+            order: self.order,
+            limiter: self.limiter,
+            released: Mutex::new(None),
+            epoch: Instant::now(),
+            last_output: AtomicU64::new(0),
+        });
+
+        let renderer = switch.make_renderer(mixer, self.config);
+        let output = SwitchableRenderer::new(renderer, switch.clone());
+        sink.append(output);
+
+        (controller, switch)
     }
 
     /// Select device (defaults to `rodio::default_output_device()`
     pub fn with_device(self, device: rodio::Device) -> Self {
         AmbisonicBuilder {
             device: Some(device),
+            device_name: None,
+            stream_handle: None,
+            ..self
+        }
+    }
+
+    /// Play into an existing output stream instead of opening a device
+    ///
+    /// The context appends its own sink to the stream, so the scene is mixed with everything
+    /// else played on the stream. Any device selected with `with_device` is ignored, and so is
+    /// `with_exact_sample_rate` because the stream's rate cannot be queried from the handle.
+    ///
+    /// The context only holds the handle. The application owns the `rodio::OutputStream` and
+    /// must keep it alive for as long as the scene should be heard; once the stream is dropped,
+    /// the scene falls silent and `Ambisonic::output_error` reports a stalled output.
+    pub fn with_stream_handle(self, handle: rodio::OutputStreamHandle) -> Self {
+        AmbisonicBuilder {
+            stream_handle: Some(handle),
+            ..self
+        }
+    }
+
+    /// Select the first output device whose name contains `name` (ignoring case)
+    ///
+    /// The device is looked up when the context is built; `try_build` returns
+    /// `BuildError::DeviceNotFound` if no device matches. `available_output_devices` lists the
+    /// names of all devices.
+    pub fn with_device_name(self, name: &str) -> Self {
+        AmbisonicBuilder {
+            device: None,
+            device_name: Some(name.to_owned()),
+            stream_handle: None,
             ..self
         }
     }
 
     /// Set sample rate fo the ambisonic mix
+    ///
+    /// If the output device runs at a different rate, the rendered output is resampled to the
+    /// device rate (see `Ambisonic::output_sample_rate`). Rates from 1 kHz to 768 kHz are
+    /// accepted; `try_build` returns `BuildError::InvalidSampleRate` for others.
     pub fn with_sample_rate(self, sample_rate: u32) -> Self {
         AmbisonicBuilder {
             sample_rate,
@@ -153,33 +738,696 @@ impl AmbisonicBuilder {
         }
     }
 
+    /// Refuse to resample the output
+    ///
+    /// The output stream is opened at the sample rate of the ambisonic mix, with the first of the
+    /// device's supported configurations that allows this rate, instead of the device's default
+    /// configuration. `try_build` returns `BuildError::UnsupportedSampleRate` if the device
+    /// supports no configuration with this rate.
+    pub fn with_exact_sample_rate(self) -> Self {
+        AmbisonicBuilder {
+            exact_sample_rate: true,
+            ..self
+        }
+    }
+
+    /// Advance the scene only on request instead of in real time
+    ///
+    /// `build` and `try_build` then return a context like `build_offline` that does not open an
+    /// audio device. The mixer only runs when the application renders output, with
+    /// `Ambisonic::render_block`, `Ambisonic::advance` or `Ambisonic::render_offline`. Changes
+    /// made between two calls take effect at the start of the next mixer block, so the output
+    /// depends only on the sequence of calls and not on timing, which makes tests reproducible.
+    /// `try_build` returns `BuildError::ManualClockWithOutput` if a device or stream handle was
+    /// selected as well.
+    pub fn with_manual_clock(self) -> Self {
+        AmbisonicBuilder {
+            manual_clock: true,
+            ..self
+        }
+    }
+
+    /// Set the physical constants of the scene
+    ///
+    /// See `BmixerConfig::with_constants`. A speed of sound set with `with_speed_of_sound` or a
+    /// doppler factor set with `with_doppler_factor` afterwards takes precedence.
+    pub fn with_constants(self, constants: constants::SceneConstants) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_constants(constants),
+            ..self
+        }
+    }
+
+    /// Set the speed of sound (in m/s) in the scene
+    ///
+    /// The speed of sound determines the strength of the doppler effect. Very high values
+    /// effectively disable the doppler effect. Defaults to `constants::SPEED_OF_SOUND`.
+    pub fn with_speed_of_sound(self, speed_of_sound: f32) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_speed_of_sound(speed_of_sound),
+            ..self
+        }
+    }
+
+    /// Scale the doppler effect of all sources in the scene
+    ///
+    /// At 1 (the default) the pitch shift is physically correct, 0 disables the doppler effect
+    /// globally, and values above 1 exaggerate it for effect. The factor multiplies the velocity
+    /// of sources relative to the listener, like OpenAL's `AL_DOPPLER_FACTOR`.
+    pub fn with_doppler_factor(self, factor: f32) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_doppler_factor(factor),
+            ..self
+        }
+    }
+
+    /// Set the ambisonic order of the scene (defaults to `AmbisonicOrder::First`)
+    ///
+    /// Second-order scenes localize sources more sharply at the cost of more processing. The
+    /// stereo, multi-speaker and 5.1 surround renderers decode the selected order. HRTF
+    /// rendering decodes second order only with a configuration constructed by
+    /// `HrtfConfig::from_measurements_with_order`, and AmbiX output is always first order.
+    pub fn with_order(self, order: AmbisonicOrder) -> Self {
+        AmbisonicBuilder { order, ..self }
+    }
+
+    /// Set the number of samples the mixer processes at once (defaults to 512)
+    ///
+    /// The audio backend pulls samples from the scene in buffers whose size is chosen by the
+    /// device, typically a few milliseconds worth of audio. Independent of that, the mixer
+    /// processes blocks of `n` samples, and changes made through the scene or controllers
+    /// (new sources, positions, gains, ...) take effect at the start of the next block. The
+    /// block size therefore adds up to `n / sample_rate` seconds of control latency on top of
+    /// the device buffer, e.g. about 10.7 ms for the default at 48 kHz. Small blocks react
+    /// faster, large blocks need less CPU. `try_build` returns `BuildError::InvalidBlockSize`
+    /// if `n` is 0.
+    pub fn with_block_size(self, n: usize) -> Self {
+        AmbisonicBuilder {
+            block_size: Some(n),
+            ..self
+        }
+    }
+
+    /// Add a shared room reverb to the scene (no reverb by default)
+    ///
+    /// The reverb produces a diffuse sound field from the reverb sends of all sources. See
+    /// `BstreamConfig::with_reverb_send` to control how much of a source is reverberated.
+    pub fn with_reverb(self, config: ReverbConfig) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_reverb(config),
+            ..self
+        }
+    }
+
+    /// Duck sources marked as duckable while sources marked as duck triggers are loud
+    ///
+    /// No ducking by default. See `DuckConfig` and `BstreamConfig::as_duck_trigger`.
+    pub fn with_ducking(self, config: DuckConfig) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_ducking(config),
+            ..self
+        }
+    }
+
+    /// Emphasize sources in the direction the listener is facing (disabled by default)
+    ///
+    /// Sources within `angle / 2` degrees of the listener's forward direction (see
+    /// `Ambisonic::set_listener_orientation`) play at `front_gain`, and sources directly behind
+    /// the listener at `rear_gain`, with a linear transition in between. E.g. a 60º cone with
+    /// gains of 1.2 and 0.6 gently draws attention to what the listener looks at.
+    pub fn with_focus(self, angle: f32, front_gain: f32, rear_gain: f32) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_focus(angle, front_gain, rear_gain),
+            ..self
+        }
+    }
+
+    /// Attenuate the mix of all sources by `db` decibels to leave headroom (defaults to 0)
+    ///
+    /// Simultaneous sources add up and may overload the output, even if each of them plays
+    /// below full scale. E.g. 8 equal sources peak about 18 dB above a single one. The
+    /// headroom is a fixed gain and does not change with the signal; combine it with
+    /// `with_limiter` to catch the remaining peaks.
+    pub fn with_headroom_db(self, db: f32) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_headroom(db),
+            ..self
+        }
+    }
+
+    /// Limit the peaks of the rendered output (disabled by default)
+    ///
+    /// The limiter runs after the renderer, so it applies to every playback configuration. See
+    /// `LimiterConfig` for details.
+    pub fn with_limiter(self, config: LimiterConfig) -> Self {
+        AmbisonicBuilder {
+            limiter: Some(config),
+            ..self
+        }
+    }
+
+    /// Fade the scene out over the given duration when the context is dropped (defaults to 0)
+    ///
+    /// Without a fade, dropping the context cuts off all sound at once, which may click. With a
+    /// fade, dropping the context blocks the dropping thread until the scene has faded to
+    /// silence and the output had time to play the fade, then releases the output.
+    pub fn with_drop_fade(self, duration: Duration) -> Self {
+        AmbisonicBuilder {
+            drop_fade: duration,
+            ..self
+        }
+    }
+
+    /// Limit the number of simultaneously playing sources (unlimited by default)
+    ///
+    /// When playing a new source would exceed the limit, another source is stopped according
+    /// to the eviction policy. See `BmixerConfig::with_max_streams` for details.
+    pub fn with_max_sources(self, n: usize) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_max_streams(n),
+            ..self
+        }
+    }
+
+    /// Set the policy for stopping sources when there are too many (defaults to `Oldest`)
+    pub fn with_eviction_policy(self, policy: EvictionPolicy) -> Self {
+        AmbisonicBuilder {
+            mixer_config: self.mixer_config.with_eviction_policy(policy),
+            ..self
+        }
+    }
+
     /// Set playback configuration
     pub fn with_config(self, config: PlaybackConfiguration) -> Self {
         AmbisonicBuilder { config, ..self }
     }
+
+    /// Apply scene settings, e.g. loaded from a configuration file
+    ///
+    /// Replaces all settings except the selected device, `with_exact_sample_rate` and
+    /// `with_manual_clock`.
+    pub fn with_settings(self, settings: SceneSettings) -> Self {
+        let mut mixer_config = BmixerConfig::new()
+            .with_constants(settings.constants)
+            .with_eviction_policy(settings.eviction_policy)
+            .with_headroom(settings.headroom_db);
+        if let Some(n) = settings.max_sources {
+            mixer_config = mixer_config.with_max_streams(n);
+        }
+        if let Some(reverb) = settings.reverb {
+            mixer_config = mixer_config.with_reverb(reverb);
+        }
+        if let Some(ducking) = settings.ducking {
+            mixer_config = mixer_config.with_ducking(ducking);
+        }
+        AmbisonicBuilder {
+            sample_rate: settings.sample_rate,
+            block_size: settings.block_size,
+            mixer_config,
+            order: settings.order,
+            config: settings.playback,
+            limiter: settings.limiter,
+            drop_fade: settings.drop_fade,
+            ..self
+        }
+    }
 }
 
 impl Default for AmbisonicBuilder {
     fn default() -> Self {
         AmbisonicBuilder {
             device: None,
+            device_name: None,
+            stream_handle: None,
             sample_rate: 48000,
+            exact_sample_rate: false,
+            manual_clock: false,
+            block_size: None,
+            mixer_config: BmixerConfig::default(),
+            order: AmbisonicOrder::First,
             config: PlaybackConfiguration::default(),
+            limiter: None,
+            drop_fade: Duration::from_secs(0),
         }
     }
 }
 
-/// High-level Ambisonic Context.
-///
-/// Stops playing all sounds when dropped.
-pub struct Ambisonic {
-    // We need to hold on to Sink and Stream to keep the Audio alive
-    #[allow(dead_code)]
+/// Renderer that decodes the scene's mixer output
+trait Renderer: rodio::Source<Item = f32> + Send {
+    /// Mutable reference to the mixer
+    fn mixer_mut(&mut self) -> &mut BstreamMixer;
+}
+
+macro_rules! impl_renderer {
+    ($($r:ident),*) => {
+        $(impl Renderer for renderer::$r<BstreamMixer> {
+            fn mixer_mut(&mut self) -> &mut BstreamMixer {
+                self.get_mut()
+            }
+        })*
+    };
+}
+
+impl_renderer!(
+    BstreamStereoRenderer,
+    BstreamHrtfRenderer,
+    BstreamTransauralRenderer,
+    BstreamMultiSpeakerRenderer,
+    BstreamSurround51Renderer,
+    BstreamAmbixRenderer
+);
+
+/// Renderer whose output passes through a limiter
+struct LimitedRenderer {
+    renderer: Box<dyn Renderer>,
+    limiter: limiter::Limiter,
+    frame: Vec<f32>,
+    next_channel: usize,
+}
+
+impl LimitedRenderer {
+    fn new(renderer: Box<dyn Renderer>, config: &LimiterConfig) -> Self {
+        LimitedRenderer {
+            limiter: limiter::Limiter::new(config, renderer.sample_rate()),
+            frame: vec![0.0; renderer.channels() as usize],
+            next_channel: 0,
+            renderer,
+        }
+    }
+}
+
+impl Renderer for LimitedRenderer {
+    fn mixer_mut(&mut self) -> &mut BstreamMixer {
+        self.renderer.mixer_mut()
+    }
+}
+
+impl Iterator for LimitedRenderer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.next_channel == 0 {
+            for x in self.frame.iter_mut() {
+                *x = self.renderer.next()?;
+            }
+            self.limiter.process(&mut self.frame);
+        }
+        let x = self.frame[self.next_channel];
+        self.next_channel = (self.next_channel + 1) % self.frame.len();
+        Some(x)
+    }
+}
+
+impl rodio::Source for LimitedRenderer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.renderer.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.renderer.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Construct the renderer for a playback configuration
+fn make_renderer(
+    mixer: BstreamMixer,
+    config: PlaybackConfiguration,
+    order: AmbisonicOrder,
+) -> Box<dyn Renderer> {
+    match config {
+        PlaybackConfiguration::Stereo(mut cfg) => {
+            cfg.set_order(order);
+            Box::new(renderer::BstreamStereoRenderer::new(mixer, cfg))
+        }
+
+        PlaybackConfiguration::Hrtf(cfg) => {
+            Box::new(renderer::BstreamHrtfRenderer::new(mixer, cfg))
+        }
+
+        PlaybackConfiguration::Transaural(cfg) => {
+            Box::new(renderer::BstreamTransauralRenderer::new(mixer, cfg))
+        }
+
+        PlaybackConfiguration::MultiSpeaker(cfg) => {
+            let cfg = cfg.with_order(order);
+            Box::new(renderer::BstreamMultiSpeakerRenderer::new(mixer, cfg))
+        }
+
+        PlaybackConfiguration::Surround51(cfg) => {
+            let cfg = cfg.with_order(order);
+            Box::new(renderer::BstreamSurround51Renderer::new(mixer, cfg))
+        }
+
+        PlaybackConfiguration::Ambix => Box::new(renderer::BstreamAmbixRenderer::new(mixer)),
+    }
+}
+
+/// number of output samples between two reports that the output is alive
+const OUTPUT_REPORT_INTERVAL: u32 = 1024;
+
+/// time without output after which the audio device is considered dead
+const OUTPUT_STALL_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// time `Ambisonic::reconnect` waits for the old output to release the renderer
+const RENDERER_RELEASE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// State shared between the context and the renderer that plays on its output
+struct RendererSwitch {
+    // renderer waiting to replace the current one; it decodes a placeholder mixer until it
+    // takes over the scene's mixer
+    pending: Mutex<Option<Box<dyn Renderer>>>,
+    has_pending: AtomicBool,
+    // number of output channels of the pending renderer, so that the output only locks
+    // `pending` where the renderer may take over
+    pending_channels: AtomicU16,
+    // replaced renderer, which the context drops so that the audio thread does not deallocate
+    retired: Mutex<Option<Box<dyn Renderer>>>,
+    // number of output channels of the renderer that is playing
+    channels: AtomicU16,
+    sample_rate: u32,
+    order: AmbisonicOrder,
+    limiter: Option<LimiterConfig>,
+
+    // the renderer returns here when its output is dropped, to be attached to a new output
+    released: Mutex<Option<Box<dyn Renderer>>>,
+
+    // time (in ms since `epoch`) when the output last requested samples
+    epoch: Instant,
+    last_output: AtomicU64,
+}
+
+impl RendererSwitch {
+    /// Construct the renderer for a playback configuration, followed by the limiter
+    fn make_renderer(
+        &self,
+        mixer: BstreamMixer,
+        config: PlaybackConfiguration,
+    ) -> Box<dyn Renderer> {
+        let renderer = make_renderer(mixer, config, self.order);
+        match &self.limiter {
+            Some(cfg) => Box::new(LimitedRenderer::new(renderer, cfg)),
+            None => renderer,
+        }
+    }
+
+    fn report_output(&self) {
+        let now = self.epoch.elapsed().as_millis() as u64;
+        self.last_output.store(now, Ordering::Relaxed);
+    }
+
+    /// Time since the output last requested samples
+    fn stalled_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last_output.load(Ordering::Relaxed));
+        self.epoch.elapsed().saturating_sub(last)
+    }
+}
+
+/// number of output frames between two points where the number of output channels may change
+const SWITCH_SPAN_FRAMES: usize = 1024;
+
+/// Output source that can replace its renderer during playback
+///
+/// The output is divided into spans of `SWITCH_SPAN_FRAMES` frames, which rodio reads as
+/// separate frames of constant channel count. Renderers with the same number of channels take
+/// over at the next frame; renderers with a different number only at the next span.
+struct SwitchableRenderer {
+    // only `None` while the renderer is being replaced
+    renderer: Option<Box<dyn Renderer>>,
+    switch: Arc<RendererSwitch>,
+    channels: u16,
+    next_channel: u16,
+    // samples left in the current span
+    span_remaining: usize,
+    samples_until_report: u32,
+}
+
+impl SwitchableRenderer {
+    fn new(renderer: Box<dyn Renderer>, switch: Arc<RendererSwitch>) -> Self {
+        switch.report_output();
+        let channels = renderer.channels();
+        switch.channels.store(channels, Ordering::SeqCst);
+        SwitchableRenderer {
+            renderer: Some(renderer),
+            switch,
+            channels,
+            next_channel: 0,
+            span_remaining: SWITCH_SPAN_FRAMES * channels as usize,
+            samples_until_report: OUTPUT_REPORT_INTERVAL,
+        }
+    }
+
+    /// Replace the renderer by the pending one, if it may take over at this frame
+    fn apply_pending(&mut self) {
+        let mut pending = lock(&self.switch.pending);
+        let may_switch = match &*pending {
+            Some(renderer) => renderer.channels() == self.channels || self.span_remaining == 0,
+            None => true,
+        };
+        if !may_switch {
+            return;
+        }
+        if let Some(mut renderer) = pending.take() {
+            // the new renderer takes over the scene's mixer, and leaves its placeholder behind
+            let mut old = self.renderer.take().unwrap();
+            std::mem::swap(old.mixer_mut(), renderer.mixer_mut());
+            self.channels = renderer.channels();
+            self.switch.channels.store(self.channels, Ordering::SeqCst);
+            self.renderer = Some(renderer);
+            *lock(&self.switch.retired) = Some(old);
+        }
+        self.switch.has_pending.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Drop for SwitchableRenderer {
+    fn drop(&mut self) {
+        if let Some(mut renderer) = self.renderer.take() {
+            // finish the current frame so that a new output starts with the first channel
+            while self.next_channel != 0 {
+                renderer.next();
+                self.next_channel = (self.next_channel + 1) % self.channels;
+            }
+            *lock(&self.switch.released) = Some(renderer);
+        }
+    }
+}
+
+impl Iterator for SwitchableRenderer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.samples_until_report -= 1;
+        if self.samples_until_report == 0 {
+            self.switch.report_output();
+            self.samples_until_report = OUTPUT_REPORT_INTERVAL;
+        }
+
+        let x = self.renderer.as_mut().unwrap().next();
+        self.next_channel = (self.next_channel + 1) % self.channels;
+        self.span_remaining -= 1;
+
+        // renderers are only replaced between frames, so channels stay in order; the switch
+        // happens right after the last sample so that rodio sees the new channel count
+        if self.next_channel == 0
+            && self.switch.has_pending.load(Ordering::SeqCst)
+            && (self.span_remaining == 0
+                || self.switch.pending_channels.load(Ordering::SeqCst) == self.channels)
+        {
+            self.apply_pending();
+        }
+        if self.span_remaining == 0 {
+            self.span_remaining = SWITCH_SPAN_FRAMES * self.channels as usize;
+        }
+        x
+    }
+}
+
+impl rodio::Source for SwitchableRenderer {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.span_remaining)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.renderer.as_ref().unwrap().sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Errors of the audio output of a playing `Ambisonic` context
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum OutputError {
+    /// The audio device has not requested any samples for the given time, e.g. because it was
+    /// disconnected
+    Stalled(Duration),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputError::Stalled(t) => {
+                write!(f, "audio device has not played for {} ms", t.as_millis())
+            }
+        }
+    }
+}
+
+impl Error for OutputError {}
+
+/// Stream that keeps an audio device playing
+#[allow(dead_code)]
+enum DeviceStream {
+    // opened by rodio with the device's default configuration
+    Default(rodio::OutputStream),
+    // opened at the exact sample rate of the mix
+    ExactRate(cpal::Stream),
+}
+
+/// Audio output of an `Ambisonic` context
+struct Output {
     sink: rodio::Sink,
-    #[allow(dead_code)]
-    output_stream: rodio::OutputStream,
+    // We need to hold on to the stream to keep the Audio alive
+    stream: Option<DeviceStream>,
+
+    // Stream owned by the application that the output is attached to
+    handle: Option<rodio::OutputStreamHandle>,
+
+    // Only offline contexts pull their output manually
+    offline: Option<rodio::queue::SourcesQueueOutput<f32>>,
+    sample_rate: u32,
+}
+
+impl Output {
+    /// Open an output stream on the device, at `exact_rate` if given
+    fn open(device: &rodio::Device, exact_rate: Option<u32>) -> Result<Self, BuildError> {
+        if let Some(rate) = exact_rate {
+            return Output::open_at_rate(device, rate);
+        }
+
+        // rodio opens the stream with the device's default config
+        let sample_rate = device.default_output_config()?.sample_rate().0;
+        let (stream, stream_handle) = rodio::OutputStream::try_from_device(device)?;
+        let sink = rodio::Sink::try_new(&stream_handle)?;
+        Ok(Output {
+            sink,
+            stream: Some(DeviceStream::Default(stream)),
+            handle: None,
+            offline: None,
+            sample_rate,
+        })
+    }
+
+    /// Open an output stream on the device with a supported config at the sample rate
+    ///
+    /// Configs with the default number of channels and float samples are preferred.
+    fn open_at_rate(device: &rodio::Device, sample_rate: u32) -> Result<Self, BuildError> {
+        let default = device.default_output_config()?;
+        let config = device
+            .supported_output_configs()?
+            .filter(|c| (c.min_sample_rate().0..=c.max_sample_rate().0).contains(&sample_rate))
+            .max_by_key(|c| {
+                (
+                    c.channels() == default.channels(),
+                    c.sample_format() == cpal::SampleFormat::F32,
+                )
+            })
+            .ok_or(BuildError::UnsupportedSampleRate {
+                requested: sample_rate,
+                device: default.sample_rate().0,
+            })?
+            .with_sample_rate(cpal::SampleRate(sample_rate));
+
+        // the stream pulls from an idle sink, mapping the renderer's channels to the device's
+        let (sink, queue) = rodio::Sink::new_idle();
+        let samples =
+            rodio::source::UniformSourceIterator::new(queue, config.channels(), sample_rate);
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => play_samples::<f32>(device, &config.config(), samples),
+            cpal::SampleFormat::I16 => play_samples::<i16>(device, &config.config(), samples),
+            cpal::SampleFormat::U16 => play_samples::<u16>(device, &config.config(), samples),
+        }?;
+        stream.play().map_err(rodio::StreamError::from)?;
+
+        Ok(Output {
+            sink,
+            stream: Some(DeviceStream::ExactRate(stream)),
+            handle: None,
+            offline: None,
+            sample_rate,
+        })
+    }
+
+    /// Attach an output to a stream owned by the application
+    fn shared(handle: rodio::OutputStreamHandle, sample_rate: u32) -> Result<Self, BuildError> {
+        let sink = rodio::Sink::try_new(&handle)?;
+        Ok(Output {
+            sink,
+            stream: None,
+            handle: Some(handle),
+            offline: None,
+            sample_rate,
+        })
+    }
+
+    /// Create an output that is pulled manually
+    fn offline(sample_rate: u32) -> Self {
+        let (sink, output) = rodio::Sink::new_idle();
+        Output {
+            sink,
+            stream: None,
+            handle: None,
+            offline: Some(output),
+            sample_rate,
+        }
+    }
+}
+
+/// Build an output stream on the device that plays the samples of `source`
+fn play_samples<T: cpal::Sample>(
+    device: &rodio::Device,
+    config: &cpal::StreamConfig,
+    mut source: impl Iterator<Item = f32> + Send + 'static,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            for sample in data.iter_mut() {
+                *sample = T::from(&source.next().unwrap_or(0.0));
+            }
+        },
+        // errors show up as a stalled output (see `Ambisonic::output_error`)
+        |_| {},
+    )
+}
+
+/// High-level Ambisonic Context.
+///
+/// Stops playing all sounds when dropped.
+pub struct Ambisonic {
+    output: Mutex<Output>,
 
     composer: Arc<BmixerComposer>,
+    renderer_switch: Arc<RendererSwitch>,
+    // frames rendered with `render_offline`, to locate the boundaries of mixer blocks
+    rendered_frames: AtomicU64,
+
+    // how long the scene fades out when the context is dropped
+    drop_fade: Duration,
 }
 
 impl Ambisonic {
@@ -220,4 +1468,1424 @@ impl Ambisonic {
         self.composer
             .play(input, BstreamConfig::new().with_position(pos))
     }
+
+    /// Play a single-channel `Source` at a position and wait until it has finished
+    ///
+    /// Blocks the calling thread, but not the audio thread, for the duration of the source.
+    /// Useful for simple scripts; see `SoundController::wait`.
+    pub fn play_at_blocking<I>(&self, input: I, pos: [f32; 3])
+    where
+        I: rodio::Source<Item = f32> + Send + 'static,
+    {
+        self.play_at(input, pos).wait()
+    }
+
+    /// Add a `Source` of any sample type to the sound scene at a position relative to the
+    /// listener
+    ///
+    /// Like `play_at`, but the samples are converted to `f32` first. Integer samples are scaled
+    /// so that their full range maps to [-1, 1]. Useful for decoders that produce `i16` samples.
+    #[inline(always)]
+    pub fn play_at_convert<I>(&self, input: I, pos: [f32; 3]) -> SoundController
+    where
+        I: rodio::Source + Send + 'static,
+        I::Item: rodio::Sample + Send,
+    {
+        self.play_at(input.convert_samples::<f32>(), pos)
+    }
+
+    /// Add a single-channel `Source` to the sound scene at a direction and distance from the
+    /// listener.
+    ///
+    /// Angles are in degrees; see the `math` module for their conventions.
+    #[inline(always)]
+    pub fn play_at_spherical<I>(
+        &self,
+        input: I,
+        azimuth: f32,
+        elevation: f32,
+        distance: f32,
+    ) -> SoundController
+    where
+        I: rodio::Source<Item = f32> + Send + 'static,
+    {
+        let pos = math::spherical_to_cartesian(azimuth, elevation, distance);
+        self.play_at(input, pos)
+    }
+
+    /// Add a single-channel `Source` to the sound scene that starts playing at a given sample.
+    ///
+    /// `start_sample` refers to the scene's clock (see `current_sample`). Sources scheduled for
+    /// the same sample start in sync, regardless of when they were added. Sources whose start
+    /// sample has already passed start immediately.
+    #[inline(always)]
+    pub fn play_at_time<I>(&self, input: I, pos: [f32; 3], start_sample: u64) -> SoundController
+    where
+        I: rodio::Source<Item = f32> + Send + 'static,
+    {
+        self.composer.play(
+            input,
+            BstreamConfig::new()
+                .with_position(pos)
+                .with_start_sample(start_sample),
+        )
+    }
+
+    /// Sample rate of the audio output
+    ///
+    /// This is the rate the output device runs at. It differs from the sample rate of the
+    /// ambisonic mix if the device does not support that rate, in which case the rendered output
+    /// is resampled. Offline contexts, and contexts playing into a stream set with
+    /// `AmbisonicBuilder::with_stream_handle`, report the rate of the mix.
+    pub fn output_sample_rate(&self) -> u32 {
+        lock(&self.output).sample_rate
+    }
+
+    /// Check whether the audio device is still playing
+    ///
+    /// Returns an error if the device has stopped requesting samples for a while, which usually
+    /// means that it was disconnected. Use `reconnect` to continue playback on another device.
+    /// Offline contexts never report errors.
+    pub fn output_error(&self) -> Option<OutputError> {
+        let on_device = lock(&self.output).offline.is_none();
+        let stalled = self.renderer_switch.stalled_for();
+        if on_device && stalled > OUTPUT_STALL_TIMEOUT {
+            Some(OutputError::Stalled(stalled))
+        } else {
+            None
+        }
+    }
+
+    /// Continue playback on the default output device
+    ///
+    /// Closes the current audio output and opens a new one. The scene is preserved: all sources
+    /// keep playing where they were. Useful after the device was disconnected (see
+    /// `output_error`). Offline contexts get a fresh output that continues rendering the scene.
+    /// Contexts playing into an application's stream attach a new sink to the same stream.
+    ///
+    /// Returns `BuildError::RendererNotReleased` if the old output does not let go of the scene
+    /// within 100 ms, e.g. because rodio has not yet noticed that its sink was dropped. The new
+    /// output then stays silent until `reconnect` is called again.
+    pub fn reconnect(&self) -> Result<(), BuildError> {
+        let mut output = lock(&self.output);
+        let new_output = if let Some(handle) = output.handle.clone() {
+            Output::shared(handle, output.sample_rate)?
+        } else if let Some(stream) = &output.stream {
+            let device = cpal::default_host()
+                .default_output_device()
+                .ok_or(rodio::StreamError::NoDevice)?;
+            let exact_rate = match stream {
+                DeviceStream::Default(_) => None,
+                DeviceStream::ExactRate(_) => Some(output.sample_rate),
+            };
+            Output::open(&device, exact_rate)?
+        } else {
+            Output::offline(output.sample_rate)
+        };
+
+        // dropping the old output releases the renderer
+        drop(std::mem::replace(&mut *output, new_output));
+        let start = Instant::now();
+        let renderer = loop {
+            if let Some(renderer) = lock(&self.renderer_switch.released).take() {
+                break renderer;
+            }
+            if start.elapsed() > RENDERER_RELEASE_TIMEOUT {
+                return Err(BuildError::RendererNotReleased);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+        let renderer = SwitchableRenderer::new(renderer, self.renderer_switch.clone());
+        output.sink.append(renderer);
+        Ok(())
+    }
+
+    /// Current sample of the scene's clock
+    ///
+    /// See `BmixerComposer::current_sample`.
+    pub fn current_sample(&self) -> u64 {
+        self.composer.current_sample()
+    }
+
+    /// Add a single-channel `Source` to the sound scene at a position in world coordinates.
+    ///
+    /// Unlike `play_at`, the position is fixed in the world and the source's direction changes
+    /// when the listener moves (see `set_listener_position`).
+    #[inline(always)]
+    pub fn play_at_world<I>(&self, input: I, pos: [f32; 3]) -> SoundController
+    where
+        I: rodio::Source<Item = f32> + Send + 'static,
+    {
+        self.composer
+            .play(input, BstreamConfig::new().with_world_position(pos))
+    }
+
+    /// Create an empty group of sources that move and change level together
+    ///
+    /// See `SoundGroup` for details.
+    pub fn create_group(&self) -> SoundGroup {
+        SoundGroup::new(self.composer.clone())
+    }
+
+    /// Decode a finite `Source` once, so that it can be played many times
+    ///
+    /// See `sources::PreparedSound` for details.
+    pub fn prepare<I>(&self, input: I) -> sources::PreparedSound
+    where
+        I: rodio::Source<Item = f32>,
+    {
+        sources::PreparedSound::new(input)
+    }
+
+    /// Play a prepared sound at a position relative to the listener
+    ///
+    /// Each call starts an independent playback of the shared samples, without decoding or
+    /// copying the sound again.
+    #[inline(always)]
+    pub fn play_prepared_at(
+        &self,
+        prepared: &sources::PreparedSound,
+        pos: [f32; 3],
+    ) -> SoundController {
+        self.play_at(prepared.source(), pos)
+    }
+
+    /// Add a single-channel `Source` to the sound scene that loops a section of its input.
+    ///
+    /// The source plays up to sample `loop_end`, then seamlessly repeats the section from sample
+    /// `loop_start` to `loop_end` until it is stopped. The input is buffered up to `loop_end` on
+    /// the calling thread. Returns an error if the loop is empty or the input has more than one
+    /// channel. See `sources::Loop` for details.
+    #[inline(always)]
+    pub fn play_looping_at<I>(
+        &self,
+        input: I,
+        pos: [f32; 3],
+        loop_start: usize,
+        loop_end: usize,
+    ) -> Result<SoundController, sources::LoopError>
+    where
+        I: rodio::Source<Item = f32>,
+    {
+        let source = sources::Loop::new(input, loop_start, loop_end)?;
+        Ok(self.play_at(source, pos))
+    }
+
+    /// Add a single-channel `Source` that bypasses spatialization.
+    ///
+    /// The source plays equally from all speakers (or in both ears), but still goes through the
+    /// scene's master volume. It is not attenuated with distance and has no doppler effect;
+    /// the position methods of the returned controller have no audible effect.
+    #[inline(always)]
+    pub fn play_direct<I>(&self, input: I) -> SoundController
+    where
+        I: rodio::Source<Item = f32> + Send + 'static,
+    {
+        self.composer
+            .play(input, BstreamConfig::new().with_direct())
+    }
+
+    /// Add a two-channel `Source` to the sound scene, with both channels placed at separate
+    /// positions relative to the listener.
+    ///
+    /// Returns a controller object that can be used to control both channels during playback.
+    /// Panics if the source does not have exactly two channels.
+    pub fn play_stereo_at<I>(
+        &self,
+        input: I,
+        left_pos: [f32; 3],
+        right_pos: [f32; 3],
+    ) -> StereoSoundController
+    where
+        I: rodio::Source<Item = f32> + Send + 'static,
+    {
+        self.composer.play_stereo(
+            input,
+            BstreamConfig::new().with_position(left_pos),
+            BstreamConfig::new().with_position(right_pos),
+        )
+    }
+
+    /// Play a recorded first-order *B-format* sound field, such as an ambience recording
+    ///
+    /// `layout` gives the channel order and normalization of the four-channel input. The field
+    /// surrounds the listener and cannot be placed at a position, but it can be turned with the
+    /// returned controller. Panics if the source does not have exactly four channels.
+    pub fn play_bformat<I>(&self, input: I, layout: BformatLayout) -> FieldController
+    where
+        I: rodio::Source<Item = f32> + Send + 'static,
+    {
+        self.composer.play_bformat(input, layout)
+    }
+
+    /// Render the next `n_frames` samples of each output channel
+    ///
+    /// Returns the interleaved output of the renderer. Panics if the context was not built with
+    /// `AmbisonicBuilder::build_offline`. If the number of output channels changes during the
+    /// call (see `set_speaker_layout`), the frames after the change have the new number of
+    /// channels.
+    pub fn render_offline(&self, n_frames: usize) -> Vec<f32> {
+        let mut samples = Vec::with_capacity(n_frames * self.output_channels() as usize);
+        self.pull_offline(n_frames, |x| samples.push(x));
+        samples
+    }
+
+    /// Pull `n_frames` frames from the offline output, passing each sample to `f`
+    fn pull_offline(&self, n_frames: usize, mut f: impl FnMut(f32)) {
+        let mut output = lock(&self.output);
+        let output = output
+            .offline
+            .as_mut()
+            .expect("only offline contexts can be rendered manually");
+        for _ in 0..n_frames {
+            let channels = self.output_channels() as usize;
+            output.by_ref().take(channels).for_each(&mut f);
+        }
+        self.rendered_frames
+            .fetch_add(n_frames as u64, Ordering::SeqCst);
+    }
+
+    /// Render up to the end of the current mixer block
+    ///
+    /// Returns the interleaved output of the renderer: a whole block (see
+    /// `AmbisonicBuilder::with_block_size`), or the rest of a block that was partially rendered
+    /// with `render_offline` or `advance`. Changes made before the call take effect at the start
+    /// of the next block, so with a manual clock (see `AmbisonicBuilder::with_manual_clock`)
+    /// they are heard at a known sample. Panics if the context plays on an audio device.
+    pub fn render_block(&self) -> Vec<f32> {
+        let block_size = self.composer.block_size() as u64;
+        let position = self.rendered_frames.load(Ordering::SeqCst) % block_size;
+        self.render_offline((block_size - position) as usize)
+    }
+
+    /// Advance the scene by `n_frames` samples, discarding the output
+    ///
+    /// Like `render_offline`, but without collecting the output. Panics if the context plays on
+    /// an audio device.
+    pub fn advance(&self, n_frames: usize) {
+        self.pull_offline(n_frames, |_| {});
+    }
+
+    /// Number of channels of the rendered output
+    ///
+    /// This is the number of channels of the playback configuration, which the audio device
+    /// may map to a different number of channels.
+    pub fn output_channels(&self) -> u16 {
+        self.renderer_switch.channels.load(Ordering::SeqCst)
+    }
+
+    /// Render until all sources in the scene have finished playing, for at most `max_duration`
+    ///
+    /// Returns the interleaved output of the renderer, in whole mixer blocks. Rendering stops at
+    /// the first block boundary after `max_duration` if sources are still playing, e.g.
+    /// infinite, paused or sustained ones; `active_streams` tells whether the scene finished.
+    /// Panics if the context was not built with `AmbisonicBuilder::build_offline`.
+    pub fn drain_output(&self, max_duration: Duration) -> Vec<f32> {
+        let block_frames = self.composer.block_size();
+        let max_frames = max_duration.as_secs_f64() * self.output_sample_rate() as f64;
+        let mut output = Vec::new();
+        let mut rendered = 0;
+        loop {
+            output.extend(self.render_offline(block_frames));
+            rendered += block_frames;
+            if self.active_streams() == 0 || rendered as f64 >= max_frames {
+                return output;
+            }
+        }
+    }
+
+    /// Render the events of a timeline over the given duration
+    ///
+    /// The timeline starts at the current sample of the scene's clock (see `current_sample`),
+    /// and each event starts sample-accurately at its start time. Positions are interpolated
+    /// between keyframes from block to block, and moving sources are heard with the doppler
+    /// effect of their velocity between keyframes. Returns the interleaved output of the
+    /// renderer, including other sources playing in the scene. Events still playing at the end
+    /// are stopped. Panics if the context was not built with `AmbisonicBuilder::build_offline`.
+    pub fn render_timeline(&self, timeline: Timeline, duration: Duration) -> Vec<f32> {
+        let sample_rate = self.output_sample_rate() as f64;
+        let block_frames = self.composer.block_size();
+        let block_duration = Duration::from_secs_f64(block_frames as f64 / sample_rate);
+        let start = self.current_sample();
+
+        let mut events: Vec<_> = timeline
+            .events
+            .into_iter()
+            .map(|event| {
+                let start_sample = start + (event.start.as_secs_f64() * sample_rate).round() as u64;
+                let mut config = BstreamConfig::new()
+                    .with_start_sample(start_sample)
+                    .with_position_smoothing(block_duration);
+                if let Some(pos) = event.keyframes.position_at(Duration::from_secs(0)) {
+                    config = config.with_position(pos);
+                }
+                let controller = self.composer.play(event.source, config);
+                (start_sample, event.keyframes, controller)
+            })
+            .collect();
+
+        let n_frames = (duration.as_secs_f64() * sample_rate).round() as usize;
+        let mut output = Vec::with_capacity(n_frames * self.output_channels() as usize);
+        let mut rendered = 0;
+        while rendered < n_frames {
+            let n = block_frames.min(n_frames - rendered);
+            // move sources towards where they are at the end of the block
+            let block_end = start + (rendered + n) as u64;
+            for (start_sample, keyframes, controller) in &mut events {
+                if keyframes.is_moving() && *start_sample < block_end {
+                    let t = (block_end - *start_sample) as f64 / sample_rate;
+                    let t = Duration::from_secs_f64(t);
+                    controller.set_velocity(keyframes.velocity_at(t));
+                    if let Some(pos) = keyframes.position_at(t) {
+                        controller.adjust_position(pos);
+                    }
+                }
+            }
+            output.extend(self.render_offline(n));
+            rendered += n;
+        }
+
+        for (_, _, controller) in events {
+            controller.stop();
+        }
+        output
+    }
+
+    /// Replace the renderer while the scene keeps playing
+    ///
+    /// The scene's sources are not affected; only the decoding of the *B-format* stream
+    /// changes, e.g. to switch between stereo speakers and headphones. The new renderer takes
+    /// over at the next output frame. The renderer is constructed on the calling thread.
+    ///
+    /// Returns `BuildError::ChannelCountChanged` if the new configuration has a different
+    /// number of output channels than the current one, and the errors of `try_build` for
+    /// invalid output channels or HRTFs sampled at a different rate than the scene.
+    pub fn set_playback_configuration(&self, cfg: PlaybackConfiguration) -> Result<(), BuildError> {
+        let current = self.output_channels();
+        if cfg.channels() != current {
+            return Err(BuildError::ChannelCountChanged {
+                current,
+                requested: cfg.channels(),
+            });
+        }
+        self.switch_renderer(cfg)
+    }
+
+    /// Decode the scene to a new speaker layout while it keeps playing
+    ///
+    /// Replaces the renderer with a multi-speaker renderer for `config`, whatever the current
+    /// playback configuration, e.g. to add speakers to a growing installation. The scene's
+    /// sources are not affected. If the number of output channels changes, the new layout takes
+    /// over within 1024 output frames, and `output_channels` reports the new number from then
+    /// on; otherwise it takes over at the next output frame. Returns the errors of `try_build`
+    /// for a layout without speakers or with an invalid output channel map.
+    ///
+    /// Only offline contexts change their number of output channels. An audio device keeps
+    /// the channel count of the configuration it was opened with, whatever layout the context
+    /// was built with: if the new layout has more channels than the device, the extra channels
+    /// are not played; if it has fewer, rodio fills the remaining device channels with copies
+    /// of the last one.
+    pub fn set_speaker_layout(&self, config: MultiSpeakerConfig) -> Result<(), BuildError> {
+        self.switch_renderer(PlaybackConfiguration::MultiSpeaker(config))
+    }
+
+    /// Construct the renderer for a playback configuration, and hand it to the output
+    fn switch_renderer(&self, cfg: PlaybackConfiguration) -> Result<(), BuildError> {
+        cfg.validate(self.renderer_switch.sample_rate)?;
+        let switch = &self.renderer_switch;
+
+        // the renderer decodes a silent placeholder until it takes over the scene's mixer
+        let placeholder_config = BmixerConfig::new().with_block_size(1);
+        let (placeholder, _) = bmixer::bmixer_with_config(switch.sample_rate, placeholder_config);
+        let renderer = switch.make_renderer(placeholder, cfg);
+
+        // renderers are dropped here rather than on the audio thread
+        let mut pending = lock(&switch.pending);
+        switch
+            .pending_channels
+            .store(renderer.channels(), Ordering::SeqCst);
+        let replaced = pending.replace(renderer);
+        switch.has_pending.store(true, Ordering::SeqCst);
+        drop(pending);
+        drop(replaced);
+        drop(lock(&switch.retired).take());
+        Ok(())
+    }
+
+    /// Number of sound sources currently playing in the scene
+    pub fn active_streams(&self) -> usize {
+        self.composer.active_streams()
+    }
+
+    /// Pause all sounds in the scene
+    ///
+    /// The scene fades out quickly and no source advances until `resume_all` is called. See
+    /// `BmixerComposer::pause_all`.
+    pub fn pause_all(&self) {
+        self.composer.pause_all()
+    }
+
+    /// Resume the scene after `pause_all`
+    pub fn resume_all(&self) {
+        self.composer.resume_all()
+    }
+
+    /// Listen to a single source in isolation
+    ///
+    /// All other sources are muted until `unsolo` is called. Soloing several sources makes all
+    /// of them audible. See `BmixerComposer::solo`.
+    pub fn solo(&self, controller: &SoundController) {
+        self.composer.solo(controller)
+    }
+
+    /// Make all sources audible again after `solo`
+    pub fn unsolo(&self) {
+        self.composer.unsolo()
+    }
+
+    /// Stop all sounds in the scene
+    ///
+    /// Unlike dropping the context, the audio device stays open and new sounds can be played
+    /// right away.
+    pub fn clear(&self) {
+        self.composer.clear()
+    }
+
+    /// Set the master volume (defaults to 1)
+    ///
+    /// The volume changes smoothly to avoid clicks. See `BmixerComposer::set_volume`.
+    pub fn set_volume(&self, volume: f32) {
+        self.composer.set_volume(volume)
+    }
+
+    /// Master volume
+    pub fn volume(&self) -> f32 {
+        self.composer.volume()
+    }
+
+    /// Register a callback that receives every mixed *B-format* sample as `[w, x, y, z]`
+    ///
+    /// Useful for visualizing the sound field. See `BmixerComposer::add_bformat_tap` for details.
+    pub fn add_bformat_tap<F>(&self, f: F)
+    where
+        F: FnMut([f32; 4]) + Send + 'static,
+    {
+        self.composer.add_bformat_tap(f)
+    }
+
+    /// Register a callback that is invoked on the audio thread once per mixer block
+    ///
+    /// The callback must be real-time safe: no allocation, locking or blocking. See
+    /// `BmixerComposer::set_block_callback` for details.
+    pub fn set_block_callback<F>(&self, f: F)
+    where
+        F: FnMut(BlockInfo) + Send + 'static,
+    {
+        self.composer.set_block_callback(f)
+    }
+
+    /// State of all sources in the scene
+    ///
+    /// See `BmixerComposer::snapshot`.
+    pub fn snapshot(&self) -> Vec<SourceSnapshot> {
+        self.composer.snapshot()
+    }
+
+    /// Move many sources to new positions relative to the listener at once
+    ///
+    /// Ids are obtained with `SoundController::id`. See `BmixerComposer::update_positions`.
+    pub fn update_positions(&self, updates: &[(SourceId, [f32; 3])]) {
+        self.composer.update_positions(updates)
+    }
+
+    /// Move every source to a position computed from its current position
+    ///
+    /// See `BmixerComposer::map_positions`.
+    pub fn map_positions(&self, f: impl Fn([f32; 3]) -> [f32; 3]) {
+        self.composer.map_positions(f)
+    }
+
+    /// Set the position of the listener in world coordinates
+    ///
+    /// Together with `set_listener_orientation` this sets the full listener transform.
+    /// See `BmixerComposer::set_listener_position` for details.
+    pub fn set_listener_position(&self, pos: [f32; 3]) {
+        self.composer.set_listener_position(pos)
+    }
+
+    /// Set the orientation of the listener's head
+    ///
+    /// Rotates the whole sound scene, affecting all current and future sources.
+    /// See `BmixerComposer::set_listener_orientation` for details.
+    pub fn set_listener_orientation(&self, forward: [f32; 3], up: [f32; 3]) {
+        self.composer.set_listener_orientation(forward, up)
+    }
+
+    /// Set the orientation of the listener's head from yaw, pitch and roll (in degrees)
+    ///
+    /// Rotates the whole sound field against the head's motion, e.g. for VR head tracking. Cheap
+    /// enough to call every frame. See `BmixerComposer::set_field_rotation` for the conventions.
+    pub fn set_field_rotation(&self, yaw: f32, pitch: f32, roll: f32) {
+        self.composer.set_field_rotation(yaw, pitch, roll)
+    }
+
+    /// Set the velocity of the listener in world coordinates
+    ///
+    /// See `BmixerComposer::set_listener_velocity` for details.
+    pub fn set_listener_velocity(&self, vel: [f32; 3]) {
+        self.composer.set_listener_velocity(vel)
+    }
+}
+
+impl Drop for Ambisonic {
+    fn drop(&mut self) {
+        if self.drop_fade.is_zero() {
+            return;
+        }
+        self.composer.fade_out(self.drop_fade);
+
+        // offline contexts are not playing, so there is nothing to wait for
+        let output = lock(&self.output);
+        if output.offline.is_none() {
+            std::thread::sleep(self.drop_fade + DROP_FADE_MARGIN);
+        }
+    }
+}
+
+/// time the output gets to play the end of the fade-out before a dropped context releases it
+const DROP_FADE_MARGIN: Duration = Duration::from_millis(50);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::Source;
+
+    #[test]
+    fn offline_context_renders_without_audio_device() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        scene.play_at(sources::Constant::new(1.0, 48000), [1.0, 0.0, 0.0]);
+
+        let output = scene.render_offline(10);
+        assert_eq!(output.len(), 20);
+
+        // the source is on the right
+        let (left, right) = (output[18], output[19]);
+        assert!(right > left);
+    }
+
+    #[test]
+    fn manual_clock_renders_reproducibly() {
+        let run = || {
+            let scene = AmbisonicBuilder::new()
+                .with_config(PlaybackConfiguration::Ambix)
+                .with_block_size(64)
+                .with_manual_clock()
+                .build();
+            let mut source = scene.play_at(sources::Constant::new(0.5, 48000), [1.0, 0.0, 0.0]);
+            scene.render_offline(10);
+            assert_eq!(scene.render_block().len(), 54 * 4);
+
+            // the source moves to the front over the default smoothing of 960 samples, starting
+            // with the block at sample 64
+            source.adjust_position([0.0, 1.0, 0.0]);
+            scene.advance(448);
+            assert_eq!(scene.current_sample(), 512);
+            scene.render_block()
+        };
+
+        // sample 543 is the 480th of the transition, half way between right and front; the
+        // weights change by 5e-4 per sample
+        let output = run();
+        assert_eq!(output.len(), 64 * 4);
+        let expected = [0.5, -0.25, 0.0, 0.25];
+        assert!(output[31 * 4..32 * 4]
+            .iter()
+            .zip(expected)
+            .all(|(x, e)| (x - e).abs() < 1e-5));
+        assert_eq!(output, run());
+
+        let result = AmbisonicBuilder::new()
+            .with_device_name("speakers")
+            .with_manual_clock()
+            .try_build();
+        assert!(matches!(result, Err(BuildError::ManualClockWithOutput)));
+    }
+
+    #[test]
+    fn offline_context_outputs_at_the_mix_sample_rate() {
+        let scene = AmbisonicBuilder::new()
+            .with_sample_rate(44100)
+            .build_offline();
+        assert_eq!(scene.output_sample_rate(), 44100);
+    }
+
+    #[test]
+    #[ignore = "needs an audio device"]
+    fn contexts_play_into_an_existing_stream() {
+        // rodio only hands out stream handles for streams on a device
+        let (_stream, handle) = rodio::OutputStream::try_default().unwrap();
+        let scene = AmbisonicBuilder::new()
+            .with_stream_handle(handle.clone())
+            .try_build()
+            .unwrap();
+        let source = scene.play_at(sources::Constant::new(0.0, 48000), [1.0, 0.0, 0.0]);
+
+        // the stream pulls the scene's output
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(scene.renderer_switch.stalled_for() < Duration::from_millis(200));
+        assert!(!source.is_finished());
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected() {
+        let result = AmbisonicBuilder::new().with_sample_rate(0).try_build();
+        assert!(matches!(result, Err(BuildError::InvalidSampleRate(0))));
+
+        let result = AmbisonicBuilder::new()
+            .with_sample_rate(10_000_000)
+            .try_build();
+        assert!(matches!(result, Err(BuildError::InvalidSampleRate(_))));
+
+        let result = AmbisonicBuilder::new().with_block_size(0).try_build();
+        assert!(matches!(result, Err(BuildError::InvalidBlockSize(0))));
+
+        let result = AmbisonicBuilder::new()
+            .with_config(PlaybackConfiguration::MultiSpeaker(
+                MultiSpeakerConfig::square().with_output_channel_map(vec![0, 1, 2, 4]),
+            ))
+            .try_build();
+        assert!(matches!(result, Err(BuildError::InvalidChannelMap(_))));
+
+        let result = AmbisonicBuilder::new()
+            .with_config(PlaybackConfiguration::Surround51(
+                Surround51Config::default().with_output_channel_map(vec![0, 1, 2, 3, 4, 4]),
+            ))
+            .try_build();
+        assert!(matches!(result, Err(BuildError::InvalidChannelMap(_))));
+
+        let result = AmbisonicBuilder::new()
+            .with_config(MultiSpeakerConfig::square().with_subwoofer(5, 80.0).into())
+            .try_build();
+        assert!(matches!(
+            result,
+            Err(BuildError::InvalidSubwooferChannel(5))
+        ));
+
+        let result = AmbisonicBuilder::new()
+            .with_config(MultiSpeakerConfig::new().into())
+            .try_build();
+        assert!(matches!(result, Err(BuildError::NoSpeakers)));
+
+        let result = AmbisonicBuilder::new()
+            .with_sample_rate(44100)
+            .with_config(PlaybackConfiguration::Hrtf(HrtfConfig::default()))
+            .try_build();
+        assert!(matches!(
+            result,
+            Err(BuildError::HrtfSampleRate {
+                hrtf: 48000,
+                scene: 44100
+            })
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid sample rate")]
+    fn offline_contexts_reject_invalid_settings() {
+        AmbisonicBuilder::new().with_sample_rate(0).build_offline();
+    }
+
+    #[test]
+    fn stalled_output_is_detected() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        scene.render_offline(OUTPUT_REPORT_INTERVAL as usize);
+        assert!(scene.renderer_switch.stalled_for() < Duration::from_millis(20));
+
+        // nothing pulls the output, as if the device was gone
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(scene.renderer_switch.stalled_for() >= Duration::from_millis(30));
+        scene.render_offline(OUTPUT_REPORT_INTERVAL as usize);
+        assert!(scene.renderer_switch.stalled_for() < Duration::from_millis(20));
+
+        // offline contexts are pulled on demand and never report errors
+        assert!(scene.output_error().is_none());
+    }
+
+    #[test]
+    fn reconnecting_keeps_sources_playing() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        let sound = scene.play_at(sources::Constant::new(1.0, 48000), [1.0, 0.0, 0.0]);
+        let before = scene.render_offline(11);
+
+        scene.reconnect().unwrap();
+        let after = scene.render_offline(10);
+        assert_eq!(after.len(), 20);
+        assert_eq!(&after[18..], &before[20..]);
+        assert!(!sound.is_finished());
+        assert_eq!(scene.active_streams(), 1);
+    }
+
+    #[test]
+    fn stalled_devices_are_reported_and_reconnected() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        scene.render_offline(10);
+
+        // hold on to the output without pulling it, like a device that stopped playing
+        let stuck = lock(&scene.output).offline.take().unwrap();
+        std::thread::sleep(OUTPUT_STALL_TIMEOUT + Duration::from_millis(50));
+        assert!(matches!(
+            scene.output_error(),
+            Some(OutputError::Stalled(t)) if t > OUTPUT_STALL_TIMEOUT
+        ));
+
+        // the stuck output still holds the renderer
+        assert!(matches!(
+            scene.reconnect(),
+            Err(BuildError::RendererNotReleased)
+        ));
+
+        drop(stuck);
+        scene.reconnect().unwrap();
+        assert_eq!(scene.render_offline(10).len(), 20);
+        assert!(scene.output_error().is_none());
+    }
+
+    #[test]
+    fn prepared_sounds_play_independently() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        let tone = rodio::source::SineWave::new(440).take_duration(Duration::from_millis(100));
+        let prepared = scene.prepare(tone);
+
+        let left = scene.play_prepared_at(&prepared, [-1.0, 0.0, 0.0]);
+        scene.render_offline(2400);
+        let right = scene.play_prepared_at(&prepared, [1.0, 0.0, 0.0]);
+        assert_eq!(scene.active_streams(), 2);
+        assert_eq!(left.position(), [-1.0, 0.0, 0.0]);
+        assert_eq!(right.position(), [1.0, 0.0, 0.0]);
+
+        // the first playback ends before the second
+        scene.render_offline(3000);
+        assert!(left.is_finished());
+        assert!(!right.is_finished());
+        scene.drain_output(Duration::from_secs(1));
+        assert!(right.is_finished());
+    }
+
+    #[test]
+    fn limiter_keeps_output_below_the_threshold() {
+        let render = |n_sources, limiter: Option<LimiterConfig>| {
+            let mut builder = AmbisonicBuilder::new();
+            if let Some(cfg) = limiter {
+                builder = builder.with_limiter(cfg);
+            }
+            let scene = builder.build_offline();
+            for _ in 0..n_sources {
+                scene.play_at(sources::Constant::new(0.1, 48000), [0.0, 1.0, 0.0]);
+            }
+            scene.render_offline(1024)
+        };
+        let limiter = || Some(LimiterConfig::new().with_threshold(0.8));
+
+        let loud = render(20, None);
+        assert!(loud.iter().any(|x| x.abs() > 1.0));
+        let limited = render(20, limiter());
+        assert!(limited.iter().all(|x| x.abs() <= 0.8 + 1e-6));
+        assert!(limited.iter().any(|x| x.abs() > 0.7));
+
+        assert_eq!(render(1, limiter()), render(1, None));
+    }
+
+    #[test]
+    fn headroom_keeps_summed_sources_below_full_scale() {
+        let render = |builder: AmbisonicBuilder| {
+            let scene = builder.build_offline();
+            for _ in 0..8 {
+                scene.play_at(sources::Constant::new(0.5, 48000), [0.0, 1.0, 0.0]);
+            }
+            scene.render_offline(1024)
+        };
+        let peak = |output: Vec<f32>| output.iter().fold(0f32, |m, x| m.max(x.abs()));
+
+        let loud = peak(render(AmbisonicBuilder::new()));
+        assert!(loud > 1.0);
+
+        // 8 sources add up to 18 dB above each of them
+        let headroom = 20.0 * 8f32.log10();
+        let quiet = peak(render(AmbisonicBuilder::new().with_headroom_db(headroom)));
+        assert!(quiet < 1.0);
+        assert!((quiet - loud / 8.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn integer_sources_are_converted() {
+        let play = |scene: &Ambisonic, convert: bool| {
+            let pos = [0.0, 1.0, 0.0];
+            let sound = if convert {
+                let samples = rodio::buffer::SamplesBuffer::new(1, 48000, vec![i16::MAX / 2; 512]);
+                scene.play_at_convert(samples, pos)
+            } else {
+                let samples = rodio::buffer::SamplesBuffer::new(1, 48000, vec![0.5f32; 512]);
+                scene.play_at(samples, pos)
+            };
+            let output = scene.render_offline(256);
+            sound.stop();
+            output
+        };
+
+        let scene = AmbisonicBuilder::new().build_offline();
+        let converted = play(&scene, true);
+        let scene = AmbisonicBuilder::new().build_offline();
+        let reference = play(&scene, false);
+        for (a, b) in converted.iter().zip(&reference) {
+            assert!((a - b).abs() < 1e-4);
+        }
+        assert!(reference[200].abs() > 0.1);
+    }
+
+    #[test]
+    fn draining_renders_until_sources_finish() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        let tone = rodio::source::SineWave::new(440).take_duration(Duration::from_millis(100));
+        let sound = scene.play_at(tone, [1.0, 1.0, 0.0]);
+        assert!(!sound.is_finished());
+
+        let output = scene.drain_output(Duration::from_secs(1));
+        assert!(output.len() >= 2 * 4800);
+        assert!(output.len() < 2 * (4800 + 512));
+        assert!(output.iter().any(|&x| x.abs() > 0.1));
+
+        assert_eq!(scene.active_streams(), 0);
+        assert!(sound.is_finished());
+        let silence = scene.drain_output(Duration::from_secs(1));
+        assert!(silence.iter().all(|&x| x == 0.0));
+
+        // infinite sources are rendered up to the maximum duration
+        let sound = scene.play_at(rodio::source::SineWave::new(440), [1.0, 1.0, 0.0]);
+        let output = scene.drain_output(Duration::from_millis(100));
+        assert!(output.len() >= 2 * 4800);
+        assert!(output.len() < 2 * (4800 + 512));
+        assert!(!sound.is_finished());
+    }
+
+    #[test]
+    fn switching_renderers_keeps_sources_playing() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        for i in 0..3 {
+            scene.play_at(sources::Noise::with_seed(i, 48000), [i as f32, 1.0, 0.0]);
+        }
+        scene.render_offline(1000);
+
+        scene
+            .set_playback_configuration(HrtfConfig::default().into())
+            .unwrap();
+        let output = scene.render_offline(1000);
+        assert_eq!(scene.active_streams(), 3);
+        assert!(output[1000..].iter().any(|&x| x != 0.0));
+
+        // the replaced renderer is dropped by the context, not on the output
+        assert!(lock(&scene.renderer_switch.retired).is_some());
+        scene
+            .set_playback_configuration(StereoConfig::default().into())
+            .unwrap();
+        assert!(lock(&scene.renderer_switch.retired).is_none());
+    }
+
+    #[test]
+    fn switching_renderers_requires_valid_configurations() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        assert!(matches!(
+            scene.set_playback_configuration(PlaybackConfiguration::Ambix),
+            Err(BuildError::ChannelCountChanged {
+                current: 2,
+                requested: 4
+            })
+        ));
+
+        let scene = AmbisonicBuilder::new()
+            .with_config(MultiSpeakerConfig::square().into())
+            .build_offline();
+        let cfg = MultiSpeakerConfig::square().with_output_channel_map(vec![0, 0, 1, 2]);
+        assert!(matches!(
+            scene.set_playback_configuration(cfg.into()),
+            Err(BuildError::InvalidChannelMap(_))
+        ));
+        assert!(!scene.renderer_switch.has_pending.load(Ordering::SeqCst));
+
+        // HRTFs must match the scene's sample rate, also behind cross-talk cancellation
+        let scene = AmbisonicBuilder::new()
+            .with_sample_rate(44100)
+            .build_offline();
+        assert!(matches!(
+            scene.set_playback_configuration(HrtfConfig::default().into()),
+            Err(BuildError::HrtfSampleRate {
+                hrtf: 48000,
+                scene: 44100
+            })
+        ));
+        assert!(matches!(
+            scene.set_playback_configuration(TransauralConfig::default().into()),
+            Err(BuildError::HrtfSampleRate { .. })
+        ));
+        assert!(!scene.renderer_switch.has_pending.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn speaker_layouts_can_grow_during_playback() {
+        let scene = AmbisonicBuilder::new()
+            .with_config(MultiSpeakerConfig::square().into())
+            .build_offline();
+        scene.play_at(sources::Constant::new(0.5, 48000), [1.0, 1.0, 0.0]);
+        assert_eq!(scene.render_offline(24).len(), 24 * 4);
+
+        scene
+            .set_speaker_layout(MultiSpeakerConfig::octagon())
+            .unwrap();
+        // the current span of 1024 frames still plays on four channels
+        let output = scene.render_offline(2000);
+        assert_eq!(output.len(), 1000 * 4 + 1000 * 8);
+        assert_eq!(scene.output_channels(), 8);
+
+        let output = scene.render_offline(100);
+        assert_eq!(output.len(), 100 * 8);
+        assert!(output
+            .chunks(8)
+            .all(|frame| frame.iter().any(|&x| x != 0.0)));
+
+        // layouts without speakers are rejected, and the current one keeps playing
+        assert!(matches!(
+            scene.set_speaker_layout(MultiSpeakerConfig::new()),
+            Err(BuildError::NoSpeakers)
+        ));
+        assert_eq!(scene.render_offline(100).len(), 100 * 8);
+    }
+
+    #[test]
+    fn devices_are_matched_by_name_substring() {
+        let devices = vec![
+            Some("Built-in Output".to_owned()),
+            None,
+            Some("Focusrite USB Audio".to_owned()),
+            Some("USB Headset".to_owned()),
+        ];
+
+        let find = |pattern| find_by_name(devices.clone(), |d| d.clone(), pattern);
+        assert_eq!(find("usb"), Some(Some("Focusrite USB Audio".to_owned())));
+        assert_eq!(find("HEADSET"), Some(Some("USB Headset".to_owned())));
+        assert_eq!(find("built-in"), Some(Some("Built-in Output".to_owned())));
+        assert_eq!(find("hdmi"), None);
+    }
+
+    #[test]
+    fn output_devices_include_the_default_device() {
+        // without audio hardware the list is empty, but enumerating must not fail
+        let devices = available_output_devices();
+        assert!(devices.iter().filter(|d| d.is_default).count() <= 1);
+
+        let default_name = cpal::default_host()
+            .default_output_device()
+            .and_then(|d| d.name().ok());
+        if let Some(name) = default_name {
+            assert!(devices.iter().any(|d| d.is_default && d.name == name));
+        }
+    }
+
+    #[test]
+    fn changes_take_effect_at_block_boundaries() {
+        for &block_size in &[1, 16, 256] {
+            let scene = AmbisonicBuilder::new()
+                .with_block_size(block_size)
+                .build_offline();
+            let sound = scene.play_at(sources::Constant::new(1.0, 48000), [1.0, 0.0, 0.0]);
+            scene.render_offline(1);
+
+            sound.set_gain(0.0);
+            let output = scene.render_offline(300);
+            let right: Vec<_> = output.iter().skip(1).step_by(2).collect();
+            let n_unchanged = right.iter().take_while(|&&&x| x > 0.0).count();
+            assert_eq!(n_unchanged, block_size - 1);
+        }
+    }
+
+    #[test]
+    fn direct_sources_play_equally_in_both_ears() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        let mut sound = scene.play_direct(sources::Constant::new(1.0, 48000));
+        sound.set_position([5.0, 0.0, 0.0]);
+        sound.set_velocity([0.0, -10.0, 0.0]);
+        scene.set_listener_position([-2.0, 1.0, 0.0]);
+
+        let output = scene.render_offline(600);
+        let (left, right) = (output[1198], output[1199]);
+        assert!(left > 0.0);
+        assert_eq!(left, right);
+    }
+
+    #[test]
+    fn clearing_the_scene_keeps_it_usable() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        for i in 0..5 {
+            scene.play_at(sources::Constant::new(1.0, 48000), [i as f32, 1.0, 0.0]);
+        }
+        scene.render_offline(10);
+        assert_eq!(scene.active_streams(), 5);
+
+        scene.clear();
+        scene.render_offline(1024);
+        assert_eq!(scene.active_streams(), 0);
+        assert!(scene.render_offline(10).iter().all(|&x| x == 0.0));
+
+        scene.play_at(sources::Constant::new(1.0, 48000), [1.0, 0.0, 0.0]);
+        let output = scene.render_offline(1024);
+        assert_eq!(scene.active_streams(), 1);
+        assert!(output[2047] > 0.0);
+    }
+
+    #[test]
+    fn soloing_mutes_other_sources() {
+        let positions = [[-1.0, 1.0, 0.0], [1.0, 1.0, 0.0], [0.0, -1.0, 0.0]];
+        let play = |scene: &Ambisonic, i: usize| {
+            scene.play_at(
+                sources::Constant::new(0.1 * (i + 1) as f32, 48000),
+                positions[i],
+            )
+        };
+        let reference = |indices: &[usize]| {
+            let scene = AmbisonicBuilder::new().build_offline();
+            for &i in indices {
+                play(&scene, i);
+            }
+            scene.render_offline(2048)
+        };
+
+        let scene = AmbisonicBuilder::new().build_offline();
+        let controllers: Vec<_> = (0..3).map(|i| play(&scene, i)).collect();
+        scene.render_offline(512);
+
+        scene.solo(&controllers[1]);
+        let output = scene.render_offline(512);
+        assert_eq!(output[1022..], reference(&[1])[4094..]);
+
+        scene.solo(&controllers[2]);
+        let output = scene.render_offline(512);
+        assert_eq!(output[1022..], reference(&[1, 2])[4094..]);
+
+        // the others stay muted after the soloed sources have finished
+        controllers[1].stop();
+        controllers[2].stop();
+        let output = scene.render_offline(2048);
+        assert_eq!(scene.active_streams(), 1);
+        assert!(output[2048..].iter().all(|&x| x == 0.0));
+
+        scene.unsolo();
+        let output = scene.render_offline(512);
+        assert_eq!(output[1022..], reference(&[0])[4094..]);
+    }
+
+    #[test]
+    fn waiting_blocks_until_the_source_has_finished() {
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        // stands in for the audio thread, rendering in real time
+        let renderer = std::thread::spawn(move || {
+            let scene = AmbisonicBuilder::new().build_offline();
+            let tone = rodio::source::SineWave::new(440).take_duration(Duration::from_millis(100));
+            let sound = scene.play_at(tone, [1.0, 0.0, 0.0]);
+            sender.send(sound.clone()).unwrap();
+            while !sound.is_finished() {
+                scene.render_offline(480);
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        let sound = receiver.recv().unwrap();
+        let start = Instant::now();
+        sound.wait();
+        let elapsed = start.elapsed();
+        assert!(sound.is_finished());
+        assert!(elapsed >= Duration::from_millis(80), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+        renderer.join().unwrap();
+    }
+
+    #[test]
+    fn master_volume_scales_output() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        scene.play_at(sources::Constant::new(1.0, 48000), [1.0, 0.0, 0.0]);
+
+        // render a whole mixer block so the volume change applies to the next one
+        let full = scene.render_offline(512);
+
+        scene.set_volume(0.5);
+        assert_eq!(scene.volume(), 0.5);
+
+        let output = scene.render_offline(1000);
+        let right: Vec<_> = output.iter().skip(1).step_by(2).take(20).collect();
+        assert!(right.windows(2).all(|w| w[0] > w[1]));
+
+        let half = &output[output.len() - 2..];
+        assert_eq!(half[0], full[0] * 0.5);
+        assert_eq!(half[1], full[1] * 0.5);
+    }
+
+    #[test]
+    fn bformat_fields_from_the_left_play_on_the_left() {
+        // a plane wave from the left in AmbiX: W and Y only
+        let recording = || {
+            let samples = rodio::source::SineWave::new(440)
+                .take_duration(Duration::from_millis(100))
+                .flat_map(|x| vec![x, x, 0.0, 0.0])
+                .collect::<Vec<f32>>();
+            rodio::buffer::SamplesBuffer::new(4, 48000, samples)
+        };
+        let energy = |output: &[f32]| {
+            let left: f32 = output.iter().step_by(2).map(|x| x * x).sum();
+            let right: f32 = output.iter().skip(1).step_by(2).map(|x| x * x).sum();
+            (left, right)
+        };
+
+        let scene = AmbisonicBuilder::new().build_offline();
+        scene.play_bformat(recording(), BformatLayout::Ambix);
+        let (left, right) = energy(&scene.render_offline(2048));
+        assert!(left > 4.0 * right);
+
+        // turned around, the recording's left side faces to the right
+        let scene = AmbisonicBuilder::new().build_offline();
+        let field = scene.play_bformat(recording(), BformatLayout::Ambix);
+        field.set_orientation([0.0, -1.0, 0.0], [0.0, 0.0, 1.0]);
+        let (left, right) = energy(&scene.render_offline(2048));
+        assert!(right > 4.0 * left);
+    }
+
+    #[test]
+    fn dropped_contexts_fade_out() {
+        let scene = AmbisonicBuilder::new()
+            .with_drop_fade(Duration::from_millis(10))
+            .build_offline();
+        scene.play_at(sources::Constant::new(0.5, 48000), [-1.0, 0.0, 0.0]);
+        let level = scene.render_offline(512)[1022];
+
+        // what dropping the context starts before it releases the output
+        scene.composer.fade_out(scene.drop_fade);
+        let left: Vec<f32> = scene.render_offline(1024).into_iter().step_by(2).collect();
+        assert!(left[0] > 0.9 * level);
+        assert!(left.windows(2).all(|w| w[1] <= w[0]));
+        assert!(left[240] < 0.6 * level && left[240] > 0.4 * level);
+        assert_eq!(left[480..], vec![0.0; 544][..]);
+
+        // offline contexts have nothing to wait for
+        let start = Instant::now();
+        drop(scene);
+        assert!(start.elapsed() < DROP_FADE_MARGIN);
+    }
+
+    #[test]
+    fn block_callback_runs_once_per_block() {
+        let scene = AmbisonicBuilder::new().with_block_size(64).build_offline();
+        scene.play_at(sources::Constant::new(0.5, 48000), [1.0, 0.0, 0.0]);
+
+        let blocks = Arc::new(Mutex::new(Vec::new()));
+        let sink = blocks.clone();
+        scene.set_block_callback(move |info| sink.lock().unwrap().push(info));
+
+        scene.render_offline(640);
+        let blocks = blocks.lock().unwrap();
+        assert_eq!(blocks.len(), 10);
+        for (i, info) in blocks.iter().enumerate() {
+            assert_eq!(info.start_sample, 64 * i as u64);
+            assert_eq!(info.len, 64);
+            assert_eq!(info.active_sources, 1);
+        }
+    }
+
+    #[test]
+    fn field_rotation_turns_the_scene() {
+        let energy = |yaw: f32| {
+            let scene = AmbisonicBuilder::new().build_offline();
+            scene.play_at(sources::Constant::new(0.5, 48000), [0.0, 1.0, 0.0]);
+            scene.set_field_rotation(yaw, 0.0, 0.0);
+            let output = scene.render_offline(512);
+            let left: f32 = output.iter().step_by(2).map(|x| x * x).sum();
+            let right: f32 = output.iter().skip(1).step_by(2).map(|x| x * x).sum();
+            (left, right)
+        };
+
+        let (left, right) = energy(0.0);
+        assert!((left - right).abs() < 1e-3 * left);
+
+        // the head turns to the left, so the front source is heard on the right
+        let (left, right) = energy(90.0);
+        assert!(right > 4.0 * left);
+    }
+
+    #[test]
+    fn focus_emphasizes_sources_the_listener_faces() {
+        let level = |pos| {
+            let scene = AmbisonicBuilder::new()
+                .with_config(PlaybackConfiguration::Ambix)
+                .with_focus(60.0, 1.0, 0.25)
+                .build_offline();
+            scene.set_listener_orientation([1.0, 0.0, 0.0], [0.0, 0.0, 1.0]);
+            scene.play_at(sources::Constant::new(0.5, 48000), pos);
+            // the omnidirectional channel does not depend on the direction
+            let output = scene.render_offline(1024);
+            output[4 * 1000]
+        };
+
+        // the listener looks to the right
+        let front = level([1.0, 0.0, 0.0]);
+        let behind = level([-1.0, 0.0, 0.0]);
+        let side = level([0.0, 1.0, 0.0]);
+        assert!((behind / front - 0.25).abs() < 1e-4);
+        assert!(side < front && side > behind);
+    }
+
+    #[test]
+    fn detached_sources_play_to_their_end() {
+        let played = |config: BstreamConfig, detach: bool| {
+            let scene = AmbisonicBuilder::new()
+                .with_config(PlaybackConfiguration::Ambix)
+                .build_offline();
+            let beep = sources::Constant::new(0.5, 48000).take_duration(Duration::from_millis(10));
+            let controller = scene.composer.play(beep, config);
+            if detach {
+                controller.detach();
+            }
+            let output = scene.drain_output(Duration::from_secs(1));
+            output.iter().step_by(4).filter(|&&w| w != 0.0).count()
+        };
+
+        let full = played(BstreamConfig::new(), false);
+        assert!(full > 470);
+        assert_eq!(
+            played(BstreamConfig::new().with_stop_on_drop(true), true),
+            full
+        );
+    }
+
+    #[test]
+    fn panicking_callbacks_do_not_stop_the_scene() {
+        let scene = AmbisonicBuilder::new()
+            .with_config(PlaybackConfiguration::Ambix)
+            .build_offline();
+        scene.set_block_callback(|_| panic!("block callback"));
+        scene.add_bformat_tap(|_| panic!("tap"));
+
+        let config = BstreamConfig::new()
+            .with_position([1.0, 1.0, 0.0])
+            .with_attenuation_fn(Arc::new(|_| panic!("attenuation")));
+        let mut faulty = scene
+            .composer
+            .play(sources::Constant::new(0.5, 48000), config);
+        let _other = scene.play_at(sources::Constant::new(0.5, 48000), [-1.0, 1.0, 0.0]);
+
+        for _ in 0..4 {
+            faulty.set_position([2.0, 1.0, 0.0]);
+            let output = scene.render_offline(1024);
+            assert!(output.iter().step_by(4).all(|&w| w > 0.0));
+        }
+    }
+
+    #[test]
+    fn timelines_schedule_and_move_events() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        let beep = || sources::Constant::new(0.5, 48000).take_duration(Duration::from_millis(100));
+        let timeline = Timeline::new()
+            .with_event(
+                TimelineEvent::new(Duration::from_millis(100), beep())
+                    .with_keyframe(Duration::from_secs(0), [-1.0, 1.0, 0.0]),
+            )
+            .with_event(
+                TimelineEvent::new(Duration::from_millis(300), beep())
+                    .with_keyframe(Duration::from_secs(0), [-1.0, 1.0, 0.0])
+                    .with_keyframe(Duration::from_millis(100), [1.0, 1.0, 0.0]),
+            );
+
+        let output = scene.render_timeline(timeline, Duration::from_millis(500));
+        assert_eq!(output.len(), 2 * 24000);
+        let frame = |i: usize| (output[2 * i], output[2 * i + 1]);
+        let onsets: Vec<_> = (1..24000)
+            .filter(|&i| frame(i - 1) == (0.0, 0.0) && frame(i) != (0.0, 0.0))
+            .collect();
+        assert_eq!(onsets, vec![4800, 14400]);
+
+        // the first event stays on the left, the second moves from the left to the right
+        let (left, right) = frame(9000);
+        assert!(left > 1.5 * right);
+        let (left, right) = frame(14500);
+        assert!(left > 1.5 * right);
+        let (left, right) = frame(19000);
+        assert!(right > 1.5 * left);
+        assert_eq!(scene.active_streams(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn scene_settings_survive_a_round_trip() {
+        let settings = || SceneSettings {
+            sample_rate: 44100,
+            block_size: Some(128),
+            playback: MultiSpeakerConfig::square()
+                .with_weighting(DecoderWeighting::MaxRe)
+                .with_subwoofer(4, 80.0)
+                .into(),
+            reverb: Some(ReverbConfig::default()),
+            ..SceneSettings::default()
+        };
+        let json = serde_json::to_string(&settings()).unwrap();
+        let restored: SceneSettings = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+
+        let render = |settings| {
+            let scene = AmbisonicBuilder::new()
+                .with_settings(settings)
+                .build_offline();
+            scene.play_at(sources::Ramp::new(44100), [1.0, 2.0, 0.0]);
+            (scene.output_channels(), scene.render_offline(1000))
+        };
+        let (channels, output) = render(restored);
+        assert_eq!(channels, 5);
+        assert_eq!((channels, output), render(settings()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn missing_settings_take_default_values() {
+        let settings: SceneSettings =
+            serde_json::from_str(r#"{"sample_rate": 44100, "playback": {"Stereo": {}}}"#).unwrap();
+        assert_eq!(settings.sample_rate, 44100);
+        assert_eq!(settings.block_size, None);
+        assert!(matches!(
+            settings.playback,
+            PlaybackConfiguration::Stereo(_)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_settings_are_checked_like_the_setters() {
+        let reverb: ReverbConfig = serde_json::from_str(r#"{"mix": 2.0}"#).unwrap();
+        assert_eq!(reverb, ReverbConfig::new().with_mix(1.0));
+        let limiter: LimiterConfig = serde_json::from_str(r#"{"threshold": -0.5}"#).unwrap();
+        assert_eq!(limiter, LimiterConfig::new().with_threshold(0.5));
+        let ducking: DuckConfig =
+            serde_json::from_str(r#"{"threshold": -0.1, "ratio": 0.5}"#).unwrap();
+        assert_eq!(
+            ducking,
+            DuckConfig::new().with_threshold(0.1).with_ratio(1.0)
+        );
+        let constants: constants::SceneConstants =
+            serde_json::from_str(r#"{"min_distance": -1.0}"#).unwrap();
+        assert_eq!(constants.min_distance(), 0.0);
+        let envelope: Envelope = serde_json::from_str(r#"{"sustain": 1.5}"#).unwrap();
+        assert_eq!(envelope, Envelope::new().with_sustain(1.0));
+
+        let json = serde_json::to_string(&HrtfConfig::default())
+            .unwrap()
+            .replace(r#""crossfeed":0.0"#, r#""crossfeed":2.0"#);
+        assert!(json.ends_with(r#""crossfeed":2.0}"#));
+        let hrtf: HrtfConfig = serde_json::from_str(&json).unwrap();
+        assert!(serde_json::to_string(&hrtf)
+            .unwrap()
+            .ends_with(r#""crossfeed":1.0}"#));
+    }
 }
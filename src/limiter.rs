@@ -0,0 +1,147 @@
+//! Peak limiting of the rendered output
+
+use std::time::Duration;
+
+/// Settings of the output limiter
+///
+/// The limiter keeps the rendered output below a ceiling. Whenever a frame of output would
+/// exceed the threshold, all channels are attenuated immediately just enough to stay at the
+/// threshold. Afterwards the gain recovers smoothly over the release time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "LimiterConfigRaw")
+)]
+pub struct LimiterConfig {
+    threshold: f32,
+    release: Duration,
+}
+
+impl Default for LimiterConfig {
+    fn default() -> Self {
+        LimiterConfig {
+            threshold: 1.0,
+            release: Duration::from_millis(100),
+        }
+    }
+}
+
+impl LimiterConfig {
+    /// Create new `LimiterConfig` with default settings.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the highest absolute sample value of the output (defaults to 1).
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold.abs();
+        self
+    }
+
+    /// Set the time constant of the gain's recovery after a peak (defaults to 100 ms).
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+}
+
+/// Deserialized fields of a `LimiterConfig`, which are applied through its setters
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(default)]
+struct LimiterConfigRaw {
+    threshold: f32,
+    release: Duration,
+}
+
+#[cfg(feature = "serde")]
+impl Default for LimiterConfigRaw {
+    fn default() -> Self {
+        let config = LimiterConfig::default();
+        LimiterConfigRaw {
+            threshold: config.threshold,
+            release: config.release,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<LimiterConfigRaw> for LimiterConfig {
+    fn from(raw: LimiterConfigRaw) -> Self {
+        LimiterConfig::new()
+            .with_threshold(raw.threshold)
+            .with_release(raw.release)
+    }
+}
+
+/// Peak limiter that processes one multi-channel frame at a time
+pub(crate) struct Limiter {
+    threshold: f32,
+    // per-frame coefficient of the exponential gain recovery
+    release: f32,
+    gain: f32,
+}
+
+impl Limiter {
+    pub fn new(config: &LimiterConfig, sample_rate: u32) -> Self {
+        let release_frames = config.release.as_secs_f32() * sample_rate as f32;
+        Limiter {
+            threshold: config.threshold,
+            release: if release_frames > 0.0 {
+                (-1.0 / release_frames).exp()
+            } else {
+                0.0
+            },
+            gain: 1.0,
+        }
+    }
+
+    /// Attenuate a frame of samples in place
+    #[inline(always)]
+    pub fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame.iter().fold(0.0, |p: f32, x| p.max(x.abs()));
+        let limit = if peak > self.threshold {
+            self.threshold / peak
+        } else {
+            1.0
+        };
+
+        let recovered = 1.0 - (1.0 - self.gain) * self.release;
+        self.gain = recovered.min(limit);
+        if self.gain < 1.0 {
+            for x in frame {
+                *x *= self.gain;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peaks_are_limited_and_gain_recovers() {
+        let config = LimiterConfig::new()
+            .with_threshold(0.5)
+            .with_release(Duration::from_millis(10));
+        let mut limiter = Limiter::new(&config, 1000);
+
+        let mut frame = [2.0, -1.0];
+        limiter.process(&mut frame);
+        assert_eq!(frame, [0.5, -0.25]);
+
+        // the gain recovers gradually after the peak
+        let gains: Vec<f32> = (0..50)
+            .map(|_| {
+                let mut frame = [0.1, 0.0];
+                limiter.process(&mut frame);
+                frame[0] / 0.1
+            })
+            .collect();
+        assert!(gains.windows(2).all(|g| g[1] >= g[0]));
+        assert!(gains[0] < 0.5);
+        assert!(gains[49] > 0.99);
+    }
+}
@@ -0,0 +1,72 @@
+//! Conversion between cartesian and spherical coordinates
+//!
+//! Positions use the crate's cartesian convention: +x points to the right, +y to the front, and
+//! +z up. In spherical coordinates, the *azimuth* is measured in degrees counter-clockwise (seen
+//! from above) from the front, so 90° is left and -90° is right. The *elevation* is measured in
+//! degrees from the horizontal plane, positive upwards. These are the same angles that are used
+//! for speaker directions in `MultiSpeakerConfig`.
+
+/// Convert azimuth and elevation (in degrees) and distance to a cartesian position
+pub fn spherical_to_cartesian(azimuth: f32, elevation: f32, distance: f32) -> [f32; 3] {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+    [
+        -azimuth.sin() * elevation.cos() * distance,
+        azimuth.cos() * elevation.cos() * distance,
+        elevation.sin() * distance,
+    ]
+}
+
+/// Convert a cartesian position to azimuth, elevation (in degrees), and distance
+///
+/// The azimuth is in the range (-180°, 180°] and the elevation in [-90°, 90°]. Both are 0 for the
+/// origin.
+pub fn cartesian_to_spherical(pos: [f32; 3]) -> (f32, f32, f32) {
+    let [x, y, z] = pos;
+    let horizontal = x.hypot(y);
+    let azimuth = match (-x).atan2(y).to_degrees() {
+        a if a <= -180.0 => a + 360.0,
+        a => a,
+    };
+    let elevation = z.atan2(horizontal).to_degrees();
+    let distance = horizontal.hypot(z);
+    (azimuth, elevation, distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for (a, b) in a.iter().zip(&b) {
+            assert!((a - b).abs() < 1e-3, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn known_directions() {
+        assert_close(spherical_to_cartesian(0.0, 0.0, 2.0), [0.0, 2.0, 0.0]);
+        assert_close(spherical_to_cartesian(90.0, 0.0, 1.0), [-1.0, 0.0, 0.0]);
+        assert_close(spherical_to_cartesian(-90.0, 0.0, 1.0), [1.0, 0.0, 0.0]);
+        assert_close(spherical_to_cartesian(180.0, 0.0, 1.0), [0.0, -1.0, 0.0]);
+        assert_close(spherical_to_cartesian(30.0, 90.0, 3.0), [0.0, 0.0, 3.0]);
+    }
+
+    #[test]
+    fn round_trip_is_lossless() {
+        for &(az, el, dist) in &[
+            (0.0, 0.0, 1.0),
+            (45.0, 30.0, 2.5),
+            (-135.0, -60.0, 0.5),
+            (180.0, 10.0, 4.0),
+            (-30.0, 89.0, 1.0),
+        ] {
+            let (a, e, d) = cartesian_to_spherical(spherical_to_cartesian(az, el, dist));
+            assert_close([a, e, d], [az, el, dist]);
+        }
+
+        for &pos in &[[1.0, 2.0, 3.0], [-0.5, -0.5, 0.0], [0.0, -1.0, -2.0]] {
+            let (az, el, dist) = cartesian_to_spherical(pos);
+            assert_close(spherical_to_cartesian(az, el, dist), pos);
+        }
+    }
+}
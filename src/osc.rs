@@ -0,0 +1,311 @@
+//! Control sound sources over OSC
+//!
+//! An `OscServer` listens for [Open Sound Control](http://opensoundcontrol.org) messages on a
+//! UDP socket and applies them to registered sound sources. Sources are identified by integer
+//! ids that are assigned with `OscServer::register`. The following messages are understood:
+//!
+//! - `/source/<id>/pos x y z`: move source `<id>` to position `[x, y, z]` relative to the
+//!   listener (see `SoundController::adjust_position`)
+//! - `/source/<id>/gain g`: change the gain of source `<id>` (see `SoundController::adjust_gain`)
+//! - `/listener/pos x y z`: move the listener (see `Ambisonic::set_listener_position`)
+//!
+//! Arguments may be sent as 32-bit floats or integers. Messages may be bundled. Messages that
+//! cannot be parsed or that address unregistered ids are ignored and counted
+//! (see `OscServer::ignored_messages`).
+//!
+//! This module is only available with the `osc` feature.
+
+use crate::sync::lock;
+use crate::{Ambisonic, BmixerComposer, SoundController};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// how often the server thread checks whether it should stop
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Receives OSC messages on its own thread and applies them to registered sources
+///
+/// The server thread stops when the server is dropped.
+pub struct OscServer {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// State shared between the server and its thread
+struct Shared {
+    sources: Mutex<HashMap<u32, SoundController>>,
+    composer: Arc<BmixerComposer>,
+    stop: AtomicBool,
+    ignored: AtomicUsize,
+}
+
+impl OscServer {
+    /// Listen for OSC messages on the given UDP address and apply them to `scene`
+    pub fn bind<A: ToSocketAddrs>(addr: A, scene: &Ambisonic) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let local_addr = socket.local_addr()?;
+
+        let shared = Arc::new(Shared {
+            sources: Mutex::new(HashMap::new()),
+            composer: scene.composer.clone(),
+            stop: AtomicBool::new(false),
+            ignored: AtomicUsize::new(0),
+        });
+
+        let thread = {
+            let shared = shared.clone();
+            std::thread::spawn(move || shared.run(socket))
+        };
+
+        Ok(OscServer {
+            shared,
+            local_addr,
+            thread: Some(thread),
+        })
+    }
+
+    /// Address the server is listening on
+    ///
+    /// Useful to find out the port when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Make a source controllable under the given id
+    ///
+    /// Returns the controller previously registered under that id, if any.
+    pub fn register(&self, id: u32, controller: SoundController) -> Option<SoundController> {
+        lock(&self.shared.sources).insert(id, controller)
+    }
+
+    /// Stop controlling the source with the given id and return its controller
+    pub fn unregister(&self, id: u32) -> Option<SoundController> {
+        lock(&self.shared.sources).remove(&id)
+    }
+
+    /// Access the controller registered under the given id
+    ///
+    /// Returns `None` if no source is registered under that id.
+    pub fn with_controller<R>(
+        &self,
+        id: u32,
+        f: impl FnOnce(&mut SoundController) -> R,
+    ) -> Option<R> {
+        lock(&self.shared.sources).get_mut(&id).map(f)
+    }
+
+    /// Number of received messages that were ignored because they could not be parsed, had an
+    /// unknown address, or addressed an unregistered id
+    pub fn ignored_messages(&self) -> usize {
+        self.shared.ignored.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for OscServer {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn run(&self, socket: UdpSocket) {
+        let mut buf = [0; 1536];
+        while !self.stop.load(Ordering::SeqCst) {
+            match socket.recv(&mut buf) {
+                Ok(n) => self.handle_packet(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(_) => self.ignore(),
+            }
+        }
+    }
+
+    fn handle_packet(&self, packet: &[u8]) {
+        match parse_packet(packet) {
+            Some(messages) => {
+                for msg in messages {
+                    if !self.apply(&msg) {
+                        self.ignore();
+                    }
+                }
+            }
+            None => self.ignore(),
+        }
+    }
+
+    /// Apply a message; returns false if it was not understood.
+    fn apply(&self, msg: &Message) -> bool {
+        let parts: Vec<&str> = msg.address.split('/').skip(1).collect();
+        match (parts.as_slice(), msg.args.as_slice()) {
+            (["source", id, "pos"], &[x, y, z]) => self.with_source(id, |src| {
+                src.adjust_position([x, y, z]);
+            }),
+            (["source", id, "gain"], &[g]) => self.with_source(id, |src| src.adjust_gain(g)),
+            (["listener", "pos"], &[x, y, z]) => {
+                self.composer.set_listener_position([x, y, z]);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn with_source(&self, id: &str, f: impl FnOnce(&mut SoundController)) -> bool {
+        let id: u32 = match id.parse() {
+            Ok(id) => id,
+            Err(_) => return false,
+        };
+        match lock(&self.sources).get_mut(&id) {
+            Some(src) => {
+                f(src);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn ignore(&self) {
+        self.ignored.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// An OSC message with numeric arguments
+#[derive(Debug, PartialEq)]
+struct Message {
+    address: String,
+    args: Vec<f32>,
+}
+
+/// Parse an OSC packet, which is either a single message or a bundle of packets
+fn parse_packet(data: &[u8]) -> Option<Vec<Message>> {
+    if data.starts_with(b"#bundle\0") {
+        // skip the time tag; messages are applied as soon as they arrive
+        let mut rest = data.get(16..)?;
+        let mut messages = Vec::new();
+        while !rest.is_empty() {
+            let size = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+            let element = rest.get(4..4 + size)?;
+            messages.extend(parse_packet(element)?);
+            rest = &rest[4 + size..];
+        }
+        Some(messages)
+    } else {
+        parse_message(data).map(|msg| vec![msg])
+    }
+}
+
+fn parse_message(data: &[u8]) -> Option<Message> {
+    let (address, rest) = parse_string(data)?;
+    if !address.starts_with('/') {
+        return None;
+    }
+
+    let (tags, mut rest) = parse_string(rest)?;
+    let tags = tags.strip_prefix(',')?;
+
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        let bytes: [u8; 4] = rest.get(..4)?.try_into().ok()?;
+        args.push(match tag {
+            'f' => f32::from_be_bytes(bytes),
+            'i' => i32::from_be_bytes(bytes) as f32,
+            _ => return None,
+        });
+        rest = &rest[4..];
+    }
+
+    Some(Message {
+        address: address.to_owned(),
+        args,
+    })
+}
+
+/// Parse a null-terminated string that is padded to a multiple of four bytes
+fn parse_string(data: &[u8]) -> Option<(&str, &[u8])> {
+    let len = data.iter().position(|&b| b == 0)?;
+    let s = std::str::from_utf8(&data[..len]).ok()?;
+    let padded = (len / 4 + 1) * 4;
+    Some((s, data.get(padded..)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sources, AmbisonicBuilder};
+    use std::time::Instant;
+
+    fn encode_string(out: &mut Vec<u8>, s: &str) {
+        out.extend_from_slice(s.as_bytes());
+        out.resize((out.len() / 4 + 1) * 4, 0);
+    }
+
+    fn encode_message(address: &str, args: &[f32]) -> Vec<u8> {
+        let mut out = Vec::new();
+        encode_string(&mut out, address);
+        encode_string(&mut out, &format!(",{}", "f".repeat(args.len())));
+        for a in args {
+            out.extend_from_slice(&a.to_be_bytes());
+        }
+        out
+    }
+
+    /// Wait until `cond` holds, or panic after a while
+    fn wait_for(mut cond: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !cond() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn messages_are_parsed() {
+        let msg = encode_message("/source/3/pos", &[1.0, -2.0, 0.5]);
+        assert_eq!(
+            parse_packet(&msg),
+            Some(vec![Message {
+                address: "/source/3/pos".to_owned(),
+                args: vec![1.0, -2.0, 0.5],
+            }])
+        );
+
+        let mut bundle = b"#bundle\0\0\0\0\0\0\0\0\x01".to_vec();
+        for m in &[encode_message("/a", &[1.0]), encode_message("/b", &[])] {
+            bundle.extend_from_slice(&(m.len() as i32).to_be_bytes());
+            bundle.extend_from_slice(m);
+        }
+        let messages = parse_packet(&bundle).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].address, "/b");
+
+        assert_eq!(parse_packet(b"/truncated"), None);
+    }
+
+    #[test]
+    fn position_messages_move_registered_sources() {
+        let scene = AmbisonicBuilder::new().build_offline();
+        let server = OscServer::bind("127.0.0.1:0", &scene).unwrap();
+        let sound = scene.play_at(sources::Constant::new(1.0, 48000), [1.0, 0.0, 0.0]);
+        server.register(7, sound);
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let send = |msg: Vec<u8>| client.send_to(&msg, server.local_addr()).unwrap();
+
+        send(encode_message("/source/7/pos", &[0.0, 2.0, 3.0]));
+        wait_for(|| server.with_controller(7, |c| c.position()) == Some([0.0, 2.0, 3.0]));
+
+        send(encode_message("/source/8/pos", &[0.0, 2.0, 3.0]));
+        send(encode_message("/unknown", &[]));
+        wait_for(|| server.ignored_messages() == 2);
+    }
+}
@@ -7,40 +7,126 @@ use std::time::Duration;
 
 use rodio::Source;
 
-use crate::bformat::{Bformat, Bweights};
+use crate::bformat::{normalize, second_order_components, AmbisonicOrder, Bformat, Bweights};
+use crate::constants::SPEED_OF_SOUND;
+use crate::math;
 
 /// Stereo Playback configuration
 ///
 /// Playback over two physical speakers in front of the listener. For best results both speakers
 /// should be placed symmetrically to the left and the right at the same distance.
 ///
-/// The default setting assumes an arrangement of +/- 45º.
+/// The default setting assumes an arrangement of +/- 45º. Sources above the listener get a
+/// subtle treble boost (and sources below a subtle treble cut), which mimics how the outer ear
+/// colors sound from above and makes elevation audible in the stereo image.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
 pub struct StereoConfig {
-    left_mic: Bweights,
-    right_mic: Bweights,
+    left_dir: [f32; 3],
+    right_dir: [f32; 3],
+    order: AmbisonicOrder,
+    elevation_cue: bool,
+    pan_law: PanLaw,
 }
 
 impl StereoConfig {
     /// Set direction of the left speaker
     pub fn set_left_direction(&mut self, dir: [f32; 3]) {
-        self.left_mic = Bweights::virtual_microphone(dir, 0.5)
+        self.left_dir = dir;
     }
 
     /// Set direction of the right speaker
     pub fn set_right_direction(&mut self, dir: [f32; 3]) {
-        self.right_mic = Bweights::virtual_microphone(dir, 0.5)
+        self.right_dir = dir;
+    }
+
+    /// Set the ambisonic order to decode (defaults to `AmbisonicOrder::First`)
+    ///
+    /// First-order decoding uses cardioid virtual microphones, second-order decoding uses
+    /// narrower second-order microphones that separate the channels better. Only affects
+    /// `PanLaw::Cardioid`; the other pan laws always decode first order.
+    pub fn set_order(&mut self, order: AmbisonicOrder) {
+        self.order = order;
+    }
+
+    /// Enable or disable the spectral elevation cue (enabled by default)
+    ///
+    /// Sources in the horizontal plane sound the same either way.
+    pub fn with_elevation_cue(mut self, enabled: bool) -> Self {
+        self.elevation_cue = enabled;
+        self
+    }
+
+    /// Select how sources are panned between the speakers (defaults to `PanLaw::Cardioid`)
+    pub fn with_pan_law(mut self, law: PanLaw) -> Self {
+        self.pan_law = law;
+        self
+    }
+
+    /// Weights that decode the channel of the speaker in direction `dir`, whose partner
+    /// speaker is in direction `other`
+    fn microphone(&self, dir: [f32; 3], other: [f32; 3]) -> Bweights {
+        match (self.pan_law, self.order) {
+            (PanLaw::Cardioid, AmbisonicOrder::First) => Bweights::virtual_microphone(dir, 0.5),
+            (PanLaw::Cardioid, AmbisonicOrder::Second) => Bweights::second_order_cardioid(dir),
+            (PanLaw::EqualPower, _) => Bweights::virtual_microphone(dir, 0.0),
+            (PanLaw::Linear, _) => {
+                let (a, b) = (normalize(dir), normalize(other));
+                Bweights::virtual_microphone([a[0] - b[0], a[1] - b[1], a[2] - b[2]], 0.5)
+            }
+        }
     }
 }
 
+/// Pan laws of the stereo renderer
+///
+/// Describe how the level of a source in each speaker changes as it moves from one speaker to
+/// the other.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PanLaw {
+    /// Decode with a cardioid virtual microphone pointing at each speaker (default)
+    ///
+    /// Sources between the speakers play somewhat louder than sources at the sides.
+    #[default]
+    Cardioid,
+
+    /// Keep the total power `L² + R²` constant as sources move across the front
+    ///
+    /// Decodes with figure-of-eight virtual microphones pointing at the speakers, like a
+    /// Blumlein pair. The power is exactly constant if the speakers are 90º apart, as in the
+    /// default arrangement. Sources behind the listener play with inverted polarity.
+    EqualPower,
+
+    /// Keep the sum of the speaker gains `L + R` constant, so that the power dips by 3 dB between
+    /// the speakers
+    ///
+    /// The gains change linearly with the position of the source along the line from one
+    /// speaker to the other; sources in front and behind the listener play equally in both.
+    Linear,
+}
+
 impl Default for StereoConfig {
     fn default() -> Self {
         StereoConfig {
-            left_mic: Bweights::virtual_microphone([-1.0, 1.0, 0.0], 0.5),
-            right_mic: Bweights::virtual_microphone([1.0, 1.0, 0.0], 0.5),
+            left_dir: [-1.0, 1.0, 0.0],
+            right_dir: [1.0, 1.0, 0.0],
+            order: AmbisonicOrder::First,
+            elevation_cue: true,
+            pan_law: PanLaw::Cardioid,
         }
     }
 }
 
+/// cutoff frequency (in Hz) of the high-pass filter that shapes the elevation cue
+const ELEVATION_CUE_CUTOFF: f32 = 5000.0;
+
+/// gain of the high-passed vertical component that is added to both stereo channels
+const ELEVATION_CUE_GAIN: f32 = 0.25;
+
 /// Render a *B-format* stream to a stereo representation.
 ///
 /// Suitable for playback over two speakers arranged in front of the user.
@@ -49,18 +135,44 @@ pub struct BstreamStereoRenderer<I> {
     buffered_sample: Option<f32>,
     left_mic: Bweights,
     right_mic: Bweights,
+    // coefficient of the low-pass filter whose complement forms the elevation cue, `None` if
+    // the cue is disabled
+    elevation_coefficient: Option<f32>,
+    elevation_state: f32,
 }
 
-impl<I> BstreamStereoRenderer<I> {
+impl<I> BstreamStereoRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
     /// Construct a new stereo renderer with default settings
     pub fn new(input: I, config: StereoConfig) -> Self {
+        let sample_rate = input.sample_rate() as f32;
+        let elevation_coefficient = if config.elevation_cue {
+            Some(1.0 - (-2.0 * std::f32::consts::PI * ELEVATION_CUE_CUTOFF / sample_rate).exp())
+        } else {
+            None
+        };
+
         BstreamStereoRenderer {
             input,
             buffered_sample: None,
-            left_mic: config.left_mic,
-            right_mic: config.right_mic,
+            left_mic: config.microphone(config.left_dir, config.right_dir),
+            right_mic: config.microphone(config.right_dir, config.left_dir),
+            elevation_coefficient,
+            elevation_state: 0.0,
         }
     }
+
+    /// Consume the renderer and return its *B-format* input
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Mutable reference to the renderer's *B-format* input
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
 }
 
 impl<I> Source for BstreamStereoRenderer<I>
@@ -100,8 +212,17 @@ where
             None => {
                 let sample = self.input.next()?;
 
-                let left = self.left_mic.dot(sample);
-                let right = self.right_mic.dot(sample);
+                let mut left = self.left_mic.dot(sample);
+                let mut right = self.right_mic.dot(sample);
+
+                // treble of the vertical component, which is silent for sources in the plane
+                if let Some(a) = self.elevation_coefficient {
+                    let z = Bweights::new(0.0, 0.0, 0.0, 1.0).dot(sample);
+                    self.elevation_state += a * (z - self.elevation_state);
+                    let cue = ELEVATION_CUE_GAIN * (z - self.elevation_state);
+                    left += cue;
+                    right += cue;
+                }
 
                 // emit left channel now, and right channel next time
                 self.buffered_sample = Some(right);
@@ -111,6 +232,710 @@ where
     }
 }
 
+/// Multi-speaker playback configuration
+///
+/// Playback over an arbitrary number of physical speakers arranged around the listener. Each
+/// speaker is given by its azimuth and elevation in degrees. An azimuth of 0º is in front of
+/// the listener and positive angles turn to the left; positive elevations point upwards.
+///
+/// Speakers are expected to be at equal distance from the listener. Output channels are
+/// interleaved in the order in which speakers were added. An optional subwoofer takes the low
+/// end of the omnidirectional component on a channel of its own (see `with_subwoofer`).
+#[derive(Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct MultiSpeakerConfig {
+    speakers: Vec<[f32; 2]>,
+    order: AmbisonicOrder,
+    weighting: DecoderWeighting,
+    subwoofer: Option<(usize, f32)>,
+    highpass_mains: bool,
+    channel_map: Option<Vec<usize>>,
+}
+
+/// Per-order gains applied when decoding to speakers
+///
+/// The weightings trade the sharpness of the decoded sound field at the listener's position
+/// against the energy spread over the speakers.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecoderWeighting {
+    /// Reconstruct the sound field exactly at the center of the layout (default)
+    ///
+    /// Speakers opposite of a source play it out of phase.
+    #[default]
+    Basic,
+
+    /// Maximize the energy concentration in the direction of the source
+    ///
+    /// Gives better localization over speakers, especially for listeners off-center.
+    MaxRe,
+
+    /// Avoid any out-of-phase playback
+    ///
+    /// Sources are less focused, but no speaker plays a source with inverted polarity, which
+    /// suits large audiences.
+    InPhase,
+}
+
+impl DecoderWeighting {
+    /// Gains of the first-order and second-order components
+    fn gains(self, order: AmbisonicOrder, horizontal: bool) -> (f32, f32) {
+        use std::f32::consts::PI;
+        match (self, order, horizontal) {
+            (DecoderWeighting::Basic, _, _) => (1.0, 1.0),
+
+            // cos(lπ / (2N + 2)) in 2D, P_l of the largest root of P_(N+1) in 3D
+            (DecoderWeighting::MaxRe, AmbisonicOrder::First, true) => ((PI / 4.0).cos(), 0.0),
+            (DecoderWeighting::MaxRe, AmbisonicOrder::First, false) => (1.0 / 3f32.sqrt(), 0.0),
+            (DecoderWeighting::MaxRe, AmbisonicOrder::Second, true) => {
+                ((PI / 6.0).cos(), (PI / 3.0).cos())
+            }
+            (DecoderWeighting::MaxRe, AmbisonicOrder::Second, false) => ((0.6f32).sqrt(), 0.4),
+
+            // N!² / ((N + l)! (N - l)!) in 2D, N! (N + 1)! / ((N + l + 1)! (N - l)!) in 3D
+            (DecoderWeighting::InPhase, AmbisonicOrder::First, true) => (1.0 / 2.0, 0.0),
+            (DecoderWeighting::InPhase, AmbisonicOrder::First, false) => (1.0 / 3.0, 0.0),
+            (DecoderWeighting::InPhase, AmbisonicOrder::Second, true) => (2.0 / 3.0, 1.0 / 6.0),
+            (DecoderWeighting::InPhase, AmbisonicOrder::Second, false) => (1.0 / 2.0, 1.0 / 10.0),
+        }
+    }
+}
+
+impl MultiSpeakerConfig {
+    /// Create a new configuration without any speakers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Four horizontal speakers in a square around the listener
+    ///
+    /// Channels are ordered front-left, back-left, back-right, front-right.
+    pub fn square() -> Self {
+        [45.0, 135.0, -135.0, -45.0]
+            .iter()
+            .fold(Self::new(), |cfg, &az| cfg.with_speaker(az, 0.0))
+    }
+
+    /// Eight horizontal speakers in a regular octagon around the listener
+    ///
+    /// The first speaker is in front of the listener; the others follow counter-clockwise (to
+    /// the left) in steps of 45º.
+    pub fn octagon() -> Self {
+        (0..8).fold(Self::new(), |cfg, i| {
+            let az = i as f32 * 45.0;
+            cfg.with_speaker(if az > 180.0 { az - 360.0 } else { az }, 0.0)
+        })
+    }
+
+    /// Eight speakers at the corners of a cube around the listener
+    ///
+    /// Channels are ordered like `square` for the upper four speakers, followed by the lower
+    /// four speakers in the same order. Sources are decoded in full 3D, including elevation.
+    pub fn cube() -> Self {
+        // elevation of a cube corner as seen from the center
+        let elevation = (1.0 / 2f32.sqrt()).atan().to_degrees();
+        [elevation, -elevation]
+            .iter()
+            .fold(Self::new(), |cfg, &el| {
+                [45.0, 135.0, -135.0, -45.0]
+                    .iter()
+                    .fold(cfg, |cfg, &az| cfg.with_speaker(az, el))
+            })
+    }
+
+    /// Add a speaker at given azimuth and elevation (in degrees)
+    pub fn with_speaker(mut self, azimuth: f32, elevation: f32) -> Self {
+        self.add_speaker(azimuth, elevation);
+        self
+    }
+
+    /// Add a speaker at given azimuth and elevation (in degrees)
+    pub fn add_speaker(&mut self, azimuth: f32, elevation: f32) {
+        self.speakers.push([azimuth, elevation]);
+    }
+
+    /// Set the ambisonic order to decode (defaults to `AmbisonicOrder::First`)
+    ///
+    /// Second-order decoding needs at least six speakers in a horizontal layout, or nine
+    /// speakers in a three-dimensional layout.
+    pub fn with_order(mut self, order: AmbisonicOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Set the per-order weighting of the decoder (defaults to `DecoderWeighting::Basic`)
+    pub fn with_weighting(mut self, weighting: DecoderWeighting) -> Self {
+        self.weighting = weighting;
+        self
+    }
+
+    /// Add a subwoofer that plays the omnidirectional component below `crossover` (in Hz)
+    ///
+    /// The subwoofer gets its own output channel at index `channel`; speakers from that index on
+    /// move up by one channel. The low end is split off with a first-order low-pass filter.
+    pub fn with_subwoofer(mut self, channel: usize, crossover: f32) -> Self {
+        self.set_subwoofer(channel, crossover);
+        self
+    }
+
+    /// Add a subwoofer that plays the omnidirectional component below `crossover` (in Hz)
+    ///
+    /// Replaces any previously configured subwoofer (see `with_subwoofer`).
+    pub fn set_subwoofer(&mut self, channel: usize, crossover: f32) {
+        self.subwoofer = Some((channel, crossover));
+    }
+
+    /// Channel index and crossover frequency (in Hz) of the subwoofer, if any
+    pub fn subwoofer(&self) -> Option<(usize, f32)> {
+        self.subwoofer
+    }
+
+    /// Remove the low end that goes to the subwoofer from the main speakers
+    ///
+    /// The main speakers are high-passed with the complement of the subwoofer's low-pass
+    /// filter, so both add up to the full signal again. Has no effect without a subwoofer.
+    pub fn with_highpassed_mains(mut self, enabled: bool) -> Self {
+        self.highpass_mains = enabled;
+        self
+    }
+
+    /// Route the output channels to different channels of the audio device
+    ///
+    /// `map[i]` is the device channel that receives output channel `i`, where output channels
+    /// are numbered as described above, including the subwoofer. The map must contain every
+    /// channel index exactly once; otherwise building the context fails with
+    /// `BuildError::InvalidChannelMap`, and constructing the renderer directly panics.
+    pub fn with_output_channel_map(mut self, map: Vec<usize>) -> Self {
+        self.channel_map = Some(map);
+        self
+    }
+
+    /// Mapping from output channels to device channels, if any (see `with_output_channel_map`)
+    pub fn output_channel_map(&self) -> Option<&[usize]> {
+        self.channel_map.as_deref()
+    }
+
+    /// Number of speakers in this configuration, not counting the subwoofer
+    pub fn n_speakers(&self) -> usize {
+        self.speakers.len()
+    }
+
+    /// Number of output channels, including the subwoofer
+    pub fn n_channels(&self) -> usize {
+        self.speakers.len() + self.subwoofer.is_some() as usize
+    }
+
+    /// Decoding matrix with one row of W, X, Y, Z weights per speaker
+    ///
+    /// The multi-speaker renderer computes each speaker's signal as the dot product of its row
+    /// with the *B-format* input, so the matrix shows exactly how the renderer decodes. Rows are
+    /// in the order in which speakers were added; the subwoofer has no row. Second-order
+    /// decoders additionally weight the second-order components, which are not part of the
+    /// matrix.
+    pub fn decoder_matrix(&self) -> Vec<[f32; 4]> {
+        self.decoder_weights()
+            .iter()
+            .map(Bweights::components)
+            .collect()
+    }
+
+    /// Compute the decoding weights for each speaker.
+    fn decoder_weights(&self) -> Vec<Bweights> {
+        let n = self.speakers.len() as f32;
+
+        // a layout without elevated speakers can only reproduce the horizontal components
+        let horizontal = self.speakers.iter().all(|s| s[1] == 0.0);
+        let dim = if horizontal { 2.0 } else { 3.0 };
+        let (g1, g2) = self.weighting.gains(self.order, horizontal);
+
+        self.speakers
+            .iter()
+            .map(|&[azimuth, elevation]| {
+                let dir = speaker_direction(azimuth, elevation);
+                let bw = Bweights::new(
+                    2f32.sqrt() / n,
+                    g1 * dim * dir[0] / n,
+                    g1 * dim * dir[1] / n,
+                    g1 * dim * dir[2] / n,
+                );
+                if self.order == AmbisonicOrder::First {
+                    return bw;
+                }
+
+                let q = second_order_components(dir);
+                if horizontal {
+                    // only the two horizontal components contribute, with (3/4)·cos(2θ)
+                    bw.with_second_order(
+                        [q[0], 0.0, 0.0, q[3], 0.0].map(|q| g2 * 8.0 / 3.0 * q / n),
+                    )
+                } else {
+                    bw.with_second_order(q.map(|q| g2 * 5.0 * q / n))
+                }
+            })
+            .collect()
+    }
+}
+
+/// For each device channel, the output channel it plays, or `None` if `map` does not contain
+/// each of the `n` channels exactly once
+pub(crate) fn invert_channel_map(map: &[usize], n: usize) -> Option<Vec<usize>> {
+    if map.len() != n {
+        return None;
+    }
+    let mut inverse = vec![n; n];
+    for (channel, &target) in map.iter().enumerate() {
+        if target >= n || inverse[target] != n {
+            return None;
+        }
+        inverse[target] = channel;
+    }
+    Some(inverse)
+}
+
+/// Device channel order of a renderer with an output channel map
+fn device_channel_order(map: Option<&[usize]>, n: usize) -> Option<Vec<usize>> {
+    map.map(|map| {
+        invert_channel_map(map, n).unwrap_or_else(|| panic!("invalid output channel map {:?}", map))
+    })
+}
+
+/// Convert azimuth and elevation (in degrees) to a unit direction vector.
+fn speaker_direction(azimuth: f32, elevation: f32) -> [f32; 3] {
+    math::spherical_to_cartesian(azimuth, elevation, 1.0)
+}
+
+/// Render a *B-format* stream to an arbitrary number of speakers.
+///
+/// Produces one interleaved channel per speaker in the order given by the `MultiSpeakerConfig`.
+pub struct BstreamMultiSpeakerRenderer<I> {
+    input: I,
+    speakers: Vec<Bweights>,
+    crossover: Option<Crossover>,
+    frame: Vec<f32>,
+    // output channel played on each device channel, `None` if they are the same
+    device_order: Option<Vec<usize>>,
+    next_channel: usize,
+}
+
+/// Splits the low end off to the subwoofer
+struct Crossover {
+    channel: usize,
+    coefficient: f32,
+    sub_state: f32,
+    /// low-pass states of the main speakers, if they are high-passed
+    main_states: Option<Vec<f32>>,
+}
+
+impl Crossover {
+    /// Output channel of the given speaker
+    #[inline(always)]
+    fn speaker_channel(&self, speaker: usize) -> usize {
+        if speaker < self.channel {
+            speaker
+        } else {
+            speaker + 1
+        }
+    }
+}
+
+impl<I> BstreamMultiSpeakerRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    /// Construct a new multi-speaker renderer
+    ///
+    /// Panics if the configuration has no speakers; `AmbisonicBuilder::try_build` reports this
+    /// as `BuildError::NoSpeakers` instead.
+    pub fn new(input: I, config: MultiSpeakerConfig) -> Self {
+        assert!(
+            config.n_speakers() > 0,
+            "multi-speaker configuration requires at least one speaker"
+        );
+
+        let sample_rate = input.sample_rate() as f32;
+        let crossover = config.subwoofer.map(|(channel, fc)| {
+            assert!(
+                channel <= config.n_speakers(),
+                "subwoofer channel {} is out of range",
+                channel
+            );
+            Crossover {
+                channel,
+                coefficient: 1.0 - (-2.0 * std::f32::consts::PI * fc / sample_rate).exp(),
+                sub_state: 0.0,
+                main_states: if config.highpass_mains {
+                    Some(vec![0.0; config.n_speakers()])
+                } else {
+                    None
+                },
+            }
+        });
+
+        let n_channels = config.n_channels();
+        BstreamMultiSpeakerRenderer {
+            input,
+            speakers: config.decoder_weights(),
+            crossover,
+            frame: vec![0.0; n_channels],
+            device_order: device_channel_order(config.output_channel_map(), n_channels),
+            next_channel: n_channels,
+        }
+    }
+
+    /// Consume the renderer and return its *B-format* input
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Mutable reference to the renderer's *B-format* input
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I> Source for BstreamMultiSpeakerRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        self.frame.len() as u16
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+impl<I> Iterator for BstreamMultiSpeakerRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_channel >= self.frame.len() {
+            let sample = self.input.next()?;
+            match &mut self.crossover {
+                None => {
+                    for (out, speaker) in self.frame.iter_mut().zip(&self.speakers) {
+                        *out = speaker.dot(sample);
+                    }
+                }
+                Some(crossover) => {
+                    let a = crossover.coefficient;
+                    let omni = Bweights::new(2f32.sqrt(), 0.0, 0.0, 0.0).dot(sample);
+                    crossover.sub_state += a * (omni - crossover.sub_state);
+                    self.frame[crossover.channel] = crossover.sub_state;
+
+                    for (i, speaker) in self.speakers.iter().enumerate() {
+                        let mut x = speaker.dot(sample);
+                        if let Some(states) = &mut crossover.main_states {
+                            states[i] += a * (x - states[i]);
+                            x -= states[i];
+                        }
+                        self.frame[crossover.speaker_channel(i)] = x;
+                    }
+                }
+            }
+            self.next_channel = 0;
+        }
+
+        let channel = match &self.device_order {
+            Some(order) => order[self.next_channel],
+            None => self.next_channel,
+        };
+        self.next_channel += 1;
+        Some(self.frame[channel])
+    }
+}
+
+/// 5.1 surround playback configuration
+///
+/// Playback over five speakers at the ITU-R BS.775 angles (center at 0º, front left and right at
+/// +/- 30º, surround left and right at +/- 110º) and a subwoofer. The subwoofer receives a
+/// low-passed copy of the omnidirectional component, with a crossover frequency of 120 Hz by
+/// default.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Surround51Config {
+    lfe_crossover: Option<f32>,
+    order: AmbisonicOrder,
+    channel_map: Option<Vec<usize>>,
+}
+
+impl Surround51Config {
+    /// Set crossover frequency (in Hz) of the LFE channel
+    pub fn with_lfe_crossover(mut self, frequency: f32) -> Self {
+        self.lfe_crossover = Some(frequency);
+        self
+    }
+
+    /// Keep the LFE channel silent
+    pub fn without_lfe(mut self) -> Self {
+        self.lfe_crossover = None;
+        self
+    }
+
+    /// Set the ambisonic order to decode (defaults to `AmbisonicOrder::First`)
+    pub fn with_order(mut self, order: AmbisonicOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Route the six output channels to different channels of the audio device
+    ///
+    /// `map[i]` is the device channel that receives output channel `i` in the order L, R, C,
+    /// LFE, Ls, Rs; e.g. `vec![0, 1, 4, 5, 2, 3]` plays the surrounds on channels 2 and 3, for
+    /// interfaces wired L, R, Ls, Rs, C, LFE. See `MultiSpeakerConfig::with_output_channel_map`.
+    pub fn with_output_channel_map(mut self, map: Vec<usize>) -> Self {
+        self.channel_map = Some(map);
+        self
+    }
+
+    /// Mapping from output channels to device channels, if any (see `with_output_channel_map`)
+    pub fn output_channel_map(&self) -> Option<&[usize]> {
+        self.channel_map.as_deref()
+    }
+}
+
+impl Default for Surround51Config {
+    fn default() -> Self {
+        Surround51Config {
+            lfe_crossover: Some(120.0),
+            order: AmbisonicOrder::First,
+            channel_map: None,
+        }
+    }
+}
+
+/// Render a *B-format* stream to 5.1 surround.
+///
+/// Produces six interleaved channels in the order L, R, C, LFE, Ls, Rs.
+pub struct BstreamSurround51Renderer<I> {
+    input: I,
+    speakers: Vec<Bweights>,
+    lfe_coefficient: Option<f32>,
+    lfe_state: f32,
+    frame: [f32; 6],
+    // output channel played on each device channel, `None` if they are the same
+    device_order: Option<Vec<usize>>,
+    next_channel: usize,
+}
+
+impl<I> BstreamSurround51Renderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    /// Construct a new 5.1 surround renderer
+    pub fn new(input: I, config: Surround51Config) -> Self {
+        let speakers = MultiSpeakerConfig::new()
+            .with_speaker(30.0, 0.0)
+            .with_speaker(-30.0, 0.0)
+            .with_speaker(0.0, 0.0)
+            .with_speaker(110.0, 0.0)
+            .with_speaker(-110.0, 0.0)
+            .with_order(config.order)
+            .decoder_weights();
+
+        let sample_rate = input.sample_rate() as f32;
+        let lfe_coefficient = config
+            .lfe_crossover
+            .map(|fc| 1.0 - (-2.0 * std::f32::consts::PI * fc / sample_rate).exp());
+
+        BstreamSurround51Renderer {
+            input,
+            speakers,
+            lfe_coefficient,
+            lfe_state: 0.0,
+            frame: [0.0; 6],
+            device_order: device_channel_order(config.output_channel_map(), 6),
+            next_channel: 6,
+        }
+    }
+
+    /// Consume the renderer and return its *B-format* input
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Mutable reference to the renderer's *B-format* input
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I> Source for BstreamSurround51Renderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        6
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+impl<I> Iterator for BstreamSurround51Renderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_channel >= self.frame.len() {
+            let sample = self.input.next()?;
+
+            let lfe = match self.lfe_coefficient {
+                Some(a) => {
+                    let omni = Bweights::new(2f32.sqrt(), 0.0, 0.0, 0.0).dot(sample);
+                    self.lfe_state += a * (omni - self.lfe_state);
+                    self.lfe_state
+                }
+                None => 0.0,
+            };
+
+            let s = &self.speakers;
+            self.frame = [
+                s[0].dot(sample),
+                s[1].dot(sample),
+                s[2].dot(sample),
+                lfe,
+                s[3].dot(sample),
+                s[4].dot(sample),
+            ];
+            self.next_channel = 0;
+        }
+
+        let channel = match &self.device_order {
+            Some(order) => order[self.next_channel],
+            None => self.next_channel,
+        };
+        self.next_channel += 1;
+        Some(self.frame[channel])
+    }
+}
+
+/// Render a *B-format* stream to first-order AmbiX.
+///
+/// Produces four interleaved channels in ACN order (W, Y, Z, X) with SN3D normalization, for
+/// use with other ambisonics tools. The internal *B-format* uses FuMa normalization with the
+/// x axis pointing right, y pointing to the front and z pointing up. It is converted as follows:
+///
+/// - W = √2 · w
+/// - Y = -x (left)
+/// - Z = z (up)
+/// - X = y (front)
+///
+/// Second-order components are discarded.
+pub struct BstreamAmbixRenderer<I> {
+    input: I,
+    frame: [f32; 4],
+    next_channel: usize,
+}
+
+impl<I> BstreamAmbixRenderer<I> {
+    /// Construct a new AmbiX renderer
+    pub fn new(input: I) -> Self {
+        BstreamAmbixRenderer {
+            input,
+            frame: [0.0; 4],
+            next_channel: 4,
+        }
+    }
+
+    /// Consume the renderer and return its *B-format* input
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Mutable reference to the renderer's *B-format* input
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+}
+
+impl<I> Source for BstreamAmbixRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        4
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+impl<I> Iterator for BstreamAmbixRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next_channel >= self.frame.len() {
+            let sample = self.input.next()?;
+            self.frame = [
+                Bweights::new(2f32.sqrt(), 0.0, 0.0, 0.0).dot(sample),
+                Bweights::new(0.0, -1.0, 0.0, 0.0).dot(sample),
+                Bweights::new(0.0, 0.0, 0.0, 1.0).dot(sample),
+                Bweights::new(0.0, 0.0, 1.0, 0.0).dot(sample),
+            ];
+            self.next_channel = 0;
+        }
+
+        let out = self.frame[self.next_channel];
+        self.next_channel += 1;
+        Some(out)
+    }
+}
+
 /// Head-Related-Transfer-Function configuration
 ///
 /// Intended to be used for playback over headphones. HRTFs describe delay and level differences
@@ -118,12 +943,97 @@ where
 ///
 /// The default setting uses a set of real but arbitrary HRIRs, that may not be suitable for
 /// all listeners.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "HrtfConfigRaw")
+)]
 pub struct HrtfConfig {
     sample_rate: u32,
     virtual_speakers: Vec<VirtualSpeaker>,
+    crossfeed: f32,
 }
 
 impl HrtfConfig {
+    /// Construct an HRTF configuration from a set of measured head related impulse responses.
+    ///
+    /// The measurements should cover all directions around the listener, as is typical for
+    /// HRIR databases in SOFA format. The impulse responses for the renderer's virtual speakers
+    /// are interpolated from the nearest measurements. If no measurements are given, the
+    /// built-in default set is used instead.
+    pub fn from_measurements(sample_rate: u32, measurements: &[HrirMeasurement]) -> Self {
+        Self::from_measurements_with_order(sample_rate, measurements, AmbisonicOrder::First)
+    }
+
+    /// Like `from_measurements`, but decodes the given ambisonic order.
+    ///
+    /// First-order decoding uses four virtual speakers, second-order decoding uses twelve. The
+    /// built-in default set only supports first-order decoding.
+    pub fn from_measurements_with_order(
+        sample_rate: u32,
+        measurements: &[HrirMeasurement],
+        order: AmbisonicOrder,
+    ) -> Self {
+        if measurements.is_empty() {
+            return Self::default();
+        }
+
+        let directions: Vec<[f32; 3]> = match order {
+            AmbisonicOrder::First => TETRAHEDRON.to_vec(),
+            AmbisonicOrder::Second => icosahedron(),
+        };
+        let n = directions.len() as f32;
+
+        let virtual_speakers = directions
+            .into_iter()
+            .map(|dir| {
+                let (left_hrir, right_hrir) = interpolate_hrirs(dir, measurements);
+                let bweights = match order {
+                    AmbisonicOrder::First => {
+                        Bweights::new(1.0 / 2f32.sqrt(), dir[0], dir[1], dir[2])
+                    }
+                    // same overall level as the first-order decoder
+                    AmbisonicOrder::Second => {
+                        let g = 2.0 / n;
+                        Bweights::new(
+                            g * 2f32.sqrt(),
+                            3.0 * g * dir[0],
+                            3.0 * g * dir[1],
+                            3.0 * g * dir[2],
+                        )
+                        .with_second_order(second_order_components(dir).map(|q| 5.0 * g * q))
+                    }
+                };
+                VirtualSpeaker {
+                    bweights,
+                    left_hrir,
+                    right_hrir,
+                }
+            })
+            .collect();
+
+        HrtfConfig {
+            sample_rate,
+            virtual_speakers,
+            crossfeed: 0.0,
+        }
+    }
+
+    /// Blend a delayed, low-passed copy of each ear into the other
+    ///
+    /// Crossfeed mimics how each ear hears both speakers of a stereo pair, which reduces the
+    /// extreme separation of hard-panned sources and makes long listening less tiring. The
+    /// `amount` ranges from 0 (no crossfeed, the default) to 1 (the opposite ear at full level).
+    pub fn with_crossfeed(mut self, amount: f32) -> Self {
+        self.crossfeed = amount.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sample rate of the impulse responses, which the scene must run at
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
     pub fn from_file(filename: &str) -> Self {
         // todo: proper error handling
         let file = File::open(filename).unwrap();
@@ -148,26 +1058,98 @@ impl HrtfConfig {
                 Some(l) => l.split(", ").map(|s| s.parse().unwrap()).collect(),
             };
 
-            let right_hrir: Vec<f32> = match lines.next() {
-                None | Some("") => panic!("expected hrir"),
-                Some(l) => l.split(", ").map(|s| s.parse().unwrap()).collect(),
-            };
+            let right_hrir: Vec<f32> = match lines.next() {
+                None | Some("") => panic!("expected hrir"),
+                Some(l) => l.split(", ").map(|s| s.parse().unwrap()).collect(),
+            };
+
+            assert_eq!(lines.next(), Some(""));
+
+            virtual_speakers.push(VirtualSpeaker {
+                bweights,
+                left_hrir,
+                right_hrir,
+            });
+        }
+
+        assert!(lines.next().is_none());
+
+        HrtfConfig {
+            sample_rate: fs as u32,
+            virtual_speakers,
+            crossfeed: 0.0,
+        }
+    }
+}
+
+/// Deserialized fields of an `HrtfConfig`, whose crossfeed is applied through its setter
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct HrtfConfigRaw {
+    sample_rate: u32,
+    virtual_speakers: Vec<VirtualSpeaker>,
+    crossfeed: f32,
+}
+
+#[cfg(feature = "serde")]
+impl From<HrtfConfigRaw> for HrtfConfig {
+    fn from(raw: HrtfConfigRaw) -> Self {
+        HrtfConfig {
+            sample_rate: raw.sample_rate,
+            virtual_speakers: raw.virtual_speakers,
+            crossfeed: 0.0,
+        }
+        .with_crossfeed(raw.crossfeed)
+    }
+}
+
+/// cutoff frequency (in Hz) of the low-pass filter on the crossfeed signal
+const CROSSFEED_CUTOFF: f32 = 700.0;
 
-            assert_eq!(lines.next(), Some(""));
+/// delay (in s) of the crossfeed signal, roughly the interaural time difference of a speaker
+/// at 30º
+const CROSSFEED_DELAY: f32 = 0.0003;
 
-            virtual_speakers.push(VirtualSpeaker {
-                bweights,
-                left_hrir,
-                right_hrir,
-            });
-        }
+/// Headphone crossfeed filter
+///
+/// Adds a delayed and low-passed copy of each channel to the other channel. The output is
+/// scaled down so that a centered source keeps its level at low frequencies.
+struct Crossfeed {
+    amount: f32,
+    coefficient: f32,
+    lowpass_state: [f32; 2],
+    history: [VecDeque<f32>; 2],
+}
 
-        assert!(lines.next().is_none());
+impl Crossfeed {
+    fn new(amount: f32, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f32;
+        let length = (CROSSFEED_DELAY * sample_rate).round().max(1.0) as usize;
+        Crossfeed {
+            amount,
+            coefficient: 1.0 - (-2.0 * std::f32::consts::PI * CROSSFEED_CUTOFF / sample_rate).exp(),
+            lowpass_state: [0.0; 2],
+            history: [
+                VecDeque::from(vec![0.0; length]),
+                VecDeque::from(vec![0.0; length]),
+            ],
+        }
+    }
 
-        HrtfConfig {
-            sample_rate: fs as u32,
-            virtual_speakers,
+    #[inline(always)]
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let mut delayed = [0.0; 2];
+        for (ch, x) in [left, right].iter().enumerate() {
+            self.lowpass_state[ch] += self.coefficient * (x - self.lowpass_state[ch]);
+            self.history[ch].push_front(self.lowpass_state[ch]);
+            delayed[ch] = self.history[ch].pop_back().unwrap();
         }
+
+        let scale = 1.0 / (1.0 + self.amount);
+        (
+            (left + self.amount * delayed[1]) * scale,
+            (right + self.amount * delayed[0]) * scale,
+        )
     }
 }
 
@@ -177,6 +1159,7 @@ pub struct BstreamHrtfRenderer<I> {
     buffered_output: Option<f32>,
     convolution_buffers: Vec<VecDeque<f32>>,
     virtual_speakers: Vec<VirtualSpeaker>,
+    crossfeed: Option<Crossfeed>,
 }
 
 impl<I> BstreamHrtfRenderer<I>
@@ -196,13 +1179,30 @@ where
             })
             .collect();
 
+        let crossfeed = if config.crossfeed > 0.0 {
+            Some(Crossfeed::new(config.crossfeed, config.sample_rate))
+        } else {
+            None
+        };
+
         BstreamHrtfRenderer {
             input,
             buffered_output: None,
             convolution_buffers,
             virtual_speakers: config.virtual_speakers,
+            crossfeed,
         }
     }
+
+    /// Consume the renderer and return its *B-format* input
+    pub fn into_inner(self) -> I {
+        self.input
+    }
+
+    /// Mutable reference to the renderer's *B-format* input
+    pub fn get_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
 }
 
 impl<I> Source for BstreamHrtfRenderer<I>
@@ -266,6 +1266,12 @@ where
                         .sum::<f32>();
                 }
 
+                if let Some(crossfeed) = &mut self.crossfeed {
+                    let (l, r) = crossfeed.process(left, right);
+                    left = l;
+                    right = r;
+                }
+
                 // emit left channel now, and right channel next time
                 self.buffered_output = Some(right);
                 Some(left)
@@ -274,12 +1280,297 @@ where
     }
 }
 
+/// Transaural configuration
+///
+/// Intended for playback of the binaural HRTF rendering over a pair of loudspeakers in front of
+/// the listener. A cross-talk canceller removes the sound that travels from each speaker to the
+/// opposite ear. The canceller is derived from the speaker geometry, modelling the ears as two
+/// points 17.5 cm apart that the far ear hears attenuated by the head. Cancellation only works
+/// for a listener at the configured position.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransauralConfig {
+    hrtf: HrtfConfig,
+    span: f32,
+    listener_distance: f32,
+}
+
+impl TransauralConfig {
+    /// Construct a transaural configuration based on the given HRTFs
+    pub fn new(hrtf: HrtfConfig) -> Self {
+        TransauralConfig {
+            hrtf,
+            span: 60.0,
+            listener_distance: 1.0,
+        }
+    }
+
+    /// Set the angle (in degrees) between the speakers as seen by the listener (defaults to 60)
+    pub fn with_span(mut self, span: f32) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// Set the distance (in m) between the listener and the speakers (defaults to 1)
+    pub fn with_listener_distance(mut self, distance: f32) -> Self {
+        self.listener_distance = distance;
+        self
+    }
+
+    /// HRTFs the cross-talk cancellation is based on
+    pub(crate) fn hrtf(&self) -> &HrtfConfig {
+        &self.hrtf
+    }
+}
+
+impl Default for TransauralConfig {
+    fn default() -> Self {
+        Self::new(HrtfConfig::default())
+    }
+}
+
+/// distance (in m) of each ear from the center of the head
+const EAR_OFFSET: f32 = 0.0875;
+
+/// rough broadband attenuation of the sound that reaches the far ear, caused by the head
+const HEAD_SHADOW: f32 = 0.7;
+
+/// upper limit of the cross-talk gain, which keeps the canceller stable
+const MAX_CROSSTALK_GAIN: f32 = 0.9;
+
+/// Recursive cross-talk cancellation filter
+///
+/// Each speaker reaches the far ear with gain `gain` and `delay` samples later than the near
+/// ear. The canceller subtracts a correspondingly delayed and attenuated copy of the opposite
+/// output from each output.
+struct CrosstalkCanceller {
+    gain: f32,
+    delay: f32,
+    history: [VecDeque<f32>; 2],
+}
+
+impl CrosstalkCanceller {
+    fn new(span: f32, listener_distance: f32, sample_rate: u32) -> Self {
+        let half_span = (span.abs() / 2.0).to_radians();
+        let speaker = [
+            listener_distance * half_span.sin(),
+            listener_distance * half_span.cos(),
+        ];
+        let distance_to = |ear: f32| (speaker[0] - ear).hypot(speaker[1]);
+        let (near, far) = (distance_to(EAR_OFFSET), distance_to(-EAR_OFFSET));
+
+        let gain = (HEAD_SHADOW * near / far).min(MAX_CROSSTALK_GAIN);
+        let delay = (far - near) / SPEED_OF_SOUND * sample_rate as f32;
+
+        // the recursion needs the opposite output of at least one sample ago
+        let delay = delay.max(1.0);
+        let length = delay.ceil() as usize + 1;
+
+        CrosstalkCanceller {
+            gain,
+            delay,
+            history: [
+                VecDeque::from(vec![0.0; length]),
+                VecDeque::from(vec![0.0; length]),
+            ],
+        }
+    }
+
+    /// Output of a channel `delay` samples ago, linearly interpolated
+    #[inline(always)]
+    fn delayed(&self, channel: usize) -> f32 {
+        let i = self.delay.floor() as usize;
+        let frac = self.delay - i as f32;
+        let h = &self.history[channel];
+        h[i - 1] * (1.0 - frac) + h[i] * frac
+    }
+
+    #[inline(always)]
+    fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let left = left - self.gain * self.delayed(1);
+        let right = right - self.gain * self.delayed(0);
+        for (h, x) in self.history.iter_mut().zip(&[left, right]) {
+            h.pop_back();
+            h.push_front(*x);
+        }
+        (left, right)
+    }
+}
+
+/// Render a *B-format* stream for a pair of loudspeakers using HRTFs and cross-talk
+/// cancellation.
+pub struct BstreamTransauralRenderer<I> {
+    hrtf: BstreamHrtfRenderer<I>,
+    canceller: CrosstalkCanceller,
+    buffered_output: Option<f32>,
+}
+
+impl<I> BstreamTransauralRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    /// Construct a new transaural renderer
+    pub fn new(input: I, config: TransauralConfig) -> Self {
+        let canceller =
+            CrosstalkCanceller::new(config.span, config.listener_distance, input.sample_rate());
+        BstreamTransauralRenderer {
+            hrtf: BstreamHrtfRenderer::new(input, config.hrtf),
+            canceller,
+            buffered_output: None,
+        }
+    }
+
+    /// Consume the renderer and return its *B-format* input
+    pub fn into_inner(self) -> I {
+        self.hrtf.into_inner()
+    }
+
+    /// Mutable reference to the renderer's *B-format* input
+    pub fn get_mut(&mut self) -> &mut I {
+        self.hrtf.get_mut()
+    }
+}
+
+impl<I> Source for BstreamTransauralRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.hrtf.current_frame_len()
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.hrtf.sample_rate()
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        self.hrtf.total_duration()
+    }
+}
+
+impl<I> Iterator for BstreamTransauralRenderer<I>
+where
+    I: Source<Item = Bformat>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(s) = self.buffered_output.take() {
+            return Some(s);
+        }
+
+        let left = self.hrtf.next()?;
+        let right = self.hrtf.next()?;
+        let (left, right) = self.canceller.process(left, right);
+        self.buffered_output = Some(right);
+        Some(left)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 struct VirtualSpeaker {
     bweights: Bweights,
     left_hrir: Vec<f32>,
     right_hrir: Vec<f32>,
 }
 
+/// Head related impulse responses of both ears, measured for one direction.
+///
+/// The direction is given by azimuth and elevation in degrees, with the same convention as
+/// in `MultiSpeakerConfig`.
+pub struct HrirMeasurement {
+    direction: [f32; 3],
+    left_hrir: Vec<f32>,
+    right_hrir: Vec<f32>,
+}
+
+impl HrirMeasurement {
+    /// Construct a new measurement from the left and right impulse responses
+    pub fn new(azimuth: f32, elevation: f32, left_hrir: Vec<f32>, right_hrir: Vec<f32>) -> Self {
+        HrirMeasurement {
+            direction: speaker_direction(azimuth, elevation),
+            left_hrir,
+            right_hrir,
+        }
+    }
+}
+
+/// Directions of the virtual speakers used for HRTF rendering
+#[allow(clippy::excessive_precision)]
+const TETRAHEDRON: [[f32; 3]; 4] = [
+    [-0.816496580927726, 0.4714045207910317, -0.3333333333333333],
+    [0.816496580927726, 0.4714045207910317, -0.3333333333333333],
+    [0.0, -0.9428090415820634, -0.3333333333333333],
+    [0.0, 0.0, 1.0],
+];
+
+/// Directions of the virtual speakers used for second-order HRTF rendering
+fn icosahedron() -> Vec<[f32; 3]> {
+    let phi = (1.0 + 5f32.sqrt()) / 2.0;
+    let l = (1.0 + phi * phi).sqrt();
+    let mut dirs = vec![];
+    for &a in &[-1.0, 1.0] {
+        for &b in &[-phi, phi] {
+            dirs.push([0.0, a / l, b / l]);
+            dirs.push([a / l, b / l, 0.0]);
+            dirs.push([b / l, 0.0, a / l]);
+        }
+    }
+    dirs
+}
+
+/// Interpolate left and right HRIRs in given direction from the nearest measurements.
+fn interpolate_hrirs(dir: [f32; 3], measurements: &[HrirMeasurement]) -> (Vec<f32>, Vec<f32>) {
+    const N_NEAREST: usize = 3;
+
+    let mut nearest: Vec<_> = measurements
+        .iter()
+        .map(|m| {
+            let cos = dir[0] * m.direction[0] + dir[1] * m.direction[1] + dir[2] * m.direction[2];
+            (cos.clamp(-1.0, 1.0).acos(), m)
+        })
+        .collect();
+    nearest.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+    // measurements at equal distance are all taken into account to avoid arbitrary choices
+    let max_angle = nearest[N_NEAREST.min(nearest.len()) - 1].0 + 1e-4;
+    nearest.retain(|(angle, _)| *angle <= max_angle);
+
+    // an exact match needs no interpolation
+    if nearest[0].0 < 1e-3 {
+        let m = nearest[0].1;
+        return (m.left_hrir.clone(), m.right_hrir.clone());
+    }
+
+    let total_weight: f32 = nearest.iter().map(|(angle, _)| 1.0 / angle).sum();
+    let n = nearest
+        .iter()
+        .map(|(_, m)| m.left_hrir.len().max(m.right_hrir.len()))
+        .max()
+        .unwrap();
+
+    let mut left = vec![0.0; n];
+    let mut right = vec![0.0; n];
+    for (angle, m) in nearest {
+        let weight = 1.0 / (angle * total_weight);
+        for (l, h) in left.iter_mut().zip(&m.left_hrir) {
+            *l += weight * h;
+        }
+        for (r, h) in right.iter_mut().zip(&m.right_hrir) {
+            *r += weight * h;
+        }
+    }
+
+    (left, right)
+}
+
 #[allow(clippy::excessive_precision)]
 #[allow(clippy::unreadable_literal)]
 impl Default for HrtfConfig {
@@ -2379,6 +3670,541 @@ impl Default for HrtfConfig {
                     ],
                 },
             ],
+            crossfeed: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bstream::{bstream, BstreamConfig};
+    use crate::sources::{Constant, SineSweep};
+
+    #[test]
+    fn ambix_renderer_uses_acn_order_and_sn3d_normalization() {
+        // source at 30º to the left
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([-0.5, 0.75f32.sqrt(), 0.0]),
+        );
+        let frame: Vec<f32> = BstreamAmbixRenderer::new(stream).take(4).collect();
+
+        let expected = [1.0, 0.5, 0.0, 0.75f32.sqrt()];
+        for (actual, expected) in frame.iter().zip(&expected) {
+            assert!((actual - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn stereo_elevation_cue_brightens_overhead_sources() {
+        // level of the left channel for a tone at given frequency and source position
+        let level = |frequency, pos, cue| {
+            let tone = rodio::source::SineWave::new(frequency);
+            let (stream, _) = bstream(tone, BstreamConfig::new().with_position(pos));
+            let config = StereoConfig::default().with_elevation_cue(cue);
+            let left: Vec<f32> = BstreamStereoRenderer::new(stream, config)
+                .step_by(2)
+                .skip(480)
+                .take(4800)
+                .collect();
+            (left.iter().map(|x| x * x).sum::<f32>() / left.len() as f32).sqrt()
+        };
+        let brightness = |pos, cue| level(10000, pos, cue) / level(100, pos, cue);
+
+        let front = [0.0, 1.0, 0.0];
+        let above = [0.0, 0.0, 1.0];
+        assert!((brightness(front, true) - brightness(front, false)).abs() < 1e-3);
+        assert!((brightness(above, false) - 1.0).abs() < 0.01);
+        assert!(brightness(above, true) > 1.1);
+    }
+
+    #[test]
+    fn surround51_renderer_concentrates_front_source_in_center() {
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([0.0, 1.0, 0.0]),
+        );
+        let renderer = BstreamSurround51Renderer::new(stream, Surround51Config::default());
+        assert_eq!(renderer.channels(), 6);
+
+        let frame: Vec<f32> = renderer.take(6).collect();
+        let center = frame[2];
+        for (i, &x) in frame.iter().enumerate() {
+            if i != 2 {
+                assert!(x.abs() < center);
+            }
+        }
+    }
+
+    #[test]
+    fn surround51_lfe_follows_low_frequencies_or_stays_silent() {
+        let lfe = |config| {
+            let (stream, _) = bstream(Constant::new(1.0, 48000), BstreamConfig::new());
+            BstreamSurround51Renderer::new(stream, config)
+                .skip(3)
+                .step_by(6)
+                .nth(48000)
+                .unwrap()
+        };
+
+        assert!((lfe(Surround51Config::default()) - 1.0).abs() < 1e-3);
+        assert_eq!(lfe(Surround51Config::default().without_lfe()), 0.0);
+    }
+
+    #[test]
+    fn crossfeed_increases_interaural_correlation() {
+        // normalized correlation of both ears for a hard-left source in the crossfeed band
+        let correlation = |amount| {
+            let sweep = SineSweep::new(20.0, 500.0, Duration::from_secs(1), 48000);
+            let (stream, _) = bstream(sweep, BstreamConfig::new().with_position([-1.0, 0.0, 0.0]));
+            let config = HrtfConfig::default().with_crossfeed(amount);
+            let output: Vec<f32> = BstreamHrtfRenderer::new(stream, config).collect();
+
+            let (mut lr, mut ll, mut rr) = (0.0, 0.0, 0.0);
+            for frame in output.chunks(2) {
+                lr += frame[0] * frame[1];
+                ll += frame[0] * frame[0];
+                rr += frame[1] * frame[1];
+            }
+            lr / (ll * rr).sqrt()
+        };
+
+        let none = correlation(0.0);
+        let mild = correlation(0.3);
+        let strong = correlation(1.0);
+        assert!(mild > none);
+        assert!(strong > mild);
+    }
+
+    #[test]
+    fn hrtf_renderer_uses_custom_measurements() {
+        // synthetic HRIRs that only encode the level difference between both ears
+        let mut measurements = vec![];
+        for &elevation in &[-90.0, -45.0, 0.0, 45.0, 90.0] {
+            for azimuth in (0..360).step_by(30) {
+                let dir = speaker_direction(azimuth as f32, elevation);
+                let left = vec![0.0, 1.0 - dir[0] / 2.0];
+                let right = vec![0.0, 1.0 + dir[0] / 2.0];
+                measurements.push(HrirMeasurement::new(azimuth as f32, elevation, left, right));
+            }
+        }
+
+        let render = |pos| {
+            let (stream, _) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new().with_position(pos),
+            );
+            let config = HrtfConfig::from_measurements(48000, &measurements);
+            BstreamHrtfRenderer::new(stream, config)
+                .take(20)
+                .collect::<Vec<_>>()
+        };
+
+        let front = render([0.0, 1.0, 0.0]);
+        assert_eq!(front.len(), 20);
+        assert!((front[18] - front[19]).abs() < 1e-4);
+
+        let right = render([1.0, 0.0, 0.0]);
+        assert!(right[19] > right[18]);
+
+        let left = render([-1.0, 0.0, 0.0]);
+        assert!((left[18] - right[19]).abs() < 1e-4);
+        assert!((left[19] - right[18]).abs() < 1e-4);
+    }
+
+    /// level of a source in direction `dir` relative to the total level of all speakers
+    fn energy_fraction(config: MultiSpeakerConfig, order: AmbisonicOrder, dir: [f32; 3]) -> f32 {
+        let n = config.n_speakers();
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position(dir).with_order(order),
+        );
+        let frame: Vec<f32> = BstreamMultiSpeakerRenderer::new(stream, config.with_order(order))
+            .take(n)
+            .collect();
+        frame[0] * frame[0] / frame.iter().map(|x| x * x).sum::<f32>()
+    }
+
+    #[test]
+    fn second_order_localizes_sources_more_sharply() {
+        let ring = MultiSpeakerConfig::octagon;
+        let front = [0.0, 1.0, 0.0];
+        let first = energy_fraction(ring(), AmbisonicOrder::First, front);
+        let second = energy_fraction(ring(), AmbisonicOrder::Second, front);
+        assert!(second > first * 1.3);
+
+        let dome = |order| {
+            let cfg = icosahedron()
+                .iter()
+                .fold(MultiSpeakerConfig::new(), |cfg, d| {
+                    let azimuth = (-d[0]).atan2(d[1]).to_degrees();
+                    let elevation = d[2].asin().to_degrees();
+                    cfg.with_speaker(azimuth, elevation)
+                });
+            energy_fraction(cfg, order, icosahedron()[0])
+        };
+        assert!(dome(AmbisonicOrder::Second) > dome(AmbisonicOrder::First) * 1.3);
+    }
+
+    #[test]
+    fn pan_laws_differ_in_power_across_the_front() {
+        // total power L² + R² of sources swept from the left to the right speaker and beyond
+        let powers = |law| {
+            (-90..=90)
+                .step_by(10)
+                .map(|azimuth| {
+                    let (stream, _) = bstream(
+                        Constant::new(1.0, 48000),
+                        BstreamConfig::new().with_position(math::spherical_to_cartesian(
+                            azimuth as f32,
+                            0.0,
+                            1.0,
+                        )),
+                    );
+                    let config = StereoConfig::default().with_pan_law(law);
+                    let frame: Vec<f32> =
+                        BstreamStereoRenderer::new(stream, config).take(2).collect();
+                    frame[0] * frame[0] + frame[1] * frame[1]
+                })
+                .collect::<Vec<f32>>()
+        };
+
+        for power in powers(PanLaw::EqualPower) {
+            assert!((power - 1.0).abs() < 1e-4);
+        }
+
+        let linear = powers(PanLaw::Linear);
+        let center = linear[linear.len() / 2];
+        assert!((center - 0.5).abs() < 1e-4);
+        assert!((linear[0] - 1.0).abs() < 1e-4);
+        assert!((linear[linear.len() - 1] - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn second_order_stereo_separates_channels_better() {
+        let separation = |order| {
+            let (stream, _) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([-1.0, 1.0, 0.0])
+                    .with_order(order),
+            );
+            let mut config = StereoConfig::default();
+            config.set_order(order);
+            let frame: Vec<f32> = BstreamStereoRenderer::new(stream, config).take(2).collect();
+            frame[1] / frame[0]
+        };
+
+        assert!(separation(AmbisonicOrder::Second) < separation(AmbisonicOrder::First) * 0.75);
+    }
+
+    #[test]
+    fn second_order_hrtf_decodes_consistently_with_first_order() {
+        let mut measurements = vec![];
+        for &elevation in &[-90.0, -45.0, 0.0, 45.0, 90.0] {
+            for azimuth in (0..360).step_by(30) {
+                let dir = speaker_direction(azimuth as f32, elevation);
+                let left = vec![0.0, 1.0 - dir[0] / 2.0];
+                let right = vec![0.0, 1.0 + dir[0] / 2.0];
+                measurements.push(HrirMeasurement::new(azimuth as f32, elevation, left, right));
+            }
+        }
+
+        let render = |order| {
+            let (stream, _) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new()
+                    .with_position([1.0, 0.0, 0.0])
+                    .with_order(order),
+            );
+            let config = HrtfConfig::from_measurements_with_order(48000, &measurements, order);
+            BstreamHrtfRenderer::new(stream, config)
+                .take(20)
+                .collect::<Vec<_>>()
+        };
+
+        let first = render(AmbisonicOrder::First);
+        let second = render(AmbisonicOrder::Second);
+
+        // both decoders place the source on the right, the second-order one more clearly
+        assert!(first[19] > first[18]);
+        assert!(second[19] / second[18] > first[19] / first[18]);
+    }
+
+    #[test]
+    fn multi_speaker_renderer_has_one_channel_per_speaker() {
+        let (mixer, _) = crate::bmixer::bmixer(48000);
+        let renderer = BstreamMultiSpeakerRenderer::new(mixer, MultiSpeakerConfig::square());
+        assert_eq!(renderer.channels(), 4);
+    }
+
+    #[test]
+    fn multi_speaker_renderer_plays_source_on_nearest_speaker() {
+        // source front-left, which is where the first speaker is
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([-1.0, 1.0, 0.0]),
+        );
+        let frame: Vec<f32> =
+            BstreamMultiSpeakerRenderer::new(stream, MultiSpeakerConfig::square())
+                .take(4)
+                .collect();
+
+        assert!(frame[0] > frame[1]);
+        assert!(frame[0] > frame[2]);
+        assert!(frame[0] > frame[3]);
+    }
+
+    #[test]
+    fn output_channel_map_permutes_channels() {
+        let render = |cfg: MultiSpeakerConfig| {
+            let (stream, _) = bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new().with_position([-1.0, 1.0, 0.0]),
+            );
+            BstreamMultiSpeakerRenderer::new(stream, cfg)
+                .take(8)
+                .collect::<Vec<f32>>()
+        };
+
+        let plain = render(MultiSpeakerConfig::square());
+        let reversed =
+            render(MultiSpeakerConfig::square().with_output_channel_map(vec![3, 2, 1, 0]));
+        for i in 0..4 {
+            assert_eq!(reversed[3 - i], plain[i]);
+            assert_eq!(reversed[7 - i], plain[4 + i]);
+        }
+        assert!(plain[0] > plain[2]);
+
+        assert_eq!(invert_channel_map(&[2, 0, 1], 3), Some(vec![1, 2, 0]));
+        assert_eq!(invert_channel_map(&[0, 0, 1], 3), None);
+        assert_eq!(invert_channel_map(&[0, 1, 3], 3), None);
+        assert_eq!(invert_channel_map(&[0, 1], 3), None);
+    }
+
+    #[test]
+    fn subwoofer_takes_low_end_of_sweep() {
+        // 20 Hz to 20 kHz in one second, crossing 120 Hz after about 0.26 s
+        let sweep = SineSweep::new(20.0, 20000.0, Duration::from_secs(1), 48000);
+        let (stream, _) = bstream(sweep, BstreamConfig::new().with_position([0.0, 1.0, 0.0]));
+        let config = MultiSpeakerConfig::square()
+            .with_subwoofer(2, 120.0)
+            .with_highpassed_mains(true);
+        assert_eq!(config.subwoofer(), Some((2, 120.0)));
+
+        let renderer = BstreamMultiSpeakerRenderer::new(stream, config);
+        assert_eq!(renderer.channels(), 5);
+
+        // energy of the subwoofer and the mains below 60 Hz and above 480 Hz
+        let mut low = [0.0; 2];
+        let mut high = [0.0; 2];
+        let samples: Vec<f32> = renderer.collect();
+        for (i, frame) in samples.chunks(5).enumerate() {
+            let t = i as f32 / 48000.0;
+            let energy = if t < 0.159 {
+                &mut low
+            } else if t > 0.46 {
+                &mut high
+            } else {
+                continue;
+            };
+            for (ch, x) in frame.iter().enumerate() {
+                energy[(ch != 2) as usize] += x * x;
+            }
+        }
+
+        assert!(low[0] > 2.0 * low[1]);
+        assert!(high[1] > 2.0 * high[0]);
+    }
+
+    #[test]
+    fn presets_have_expected_speakers() {
+        assert_eq!(MultiSpeakerConfig::square().n_speakers(), 4);
+        assert_eq!(MultiSpeakerConfig::octagon().n_speakers(), 8);
+        assert_eq!(MultiSpeakerConfig::cube().n_speakers(), 8);
+
+        let front = speaker_direction(MultiSpeakerConfig::octagon().speakers[0][0], 0.0);
+        assert_eq!(front, [0.0, 1.0, 0.0]);
+
+        // all cube corners are at the same angle from the vertical
+        for &[az, el] in &MultiSpeakerConfig::cube().speakers {
+            let d = speaker_direction(az, el);
+            assert!((d[2].abs() - 1.0 / 3f32.sqrt()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cube_plays_overhead_source_on_upper_speakers() {
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([0.0, 0.0, 1.0]),
+        );
+        let frame: Vec<f32> = BstreamMultiSpeakerRenderer::new(stream, MultiSpeakerConfig::cube())
+            .take(8)
+            .collect();
+
+        let upper: f32 = frame[..4].iter().map(|x| x * x).sum();
+        let lower: f32 = frame[4..].iter().map(|x| x * x).sum();
+        assert!(upper > 10.0 * lower, "upper {} lower {}", upper, lower);
+    }
+
+    /// magnitude of the energy vector of a source in direction `dir`
+    fn energy_concentration(
+        config: MultiSpeakerConfig,
+        order: AmbisonicOrder,
+        dir: [f32; 3],
+    ) -> f32 {
+        let directions: Vec<_> = config
+            .speakers
+            .iter()
+            .map(|&[az, el]| speaker_direction(az, el))
+            .collect();
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position(dir).with_order(order),
+        );
+        let frame: Vec<f32> = BstreamMultiSpeakerRenderer::new(stream, config.with_order(order))
+            .take(directions.len())
+            .collect();
+
+        let total: f32 = frame.iter().map(|x| x * x).sum();
+        let mut re = [0.0; 3];
+        for (x, d) in frame.iter().zip(&directions) {
+            for k in 0..3 {
+                re[k] += x * x * d[k] / total;
+            }
+        }
+        (re[0] * re[0] + re[1] * re[1] + re[2] * re[2]).sqrt()
+    }
+
+    #[test]
+    fn max_re_weighting_concentrates_energy() {
+        let front = [0.0, 1.0, 0.0];
+        for &order in &[AmbisonicOrder::First, AmbisonicOrder::Second] {
+            for layout in &[MultiSpeakerConfig::octagon, MultiSpeakerConfig::cube] {
+                let basic = energy_concentration(layout(), order, front);
+                let max_re = energy_concentration(
+                    layout().with_weighting(DecoderWeighting::MaxRe),
+                    order,
+                    front,
+                );
+                assert!(max_re > basic, "{:?}: {} <= {}", order, max_re, basic);
+            }
+        }
+    }
+
+    #[test]
+    fn in_phase_weighting_never_inverts_polarity() {
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([0.0, 1.0, 0.0]),
+        );
+        let config = MultiSpeakerConfig::octagon().with_weighting(DecoderWeighting::InPhase);
+        let frame: Vec<f32> = BstreamMultiSpeakerRenderer::new(stream, config)
+            .take(8)
+            .collect();
+        assert!(frame.iter().all(|&x| x >= -1e-6), "{:?}", frame);
+        assert!(frame[4].abs() < 1e-6);
+    }
+
+    #[test]
+    fn crosstalk_canceller_follows_geometry() {
+        let ctc = CrosstalkCanceller::new(60.0, 1.0, 48000);
+        assert!((ctc.delay - 12.2).abs() < 0.1, "delay {}", ctc.delay);
+        assert!((ctc.gain - 0.64).abs() < 0.01, "gain {}", ctc.gain);
+
+        // wider spans increase the delay to the far ear
+        let wide = CrosstalkCanceller::new(90.0, 1.0, 48000);
+        assert!(wide.delay > ctc.delay);
+
+        // the far ear is relatively farther away from closer speakers
+        let close = CrosstalkCanceller::new(60.0, 0.5, 48000);
+        assert!(close.gain < ctc.gain);
+    }
+
+    #[test]
+    fn crosstalk_canceller_is_stable_at_small_spans() {
+        for &span in &[0.0, 1.0, 10.0] {
+            let mut ctc = CrosstalkCanceller::new(span, 3.0, 48000);
+            assert!(ctc.delay >= 1.0);
+            assert!(ctc.gain < 1.0);
+
+            let output: Vec<(f32, f32)> = (0..48000)
+                .map(|i| ctc.process(if i == 0 { 1.0 } else { 0.0 }, 0.0))
+                .collect();
+            assert!(output.iter().all(|(l, r)| l.is_finite() && r.is_finite()));
+            let tail = output[47000..]
+                .iter()
+                .map(|(l, r)| l.abs().max(r.abs()))
+                .fold(0.0, f32::max);
+            assert!(tail < 1e-6, "span {}: tail {}", span, tail);
+        }
+    }
+
+    #[test]
+    fn transaural_renderer_cancels_crosstalk_of_hrtf_output() {
+        let stream = || {
+            bstream(
+                Constant::new(1.0, 48000),
+                BstreamConfig::new().with_position([0.0, 1.0, 0.0]),
+            )
+            .0
+        };
+        let n = 8000;
+        let binaural: Vec<f32> = BstreamHrtfRenderer::new(stream(), HrtfConfig::default())
+            .take(n)
+            .collect();
+        let transaural: Vec<f32> =
+            BstreamTransauralRenderer::new(stream(), TransauralConfig::default())
+                .take(n)
+                .collect();
+
+        // in the steady state each ear receives its binaural signal without cross-talk:
+        // left = l - g * right and right = r - g * left
+        let gain = CrosstalkCanceller::new(60.0, 1.0, 48000).gain;
+        let (l, r) = (binaural[n - 2], binaural[n - 1]);
+        let expected = [(l - gain * r), (r - gain * l)].map(|x| x / (1.0 - gain * gain));
+        for ch in 0..2 {
+            assert!((transaural[n - 2 + ch] - expected[ch]).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn decoder_matrix_of_square_layout_is_symmetric() {
+        let m = MultiSpeakerConfig::square().decoder_matrix();
+        assert_eq!(m.len(), 4);
+        let [front_left, back_left, back_right, front_right] = [m[0], m[1], m[2], m[3]];
+
+        // every speaker gets the same share of the omnidirectional component
+        assert!(m.iter().all(|row| row[0] == front_left[0]));
+
+        // mirroring left and right flips X, mirroring front and back flips Y
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+        assert!(close(front_left[1], -front_right[1]) && close(front_left[2], front_right[2]));
+        assert!(close(front_left[1], back_left[1]) && close(front_left[2], -back_left[2]));
+        assert!(close(back_left[1], -back_right[1]));
+
+        // left speakers have negative X (+x is right), front speakers positive Y
+        assert!(front_left[1] < 0.0 && front_left[2] > 0.0);
+        assert!(m.iter().all(|row| row[3] == 0.0));
+    }
+
+    #[test]
+    fn multi_speaker_renderer_decodes_with_the_decoder_matrix() {
+        let config = MultiSpeakerConfig::octagon();
+        let matrix = config.decoder_matrix();
+        let (stream, _) = bstream(
+            Constant::new(1.0, 48000),
+            BstreamConfig::new().with_position([1.0, 2.0, 0.0]),
+        );
+        let weights = Bweights::from_direction([1.0, 2.0, 0.0], 1.0 / 5f32.sqrt()).components();
+        let frame: Vec<f32> = BstreamMultiSpeakerRenderer::new(stream, config)
+            .take(8)
+            .collect();
+        for (row, x) in matrix.iter().zip(frame) {
+            let expected: f32 = row.iter().zip(&weights).map(|(a, b)| a * b).sum();
+            assert!((x - expected).abs() < 1e-5);
         }
     }
 }
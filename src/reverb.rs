@@ -0,0 +1,293 @@
+//! Room reverberation on the *B-format* bus
+
+use crate::bformat::Bformat;
+use rodio::Sample;
+use std::time::Duration;
+
+/// Settings of the scene's reverb
+///
+/// The reverb is a feedback delay network that is fed by the reverb sends of all streams (see
+/// `BstreamConfig::with_reverb_send`) and produces a diffuse sound field around the listener.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "ReverbConfigRaw")
+)]
+pub struct ReverbConfig {
+    decay_time: Duration,
+    pre_delay: Duration,
+    mix: f32,
+}
+
+impl Default for ReverbConfig {
+    fn default() -> Self {
+        ReverbConfig {
+            decay_time: Duration::from_millis(1500),
+            pre_delay: Duration::from_millis(20),
+            mix: 0.3,
+        }
+    }
+}
+
+impl ReverbConfig {
+    /// Create new `ReverbConfig` with default settings.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the time it takes the reverb to decay by 60 dB (defaults to 1.5 s).
+    pub fn with_decay_time(mut self, t: Duration) -> Self {
+        self.decay_time = t;
+        self
+    }
+
+    /// Set the delay between the direct sound and the onset of the reverb (defaults to 20 ms).
+    pub fn with_pre_delay(mut self, t: Duration) -> Self {
+        self.pre_delay = t;
+        self
+    }
+
+    /// Set the balance between direct sound and reverb (defaults to 0.3).
+    ///
+    /// At 0 only the direct sound is heard, at 1 only the reverb. Values outside the range
+    /// [0, 1] are clamped.
+    pub fn with_mix(mut self, mix: f32) -> Self {
+        self.mix = mix.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// Deserialized fields of a `ReverbConfig`, which are applied through its setters
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(default)]
+struct ReverbConfigRaw {
+    decay_time: Duration,
+    pre_delay: Duration,
+    mix: f32,
+}
+
+#[cfg(feature = "serde")]
+impl Default for ReverbConfigRaw {
+    fn default() -> Self {
+        let config = ReverbConfig::default();
+        ReverbConfigRaw {
+            decay_time: config.decay_time,
+            pre_delay: config.pre_delay,
+            mix: config.mix,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<ReverbConfigRaw> for ReverbConfig {
+    fn from(raw: ReverbConfigRaw) -> Self {
+        ReverbConfig::new()
+            .with_decay_time(raw.decay_time)
+            .with_pre_delay(raw.pre_delay)
+            .with_mix(raw.mix)
+    }
+}
+
+/// delay line lengths (in samples at 44.1 kHz); distinct primes to avoid coinciding echoes
+const DELAY_LENGTHS: [usize; 4] = [1543, 1613, 1493, 1427];
+
+/// Feedback delay network that turns a mono send signal into a diffuse *B-format* field
+pub(crate) struct Reverb {
+    pre_delay: DelayLine,
+    lines: [DelayLine; 4],
+    feedback: [f32; 4],
+    dry: f32,
+    wet: f32,
+}
+
+impl Reverb {
+    pub fn new(config: &ReverbConfig, sample_rate: u32) -> Self {
+        let sr = sample_rate as f32;
+        let lengths = delay_lengths(sample_rate);
+
+        // each pass through a delay line of length n attenuates by 60 dB * n / decay length
+        let decay_samples = config.decay_time.as_secs_f32() * sr;
+        let feedback = lengths.map(|n| 10f32.powf(-3.0 * n as f32 / decay_samples));
+
+        Reverb {
+            pre_delay: DelayLine::new((config.pre_delay.as_secs_f32() * sr) as usize),
+            lines: lengths.map(DelayLine::new),
+            feedback,
+            dry: 1.0 - config.mix,
+            wet: config.mix,
+        }
+    }
+
+    /// Mix the reverb of the next `send` sample into the `dry` sample
+    #[inline(always)]
+    pub fn process(&mut self, dry: Bformat, send: f32) -> Bformat {
+        let input = self.pre_delay.push(send);
+
+        let o = [
+            self.lines[0].front(),
+            self.lines[1].front(),
+            self.lines[2].front(),
+            self.lines[3].front(),
+        ];
+
+        // the orthogonal Hadamard matrix mixes the lines without changing the total energy
+        let h = hadamard(o);
+        for ((line, g), h) in self.lines.iter_mut().zip(&self.feedback).zip(&h) {
+            line.push(input + g * h);
+        }
+
+        // decorrelated line outputs on all components produce a diffuse field
+        let wet = Bformat::from_components([h[0] / 2f32.sqrt(), h[1], h[2], h[3]]);
+        dry.amplify(self.dry).saturating_add(wet.amplify(self.wet))
+    }
+}
+
+/// Lengths of the delay lines at the given sample rate
+///
+/// The lengths at 44.1 kHz are scaled and rounded to the nearest prime that no other line
+/// uses, so they stay mutually prime at any rate.
+fn delay_lengths(sample_rate: u32) -> [usize; 4] {
+    let scale = sample_rate as f32 / 44100.0;
+    let mut lengths = [0; 4];
+    for i in 0..lengths.len() {
+        let n = (DELAY_LENGTHS[i] as f32 * scale).round() as usize;
+        lengths[i] = (0..)
+            .flat_map(|d| [n.saturating_sub(d), n + d])
+            .find(|&p| is_prime(p) && !lengths[..i].contains(&p))
+            .unwrap();
+    }
+    lengths
+}
+
+fn is_prime(n: usize) -> bool {
+    n >= 2
+        && (2..)
+            .take_while(|d| d * d <= n)
+            .all(|d| !n.is_multiple_of(d))
+}
+
+/// Multiply with the normalized 4x4 Hadamard matrix
+#[inline(always)]
+fn hadamard(v: [f32; 4]) -> [f32; 4] {
+    [
+        0.5 * (v[0] + v[1] + v[2] + v[3]),
+        0.5 * (v[0] - v[1] + v[2] - v[3]),
+        0.5 * (v[0] + v[1] - v[2] - v[3]),
+        0.5 * (v[0] - v[1] - v[2] + v[3]),
+    ]
+}
+
+/// Fixed delay of a number of samples
+struct DelayLine {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl DelayLine {
+    fn new(length: usize) -> Self {
+        DelayLine {
+            buffer: vec![0.0; length],
+            position: 0,
+        }
+    }
+
+    /// Oldest sample in the line, which is the output of the next `push`
+    #[inline(always)]
+    fn front(&self) -> f32 {
+        self.buffer.get(self.position).copied().unwrap_or(0.0)
+    }
+
+    /// Insert a sample and return the sample that leaves the line
+    #[inline(always)]
+    fn push(&mut self, x: f32) -> f32 {
+        if self.buffer.is_empty() {
+            return x;
+        }
+        let out = std::mem::replace(&mut self.buffer[self.position], x);
+        self.position = (self.position + 1) % self.buffer.len();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bformat::Bweights;
+
+    /// Root mean square of the omnidirectional component in consecutive windows
+    fn rms_envelope(output: &[Bformat], window: usize) -> Vec<f32> {
+        output
+            .chunks(window)
+            .map(|c| {
+                let sum: f32 = c.iter().map(|s| s.components()[0].powi(2)).sum();
+                (sum / c.len() as f32).sqrt()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn impulse_decays_over_configured_time() {
+        let sr = 48000;
+        let config = ReverbConfig::new()
+            .with_decay_time(Duration::from_secs(1))
+            .with_pre_delay(Duration::from_millis(10))
+            .with_mix(1.0);
+        let mut reverb = Reverb::new(&config, sr);
+
+        let output: Vec<Bformat> = (0..2 * sr)
+            .map(|i| {
+                let send = if i == 0 { 1.0 } else { 0.0 };
+                reverb.process(Bformat::zero_value(), send)
+            })
+            .collect();
+
+        // nothing before the pre-delay and the first echo
+        let first = output
+            .iter()
+            .position(|s| Bweights::omni_source().dot(*s) != 0.0)
+            .unwrap();
+        assert!(first >= sr as usize / 100);
+
+        // the tail decays by about 60 dB within the decay time
+        let window = sr as usize / 10;
+        let rms = rms_envelope(&output[first..], window);
+        let decay = 20.0 * (rms[10] / rms[0]).log10();
+        assert!(decay < -50.0 && decay > -70.0, "decay {} dB", decay);
+        assert!(rms[5] > 0.0);
+    }
+
+    #[test]
+    fn delay_lengths_are_mutually_prime() {
+        fn gcd(a: usize, b: usize) -> usize {
+            if b == 0 {
+                a
+            } else {
+                gcd(b, a % b)
+            }
+        }
+
+        assert_eq!(delay_lengths(44100), DELAY_LENGTHS);
+        for sample_rate in [100, 8000, 22050, 48000, 96000, 192000] {
+            let lengths = delay_lengths(sample_rate);
+            for (i, &a) in lengths.iter().enumerate() {
+                for &b in &lengths[i + 1..] {
+                    assert_eq!(gcd(a, b), 1, "{:?} at {} Hz", lengths, sample_rate);
+                }
+            }
+        }
+        assert_eq!(delay_lengths(48000), [1669, 1753, 1627, 1553]);
+    }
+
+    #[test]
+    fn zero_mix_passes_dry_signal() {
+        let config = ReverbConfig::new().with_mix(0.0);
+        let mut reverb = Reverb::new(&config, 48000);
+        let dry = Bweights::omni_source().scale(1.0);
+        for _ in 0..5000 {
+            let out = reverb.process(dry, 1.0);
+            assert_eq!(out.components(), dry.components());
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use crate::sync::lock;
+use rodio::Source;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// number of samples a channel may fall behind the fastest channel
+const MAX_BACKLOG: usize = 4096;
+
+/// Split a multi-channel source into one single-channel source per channel
+///
+/// All channels share the inner source. Samples are buffered until each channel consumes
+/// them, so the channels should be played back at the same rate. A channel that falls behind
+/// by more than 4096 samples, e.g. because it is paused, skips its oldest samples. Dropped
+/// channels no longer buffer samples.
+pub fn deinterleave<I>(input: I) -> Vec<Channel<I>>
+where
+    I: Source<Item = f32>,
+{
+    let channels = input.channels() as usize;
+    let shared = Arc::new(Mutex::new(Shared {
+        sample_rate: input.sample_rate(),
+        total_duration: input.total_duration(),
+        input,
+        buffers: (0..channels)
+            .map(|_| Some(VecDeque::with_capacity(MAX_BACKLOG)))
+            .collect(),
+        frame: vec![0.0; channels],
+    }));
+
+    (0..channels)
+        .map(|index| Channel {
+            shared: shared.clone(),
+            index,
+        })
+        .collect()
+}
+
+struct Shared<I> {
+    input: I,
+    // buffered samples of each channel, `None` once the channel is dropped
+    buffers: Vec<Option<VecDeque<f32>>>,
+    // reused storage for reading a frame
+    frame: Vec<f32>,
+    sample_rate: u32,
+    total_duration: Option<Duration>,
+}
+
+impl<I> Shared<I>
+where
+    I: Source<Item = f32>,
+{
+    /// Read the next frame into the buffers; returns false if the input ended
+    ///
+    /// A frame that the input ends in the middle of is discarded.
+    fn read_frame(&mut self) -> bool {
+        for x in self.frame.iter_mut() {
+            match self.input.next() {
+                Some(sample) => *x = sample,
+                None => return false,
+            }
+        }
+        for (buffer, &x) in self.buffers.iter_mut().zip(&self.frame) {
+            if let Some(buffer) = buffer {
+                if buffer.len() == MAX_BACKLOG {
+                    buffer.pop_front();
+                }
+                buffer.push_back(x);
+            }
+        }
+        true
+    }
+}
+
+/// A single channel of a deinterleaved source
+pub struct Channel<I> {
+    shared: Arc<Mutex<Shared<I>>>,
+    index: usize,
+}
+
+impl<I> Iterator for Channel<I>
+where
+    I: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut shared = lock(&self.shared);
+
+        let is_empty = shared.buffers[self.index]
+            .as_ref()
+            .is_none_or(VecDeque::is_empty);
+        if is_empty && !shared.read_frame() {
+            return None;
+        }
+
+        shared.buffers[self.index].as_mut()?.pop_front()
+    }
+}
+
+impl<I> Drop for Channel<I> {
+    fn drop(&mut self) {
+        lock(&self.shared).buffers[self.index] = None;
+    }
+}
+
+impl<I> Source for Channel<I>
+where
+    I: Source<Item = f32>,
+{
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        lock(&self.shared).sample_rate
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        lock(&self.shared).total_duration
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn channels_take_turns_on_whole_frames() {
+        // the last frame is incomplete
+        let input = SamplesBuffer::new(2, 1000, vec![1.0, -1.0, 2.0, -2.0, 3.0]);
+        let mut channels = deinterleave(input);
+        let right = channels.pop().unwrap();
+        let left = channels.pop().unwrap();
+
+        assert_eq!(right.collect::<Vec<_>>(), [-1.0, -2.0]);
+        assert_eq!(left.collect::<Vec<_>>(), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn lagging_channels_buffer_a_bounded_backlog() {
+        let samples: Vec<f32> = (0..20000).map(|i| i as f32).collect();
+        let mut channels = deinterleave(SamplesBuffer::new(2, 1000, samples));
+        let mut right = channels.pop().unwrap();
+        let mut left = channels.pop().unwrap();
+
+        for _ in 0..5000 {
+            left.next();
+        }
+        {
+            let shared = lock(&right.shared);
+            assert_eq!(shared.buffers[1].as_ref().unwrap().len(), MAX_BACKLOG);
+        }
+        // the right channel skipped its oldest samples
+        assert_eq!(right.next(), Some(2.0 * (5000 - MAX_BACKLOG) as f32 + 1.0));
+
+        drop(right);
+        for _ in 0..5000 {
+            left.next();
+        }
+        assert!(lock(&left.shared).buffers[1].is_none());
+    }
+}
@@ -0,0 +1,72 @@
+use rodio::Source;
+use std::time::Duration;
+
+/// Single-channel mix of a multi-channel source
+///
+/// Each output sample is the average of all channels of an input frame. The number of input
+/// channels is read anew at the start of each frame, so it may change between rodio frames.
+pub struct Downmix<I> {
+    input: I,
+}
+
+impl<I: Source<Item = f32>> Downmix<I> {
+    pub fn new(input: I) -> Self {
+        Downmix { input }
+    }
+}
+
+impl<I: Source<Item = f32>> Iterator for Downmix<I> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let channels = self.input.channels().max(1);
+        let mut sum = 0.0;
+        for _ in 0..channels {
+            sum += self.input.next()?;
+        }
+        Some(sum / channels as f32)
+    }
+}
+
+impl<I: Source<Item = f32>> Source for Downmix<I> {
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        let channels = self.input.channels().max(1) as usize;
+        self.input.current_frame_len().map(|n| n / channels)
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn channels_are_averaged() {
+        let input = SamplesBuffer::new(2, 1000, vec![1.0, 0.0, 0.5, 0.5, -1.0, 0.0]);
+        let output = Downmix::new(input);
+        assert_eq!(output.channels(), 1);
+        assert_eq!(output.collect::<Vec<_>>(), vec![0.5, 0.5, -0.5]);
+    }
+
+    #[test]
+    fn incomplete_frames_are_dropped() {
+        let input = SamplesBuffer::new(3, 1000, vec![3.0, 0.0, 0.0, 1.0]);
+        assert_eq!(Downmix::new(input).collect::<Vec<_>>(), vec![1.0]);
+    }
+}
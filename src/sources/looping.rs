@@ -0,0 +1,160 @@
+use rodio::Source;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Errors that can occur while creating a `Loop`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LoopError {
+    /// The loop end is not after the loop start
+    EmptyLoop {
+        /// Sample index where the loop starts
+        loop_start: usize,
+        /// Sample index where the loop ends
+        loop_end: usize,
+    },
+
+    /// The input has more than one channel
+    NotMono(u16),
+}
+
+impl fmt::Display for LoopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoopError::EmptyLoop {
+                loop_start,
+                loop_end,
+            } => write!(
+                f,
+                "loop from sample {} to {} is empty",
+                loop_start, loop_end
+            ),
+            LoopError::NotMono(n) => write!(f, "only single-channel sources can loop, not {}", n),
+        }
+    }
+}
+
+impl Error for LoopError {}
+
+/// Source that repeats a section of another source
+///
+/// The input plays from the beginning up to `loop_end`, then jumps back to `loop_start` and
+/// repeats the section between both points forever without a gap. Loop points are sample
+/// indices of the input. A `loop_end` beyond the end of the input is clamped to its length. If
+/// `loop_start` is beyond the end of the input, the source simply ends with its input.
+///
+/// The input is read up to `loop_end` and buffered in memory when the loop is created, so that
+/// playback does not allocate.
+pub struct Loop {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    loop_start: usize,
+    position: usize,
+}
+
+impl Loop {
+    /// Buffer the input up to `loop_end`
+    ///
+    /// Returns an error if `loop_start` is not before `loop_end`, or if the input has more than
+    /// one channel.
+    pub fn new<I>(input: I, loop_start: usize, loop_end: usize) -> Result<Self, LoopError>
+    where
+        I: Source<Item = f32>,
+    {
+        if loop_start >= loop_end {
+            return Err(LoopError::EmptyLoop {
+                loop_start,
+                loop_end,
+            });
+        }
+        if input.channels() != 1 {
+            return Err(LoopError::NotMono(input.channels()));
+        }
+        Ok(Loop {
+            sample_rate: input.sample_rate(),
+            samples: input.take(loop_end).collect(),
+            loop_start,
+            position: 0,
+        })
+    }
+}
+
+impl Iterator for Loop {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.samples.len() {
+            if self.loop_start >= self.samples.len() {
+                return None;
+            }
+            self.position = self.loop_start;
+        }
+        let x = self.samples[self.position];
+        self.position += 1;
+        Some(x)
+    }
+}
+
+impl Source for Loop {
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn ramp(n: usize) -> SamplesBuffer<f32> {
+        SamplesBuffer::new(1, 1000, (0..n).map(|i| i as f32).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn playback_wraps_at_loop_end() {
+        let output: Vec<f32> = Loop::new(ramp(10), 3, 7).unwrap().take(13).collect();
+        assert_eq!(
+            output,
+            vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 3.0, 4.0, 5.0, 6.0, 3.0, 4.0]
+        );
+    }
+
+    #[test]
+    fn loop_end_is_clamped_to_source_length() {
+        let output: Vec<f32> = Loop::new(ramp(5), 3, 100).unwrap().take(9).collect();
+        assert_eq!(output, vec![0.0, 1.0, 2.0, 3.0, 4.0, 3.0, 4.0, 3.0, 4.0]);
+
+        let output: Vec<f32> = Loop::new(ramp(5), 8, 100).unwrap().take(9).collect();
+        assert_eq!(output, vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn invalid_loops_are_rejected() {
+        assert_eq!(
+            Loop::new(ramp(10), 7, 7).err(),
+            Some(LoopError::EmptyLoop {
+                loop_start: 7,
+                loop_end: 7
+            })
+        );
+        let stereo = SamplesBuffer::new(2, 1000, vec![0.0f32; 10]);
+        assert_eq!(Loop::new(stereo, 0, 4).err(), Some(LoopError::NotMono(2)));
+    }
+}
@@ -1,9 +1,19 @@
 //! Useful implementations of `rodio::Source`
 
 mod constant;
+mod deinterleave;
+mod downmix;
+mod looping;
 mod noise;
+mod prepared;
 mod ramp;
+mod sweep;
 
 pub use self::constant::Constant;
-pub use self::noise::Noise;
+pub use self::deinterleave::{deinterleave, Channel};
+pub use self::downmix::Downmix;
+pub use self::looping::{Loop, LoopError};
+pub use self::noise::{Noise, PinkNoise};
+pub use self::prepared::{PreparedSound, PreparedSource};
 pub use self::ramp::Ramp;
+pub use self::sweep::SineSweep;
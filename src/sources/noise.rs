@@ -16,6 +16,14 @@ impl Noise {
             rng: SmallRng::from_entropy(),
         }
     }
+
+    /// Create white noise that produces the same sample sequence for the same seed.
+    pub fn with_seed(seed: u64, sample_rate: u32) -> Self {
+        Noise {
+            sample_rate,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
 }
 
 impl Iterator for Noise {
@@ -47,3 +55,92 @@ impl Source for Noise {
         None
     }
 }
+
+/// Infinite pink noise
+///
+/// White noise filtered to a -3dB/octave slope (Paul Kellet's refined method).
+pub struct PinkNoise {
+    white: Noise,
+    b: [f32; 7],
+}
+
+impl PinkNoise {
+    pub fn new(sample_rate: u32) -> Self {
+        PinkNoise {
+            white: Noise::new(sample_rate),
+            b: [0.0; 7],
+        }
+    }
+
+    /// Create pink noise that produces the same sample sequence for the same seed.
+    pub fn with_seed(seed: u64, sample_rate: u32) -> Self {
+        PinkNoise {
+            white: Noise::with_seed(seed, sample_rate),
+            b: [0.0; 7],
+        }
+    }
+}
+
+impl Iterator for PinkNoise {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let white = self.white.next()?;
+        let b = &mut self.b;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.969 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // scale the filter's gain back to roughly unit variance
+        Some(pink / 3.0)
+    }
+}
+
+impl Source for PinkNoise {
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.white.sample_rate
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_produces_same_samples() {
+        let a: Vec<f32> = Noise::with_seed(42, 48000).take(1000).collect();
+        let b: Vec<f32> = Noise::with_seed(42, 48000).take(1000).collect();
+        assert_eq!(a, b);
+
+        let a: Vec<f32> = PinkNoise::with_seed(42, 48000).take(1000).collect();
+        let b: Vec<f32> = PinkNoise::with_seed(42, 48000).take(1000).collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn different_seeds_produce_different_samples() {
+        let a: Vec<f32> = Noise::with_seed(1, 48000).take(100).collect();
+        let b: Vec<f32> = Noise::with_seed(2, 48000).take(100).collect();
+        assert_ne!(a, b);
+    }
+}
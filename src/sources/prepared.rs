@@ -0,0 +1,107 @@
+use rodio::Source;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Decoded sound that can be played many times
+///
+/// The input is read to its end once, when the sound is prepared, so it must be finite. Every
+/// `PreparedSound::source` is an independent playback of the shared samples; creating one does
+/// not copy or allocate sample memory. Cloning a `PreparedSound` is equally cheap.
+#[derive(Clone)]
+pub struct PreparedSound {
+    samples: Arc<[f32]>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl PreparedSound {
+    pub fn new<I: Source<Item = f32>>(input: I) -> Self {
+        let channels = input.channels();
+        let sample_rate = input.sample_rate();
+        PreparedSound {
+            samples: input.collect::<Vec<_>>().into(),
+            channels,
+            sample_rate,
+        }
+    }
+
+    /// New playback of the sound from its beginning
+    pub fn source(&self) -> PreparedSource {
+        PreparedSource {
+            sound: self.clone(),
+            position: 0,
+        }
+    }
+
+    /// RMS amplitude over all samples of the sound
+    ///
+    /// Useful to normalize the level of sounds, e.g. by playing them with a gain of
+    /// `target / sound.rms()`.
+    pub fn rms(&self) -> f32 {
+        let sum_squares: f32 = self.samples.iter().map(|x| x * x).sum();
+        (sum_squares / self.samples.len().max(1) as f32).sqrt()
+    }
+
+    /// Length of the sound
+    pub fn duration(&self) -> Duration {
+        let frames = self.samples.len() / self.channels.max(1) as usize;
+        Duration::from_secs_f64(frames as f64 / self.sample_rate as f64)
+    }
+}
+
+/// Playback of a `PreparedSound`
+pub struct PreparedSource {
+    sound: PreparedSound,
+    position: usize,
+}
+
+impl Iterator for PreparedSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let x = self.sound.samples.get(self.position).copied();
+        self.position += 1;
+        x
+    }
+}
+
+impl Source for PreparedSource {
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.sound.samples.len().saturating_sub(self.position))
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        self.sound.channels
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.sound.sample_rate
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.sound.duration())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn playbacks_share_the_samples() {
+        let sound = PreparedSound::new(SamplesBuffer::new(1, 1000, vec![0.0, 1.0, 2.0, 3.0, 4.0]));
+        let mut first = sound.source();
+        first.next();
+        let second = sound.source();
+
+        assert_eq!(first.collect::<Vec<_>>(), vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(second.collect::<Vec<_>>(), vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(sound.duration(), Duration::from_millis(5));
+        assert_eq!(sound.rms(), 6f32.sqrt());
+    }
+}
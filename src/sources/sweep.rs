@@ -0,0 +1,126 @@
+use rodio::Source;
+use std::time::Duration;
+
+/// Exponential sine sweep (logarithmic chirp)
+///
+/// The frequency rises (or falls) from `start_hz` to `end_hz` at a constant number of octaves
+/// per second, which is the usual excitation signal for impulse-response measurement.
+pub struct SineSweep {
+    sample_rate: u32,
+    start_hz: f64,
+    /// Natural logarithm of the frequency ratio `end_hz / start_hz`
+    log_ratio: f64,
+    duration: Duration,
+    length: u64,
+    position: u64,
+}
+
+impl SineSweep {
+    pub fn new(start_hz: f32, end_hz: f32, duration: Duration, sample_rate: u32) -> Self {
+        assert!(
+            start_hz > 0.0 && end_hz > 0.0,
+            "sweep frequencies must be positive"
+        );
+        SineSweep {
+            sample_rate,
+            start_hz: start_hz as f64,
+            log_ratio: (end_hz as f64 / start_hz as f64).ln(),
+            duration,
+            length: (duration.as_secs_f64() * sample_rate as f64).round() as u64,
+            position: 0,
+        }
+    }
+}
+
+impl Iterator for SineSweep {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.length {
+            return None;
+        }
+
+        let t = self.position as f64 / self.sample_rate as f64;
+        let big_t = self.duration.as_secs_f64();
+        let phase = if self.log_ratio.abs() < 1e-12 {
+            2.0 * std::f64::consts::PI * self.start_hz * t
+        } else {
+            2.0 * std::f64::consts::PI * self.start_hz * big_t / self.log_ratio
+                * ((t / big_t * self.log_ratio).exp() - 1.0)
+        };
+
+        self.position += 1;
+        Some(phase.sin() as f32)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.length - self.position) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl Source for SineSweep {
+    #[inline(always)]
+    fn current_frame_len(&self) -> Option<usize> {
+        Some((self.length - self.position) as usize)
+    }
+
+    #[inline(always)]
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    #[inline(always)]
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    #[inline(always)]
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Estimate the frequency from the mean spacing of zero crossings in `samples`.
+    fn zero_crossing_frequency(samples: &[f32], sample_rate: u32) -> f32 {
+        let crossings: Vec<usize> = samples
+            .windows(2)
+            .enumerate()
+            .filter(|(_, w)| (w[0] < 0.0) != (w[1] < 0.0))
+            .map(|(i, _)| i)
+            .collect();
+        let first = *crossings.first().unwrap();
+        let last = *crossings.last().unwrap();
+        let half_periods = (crossings.len() - 1) as f32;
+        sample_rate as f32 * half_periods / (last - first) as f32 / 2.0
+    }
+
+    #[test]
+    fn sweep_covers_requested_frequency_range() {
+        let sr = 48000;
+        let sweep = SineSweep::new(200.0, 2000.0, Duration::from_secs(2), sr);
+        assert_eq!(sweep.total_duration(), Some(Duration::from_secs(2)));
+
+        let samples: Vec<f32> = sweep.collect();
+        assert_eq!(samples.len(), 2 * sr as usize);
+
+        let window = sr as usize / 50;
+        let start = zero_crossing_frequency(&samples[..window], sr);
+        let end = zero_crossing_frequency(&samples[samples.len() - window..], sr);
+
+        assert!(
+            (start - 200.0).abs() / 200.0 < 0.05,
+            "start frequency {}",
+            start
+        );
+        assert!(
+            (end - 2000.0).abs() / 2000.0 < 0.05,
+            "end frequency {}",
+            end
+        );
+    }
+}
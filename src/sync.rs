@@ -0,0 +1,45 @@
+//! Synchronization that survives panics in user code
+//!
+//! User-supplied code, such as attenuation functions and mixer callbacks, may panic while the
+//! crate holds one of its locks. The standard library then marks the lock as poisoned, and
+//! every later attempt to lock it would fail. The state behind the crate's locks stays
+//! consistent between individual updates, so locking recovers from poisoning instead, and the
+//! scene keeps playing.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Lock a mutex, ignoring whether a thread panicked while holding it
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Call user-supplied code, returning `None` if it panicked
+///
+/// The panic is reported by the panic hook as usual, but does not unwind into the caller, e.g.
+/// the audio thread.
+pub(crate) fn catch_panic<R>(f: impl FnOnce() -> R) -> Option<R> {
+    panic::catch_unwind(AssertUnwindSafe(f)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn poisoned_locks_can_be_locked() {
+        let mutex = Arc::new(Mutex::new(1));
+        let m = mutex.clone();
+        let _ = thread::spawn(move || {
+            let _guard = m.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+
+        assert!(mutex.is_poisoned());
+        *lock(&mutex) += 1;
+        assert_eq!(*lock(&mutex), 2);
+    }
+}
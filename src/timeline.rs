@@ -0,0 +1,136 @@
+//! Scheduled sources with keyframed positions for offline rendering
+
+use rodio::Source;
+use std::time::Duration;
+
+/// A source that starts at a given time and moves along keyframed positions
+///
+/// Keyframe times are relative to the start of the event. Between keyframes the position is
+/// interpolated linearly; before the first and after the last keyframe the source stays at
+/// the first and last keyframe's position. Events without keyframes play at the listener's
+/// position, like sources played with `Ambisonic::play`.
+pub struct TimelineEvent {
+    pub(crate) start: Duration,
+    pub(crate) source: Box<dyn Source<Item = f32> + Send>,
+    pub(crate) keyframes: Keyframes,
+}
+
+impl TimelineEvent {
+    /// Create an event that plays `source` starting at `start`
+    pub fn new<I>(start: Duration, source: I) -> Self
+    where
+        I: Source<Item = f32> + Send + 'static,
+    {
+        TimelineEvent {
+            start,
+            source: Box::new(source),
+            keyframes: Keyframes(Vec::new()),
+        }
+    }
+
+    /// Place the source at position `pos` (relative to the listener) at `time` after the start
+    /// of the event
+    pub fn with_keyframe(mut self, time: Duration, pos: [f32; 3]) -> Self {
+        let keyframes = &mut self.keyframes.0;
+        let i = keyframes.partition_point(|&(t, _)| t <= time);
+        keyframes.insert(i, (time, pos));
+        self
+    }
+}
+
+/// Positions of an event over time, sorted by time
+pub(crate) struct Keyframes(Vec<(Duration, [f32; 3])>);
+
+impl Keyframes {
+    /// Whether the position changes over time
+    pub(crate) fn is_moving(&self) -> bool {
+        self.0.len() > 1
+    }
+
+    /// Position at time `t` after the start of the event, `None` if there are no keyframes
+    pub(crate) fn position_at(&self, t: Duration) -> Option<[f32; 3]> {
+        let i = self.0.partition_point(|&(time, _)| time <= t);
+        match (i.checked_sub(1).map(|i| self.0[i]), self.0.get(i)) {
+            (Some((t0, p0)), Some(&(t1, p1))) => {
+                let a = (t - t0).as_secs_f32() / (t1 - t0).as_secs_f32();
+                Some([
+                    p0[0] + a * (p1[0] - p0[0]),
+                    p0[1] + a * (p1[1] - p0[1]),
+                    p0[2] + a * (p1[2] - p0[2]),
+                ])
+            }
+            (Some((_, p)), None) | (None, Some(&(_, p))) => Some(p),
+            (None, None) => None,
+        }
+    }
+
+    /// Velocity (in m/s) at time `t` after the start of the event
+    ///
+    /// The source is at rest before the first and after the last keyframe.
+    pub(crate) fn velocity_at(&self, t: Duration) -> [f32; 3] {
+        let i = self.0.partition_point(|&(time, _)| time <= t);
+        match (i.checked_sub(1).map(|i| self.0[i]), self.0.get(i)) {
+            (Some((t0, p0)), Some(&(t1, p1))) => {
+                let dt = (t1 - t0).as_secs_f32();
+                [
+                    (p1[0] - p0[0]) / dt,
+                    (p1[1] - p0[1]) / dt,
+                    (p1[2] - p0[2]) / dt,
+                ]
+            }
+            _ => [0.0, 0.0, 0.0],
+        }
+    }
+}
+
+/// A list of events to render offline with `Ambisonic::render_timeline`
+///
+/// Events may be added in any order and may overlap.
+#[derive(Default)]
+pub struct Timeline {
+    pub(crate) events: Vec<TimelineEvent>,
+}
+
+impl Timeline {
+    /// Create an empty timeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an event to the timeline
+    pub fn with_event(mut self, event: TimelineEvent) -> Self {
+        self.events.push(event);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::Constant;
+
+    #[test]
+    fn keyframes_are_interpolated_linearly() {
+        let event = TimelineEvent::new(Duration::from_secs(1), Constant::new(1.0, 48000))
+            .with_keyframe(Duration::from_secs(2), [2.0, 0.0, 0.0])
+            .with_keyframe(Duration::from_secs(0), [0.0, 0.0, 0.0]);
+        let keyframes = &event.keyframes;
+
+        assert_eq!(
+            keyframes.position_at(Duration::from_millis(500)),
+            Some([0.5, 0.0, 0.0])
+        );
+        assert_eq!(
+            keyframes.position_at(Duration::from_secs(3)),
+            Some([2.0, 0.0, 0.0])
+        );
+        assert_eq!(
+            keyframes.velocity_at(Duration::from_secs(1)),
+            [1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            keyframes.velocity_at(Duration::from_secs(3)),
+            [0.0, 0.0, 0.0]
+        );
+    }
+}